@@ -1,3 +1,21 @@
 pub use ckb_crypto::secp::*;
 pub use ckb_sdk::traits::{SecpCkbRawKeySigner, Signer};
 pub use ckb_sdk::unlock::{ScriptSignError, ScriptSigner, SecpSighashScriptSigner};
+pub use ckb_sdk::{ScriptGroup, ScriptGroupType};
+
+use ckb_hash::blake2b_256;
+use ckb_types::bytes::Bytes;
+
+// Script signer for secp256k1 blake160 sighash-all locks owned by any of the given keys
+pub fn sighash_signer(keys: Vec<secp256k1::SecretKey>) -> SecpSighashScriptSigner {
+    let signer = SecpCkbRawKeySigner::new_with_secret_keys(keys);
+    SecpSighashScriptSigner::new(Box::new(signer))
+}
+
+// Lock args of a secp256k1 blake160 sighash-all lock: blake160 of the compressed public key
+pub fn sighash_lock_args(key: &secp256k1::SecretKey) -> Bytes {
+    let secp = secp256k1::Secp256k1::signing_only();
+    let pubkey = secp256k1::PublicKey::from_secret_key(&secp, key);
+    let hash = blake2b_256(&pubkey.serialize()[..]);
+    Bytes::copy_from_slice(&hash[0..20])
+}
@@ -0,0 +1,232 @@
+use super::MockChainTxProvider;
+use crate::contract::generator::{ProviderError, ProviderResult};
+use ckb_types::{core::BlockNumber, packed, prelude::*, H256};
+use std::fmt;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Where a transaction is on its way into the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxState {
+    /// Neither the pool nor the chain know the transaction.
+    Unknown,
+    Pending,
+    Proposed,
+    Committed {
+        block_hash: H256,
+        block_number: BlockNumber,
+    },
+    Rejected {
+        reason: String,
+    },
+}
+
+impl fmt::Display for TxState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxState::Unknown => write!(f, "unknown"),
+            TxState::Pending => write!(f, "pending"),
+            TxState::Proposed => write!(f, "proposed"),
+            TxState::Committed { block_number, .. } => {
+                write!(f, "committed in block {}", block_number)
+            }
+            TxState::Rejected { reason } => write!(f, "rejected: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Confirmation {
+    pub block_hash: H256,
+    pub block_number: BlockNumber,
+    /// Blocks on top of the committing block, counting the committing block itself.
+    pub confirmations: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfirmationError {
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+    #[error("Transaction rejected: {reason}")]
+    Rejected { reason: String },
+    #[error("Transaction disappeared after it was {last}")]
+    Dropped { last: TxState },
+    #[error("Transaction is unknown and cannot appear anymore")]
+    NotFound,
+    /// The timeout passed, or a mock chain produced more blocks than the transaction needs.
+    #[error("Timed out waiting for confirmation, transaction is {state}")]
+    Timeout { state: TxState },
+}
+
+pub type ConfirmationResult<T> = std::result::Result<T, ConfirmationError>;
+
+/// Source of transaction states for a [`ConfirmationTracker`].
+pub trait TxStatusProvider {
+    fn tx_state(&self, hash: &H256) -> ProviderResult<TxState>;
+
+    fn tip_block_number(&self) -> ProviderResult<BlockNumber>;
+
+    /// Waits before the state is polled again. A mock chain produces a block instead.
+    fn wait(&self, interval: Duration) -> ProviderResult<()> {
+        std::thread::sleep(interval);
+        Ok(())
+    }
+
+    /// Whether a transaction that is unknown now can still show up later. A node may not
+    /// have received it yet, a mock chain sees every submission.
+    fn may_appear_later(&self) -> bool {
+        true
+    }
+
+    /// Most waits a transaction may take to get `confirmations` confirmations, for providers
+    /// where every wait produces a block. `None` leaves it to the timeout.
+    fn max_waits(&self, _confirmations: u64) -> Option<u64> {
+        None
+    }
+
+    /// Waits with the default tracker settings until the transaction is committed.
+    fn wait_for_commit(&self, hash: &H256) -> ConfirmationResult<Confirmation>
+    where
+        Self: Sized,
+    {
+        ConfirmationTracker::new(self).wait_for_commit(hash)
+    }
+}
+
+/// Polls a [`TxStatusProvider`] until a transaction is committed with the required number
+/// of confirmations.
+///
+/// Tracking fails when the transaction is rejected, when it disappears from the pool or the
+/// chain after it has been seen, or when the timeout or [`TxStatusProvider::max_waits`]
+/// passes. A transaction that was never seen is waited for until then, unless the provider
+/// knows it cannot appear anymore.
+pub struct ConfirmationTracker<'a, P: ?Sized> {
+    provider: &'a P,
+    confirmations: u64,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl<'a, P: TxStatusProvider + ?Sized> ConfirmationTracker<'a, P> {
+    pub fn new(provider: &'a P) -> Self {
+        Self {
+            provider,
+            confirmations: 1,
+            timeout: DEFAULT_CONFIRMATION_TIMEOUT,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Blocks required on top of the committing block, counting the committing block itself.
+    pub fn confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn wait_for_commit(&self, hash: &H256) -> ConfirmationResult<Confirmation> {
+        self.track(hash, |_| {})
+    }
+
+    /// Like [`ConfirmationTracker::wait_for_commit`], calling `on_change` with every state
+    /// the transaction moves to, starting with the first one observed.
+    pub fn track<F: FnMut(&TxState)>(
+        &self,
+        hash: &H256,
+        mut on_change: F,
+    ) -> ConfirmationResult<Confirmation> {
+        let deadline = Instant::now() + self.timeout;
+        let max_waits = self.provider.max_waits(self.confirmations);
+        let mut waits = 0;
+        let mut last: Option<TxState> = None;
+        loop {
+            let state = self.provider.tx_state(hash)?;
+            if last.as_ref() != Some(&state) {
+                on_change(&state);
+            }
+            match (&state, last) {
+                (TxState::Rejected { reason }, _) => {
+                    return Err(ConfirmationError::Rejected {
+                        reason: reason.clone(),
+                    })
+                }
+                (TxState::Unknown, Some(last)) if last != TxState::Unknown => {
+                    return Err(ConfirmationError::Dropped { last })
+                }
+                (TxState::Unknown, _) if !self.provider.may_appear_later() => {
+                    return Err(ConfirmationError::NotFound)
+                }
+                (
+                    TxState::Committed {
+                        block_hash,
+                        block_number,
+                    },
+                    _,
+                ) => {
+                    let tip = self.provider.tip_block_number()?;
+                    let confirmations = (tip + 1).saturating_sub(*block_number);
+                    if confirmations >= self.confirmations {
+                        return Ok(Confirmation {
+                            block_hash: block_hash.clone(),
+                            block_number: *block_number,
+                            confirmations,
+                        });
+                    }
+                }
+                _ => {}
+            }
+            if Instant::now() >= deadline || max_waits.map_or(false, |max| waits >= max) {
+                return Err(ConfirmationError::Timeout { state });
+            }
+            last = Some(state);
+            self.provider.wait(self.poll_interval)?;
+            waits += 1;
+        }
+    }
+}
+
+// Transactions are only pending or committed on a mock chain, and waiting commits a block
+impl TxStatusProvider for MockChainTxProvider {
+    fn tx_state(&self, hash: &H256) -> ProviderResult<TxState> {
+        let chain = self.chain.borrow();
+        let hash: packed::Byte32 = hash.pack();
+        Ok(match chain.get_transaction(&hash) {
+            None => TxState::Unknown,
+            Some((_, None)) => TxState::Pending,
+            Some((_, Some(block_hash))) => TxState::Committed {
+                block_number: chain.headers[&block_hash].number(),
+                block_hash: block_hash.unpack(),
+            },
+        })
+    }
+
+    fn tip_block_number(&self) -> ProviderResult<BlockNumber> {
+        Ok(self.chain.borrow().tip_header().number())
+    }
+
+    fn wait(&self, _interval: Duration) -> ProviderResult<()> {
+        self.chain.borrow_mut().commit_block();
+        Ok(())
+    }
+
+    fn may_appear_later(&self) -> bool {
+        false
+    }
+
+    // A pending transaction is committed in the next block, one more block is slack
+    fn max_waits(&self, confirmations: u64) -> Option<u64> {
+        Some(confirmations + 1)
+    }
+}
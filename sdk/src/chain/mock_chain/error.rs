@@ -0,0 +1,95 @@
+use ckb_error::{Error as CKBError, InternalErrorKind};
+use ckb_script::ScriptGroupType;
+use ckb_types::{core::error::OutPointError, packed::Byte32, packed::OutPoint};
+use ckb_verification::TransactionError;
+use std::fmt;
+use thiserror::Error;
+
+use super::SnapshotId;
+
+/// The cell whose script group failed verification: the first input of the group,
+/// or the first output for type scripts that only appear in outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptCell {
+    Input(usize),
+    Output(usize),
+}
+
+impl fmt::Display for ScriptCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptCell::Input(idx) => write!(f, "input {}", idx),
+            ScriptCell::Output(idx) => write!(f, "output {}", idx),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MockChainError {
+    #[error("Unknown input {0}")]
+    UnknownInput(OutPoint),
+    #[error("Input {0} has already been spent")]
+    DeadInput(OutPoint),
+    #[error("Unknown cell dep {0}")]
+    UnknownCellDep(OutPoint),
+    #[error("Cell dep {0} has already been spent")]
+    DeadCellDep(OutPoint),
+    #[error("Input {index} ({out_point}) has an invalid since value")]
+    InvalidSince { index: usize, out_point: OutPoint },
+    #[error("Input {index} ({out_point}) is not mature yet")]
+    ImmatureInput { index: usize, out_point: OutPoint },
+    #[error(
+        "{group_type} script {script_hash} of {cell} failed (exit code {exit_code:?}): {error}"
+    )]
+    Script {
+        error: CKBError,
+        group_type: ScriptGroupType,
+        cell: ScriptCell,
+        script_hash: Byte32,
+        /// Exit code returned by the script, if it ran to completion.
+        exit_code: Option<i8>,
+    },
+    #[error("Unknown snapshot {0}")]
+    UnknownSnapshot(SnapshotId),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    FixtureFormat(#[from] serde_json::Error),
+    #[error("Invalid fixture: {0}")]
+    InvalidFixture(String),
+    #[error(transparent)]
+    Verification(#[from] CKBError),
+}
+
+impl MockChainError {
+    /// Exit code of the failing script, if this is a script failure.
+    pub fn exit_code(&self) -> Option<i8> {
+        match self {
+            MockChainError::Script { exit_code, .. } => *exit_code,
+            _ => None,
+        }
+    }
+}
+
+pub type MockChainResult<T> = std::result::Result<T, MockChainError>;
+
+impl From<MockChainError> for CKBError {
+    fn from(e: MockChainError) -> Self {
+        match e {
+            MockChainError::UnknownInput(outp) | MockChainError::UnknownCellDep(outp) => {
+                OutPointError::Unknown(outp).into()
+            }
+            MockChainError::DeadInput(outp) | MockChainError::DeadCellDep(outp) => {
+                OutPointError::Dead(outp).into()
+            }
+            MockChainError::InvalidSince { index, .. } => {
+                TransactionError::InvalidSince { index }.into()
+            }
+            MockChainError::ImmatureInput { index, .. } => {
+                TransactionError::Immature { index }.into()
+            }
+            MockChainError::Script { error, .. } | MockChainError::Verification(error) => error,
+            e => InternalErrorKind::System.other(e.to_string()).into(),
+        }
+    }
+}
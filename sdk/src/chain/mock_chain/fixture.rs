@@ -0,0 +1,273 @@
+use super::*;
+use ckb_jsonrpc_types::{
+    CellOutput as JsonCellOutput, HeaderView as JsonHeaderView, JsonBytes,
+    OutPoint as JsonOutPoint, Transaction as JsonTx, Uint32, Uint64,
+};
+use ckb_types::{packed, H256};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+// On-disk representation of a MockChain. Entries are sorted so that saving the same chain
+// always produces the same file.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockChainFixture {
+    pub tip: H256,
+    pub cells: Vec<FixtureCell>,
+    pub headers: Vec<JsonHeaderView>,
+    pub epochs: Vec<FixtureEpoch>,
+    pub blocks: Vec<FixtureBlock>,
+    pub pending_txs: Vec<JsonTx>,
+    pub cells_by_data_hash: Vec<FixtureDataIndex>,
+    pub all_cells_by_data_hash: Vec<FixtureIndex>,
+    pub cells_by_lock_hash: Vec<FixtureIndex>,
+    pub cells_by_type_hash: Vec<FixtureIndex>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureCell {
+    pub out_point: JsonOutPoint,
+    pub output: JsonCellOutput,
+    pub data: JsonBytes,
+    pub dead: bool,
+    pub tx_info: Option<FixtureTxInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureTxInfo {
+    pub block_hash: H256,
+    pub block_number: Uint64,
+    /// Full value of the block's EpochNumberWithFraction
+    pub block_epoch: Uint64,
+    pub index: Uint64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureEpoch {
+    pub block_hash: H256,
+    pub number: Uint64,
+    pub start_number: Uint64,
+    pub length: Uint64,
+    pub compact_target: Uint32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureBlock {
+    pub number: Uint64,
+    pub hash: H256,
+    pub transactions: Vec<JsonTx>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureDataIndex {
+    pub data_hash: H256,
+    pub out_point: JsonOutPoint,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureIndex {
+    pub hash: H256,
+    pub out_points: Vec<JsonOutPoint>,
+}
+
+fn sorted_index(index: &im::HashMap<Byte32, Vec<OutPoint>>) -> Vec<FixtureIndex> {
+    let mut entries = index
+        .iter()
+        .map(|(hash, out_points)| FixtureIndex {
+            hash: hash.unpack(),
+            out_points: out_points.iter().cloned().map(Into::into).collect(),
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+    entries
+}
+
+fn unsorted_index(entries: Vec<FixtureIndex>) -> im::HashMap<Byte32, Vec<OutPoint>> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            (
+                entry.hash.pack(),
+                entry.out_points.into_iter().map(Into::into).collect(),
+            )
+        })
+        .collect()
+}
+
+impl MockChain {
+    pub fn to_fixture(&self) -> MockChainFixture {
+        let mut cells = self
+            .cells
+            .iter()
+            .map(|(out_point, (output, data))| FixtureCell {
+                out_point: out_point.clone().into(),
+                output: output.clone().into(),
+                data: JsonBytes::from_bytes(data.clone()),
+                dead: self.dead_cells.contains(out_point),
+                tx_info: self.outpoint_txs.get(out_point).map(|info| FixtureTxInfo {
+                    block_hash: info.block_hash.unpack(),
+                    block_number: info.block_number.into(),
+                    block_epoch: info.block_epoch.full_value().into(),
+                    index: (info.index as u64).into(),
+                }),
+            })
+            .collect::<Vec<_>>();
+        cells.sort_by_key(|cell| {
+            packed::OutPoint::from(cell.out_point.clone())
+                .as_slice()
+                .to_vec()
+        });
+
+        let mut headers = self.headers.values().cloned().collect::<Vec<_>>();
+        headers.sort_by_key(|header| (header.number(), header.hash().unpack()));
+
+        let mut epochs = self
+            .epoches
+            .iter()
+            .map(|(block_hash, epoch)| FixtureEpoch {
+                block_hash: block_hash.unpack(),
+                number: epoch.number().into(),
+                start_number: epoch.start_number().into(),
+                length: epoch.length().into(),
+                compact_target: epoch.compact_target().into(),
+            })
+            .collect::<Vec<_>>();
+        epochs.sort_by(|a, b| a.block_hash.cmp(&b.block_hash));
+
+        let blocks = self
+            .block_hashes
+            .iter()
+            .map(|(number, hash)| FixtureBlock {
+                number: (*number).into(),
+                hash: hash.unpack(),
+                transactions: self
+                    .block_txs
+                    .get(hash)
+                    .map(|txs| txs.iter().map(|tx| tx.data().into()).collect())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let mut cells_by_data_hash = self
+            .cells_by_data_hash
+            .iter()
+            .map(|(data_hash, out_point)| FixtureDataIndex {
+                data_hash: data_hash.unpack(),
+                out_point: out_point.clone().into(),
+            })
+            .collect::<Vec<_>>();
+        cells_by_data_hash.sort_by(|a, b| a.data_hash.cmp(&b.data_hash));
+
+        MockChainFixture {
+            tip: self.tip.hash().unpack(),
+            cells,
+            headers: headers.into_iter().map(Into::into).collect(),
+            epochs,
+            blocks,
+            pending_txs: self.pending_txs.iter().map(|tx| tx.data().into()).collect(),
+            cells_by_data_hash,
+            all_cells_by_data_hash: sorted_index(&self.all_cells_by_data_hash),
+            cells_by_lock_hash: sorted_index(&self.cells_by_lock_hash),
+            cells_by_type_hash: sorted_index(&self.cells_by_type_hash),
+        }
+    }
+
+    pub fn from_fixture(fixture: MockChainFixture) -> MockChainResult<Self> {
+        let mut chain = MockChain {
+            cells: Default::default(),
+            outpoint_txs: Default::default(),
+            headers: Default::default(),
+            epoches: Default::default(),
+            cells_by_data_hash: Default::default(),
+            all_cells_by_data_hash: unsorted_index(fixture.all_cells_by_data_hash),
+            cells_by_lock_hash: unsorted_index(fixture.cells_by_lock_hash),
+            cells_by_type_hash: unsorted_index(fixture.cells_by_type_hash),
+            dead_cells: Default::default(),
+            block_hashes: Default::default(),
+            block_txs: Default::default(),
+            pending_txs: fixture
+                .pending_txs
+                .into_iter()
+                .map(|tx| packed::Transaction::from(tx).into_view())
+                .collect(),
+            debug: Default::default(),
+            tip: HeaderView::new_advanced_builder().build(),
+            messages: Default::default(),
+            snapshots: Default::default(),
+        };
+
+        for cell in fixture.cells {
+            let out_point = OutPoint::from(cell.out_point);
+            if cell.dead {
+                chain.dead_cells.insert(out_point.clone());
+            }
+            if let Some(info) = cell.tx_info {
+                chain.outpoint_txs.insert(
+                    out_point.clone(),
+                    TransactionInfo::new(
+                        info.block_number.into(),
+                        EpochNumberWithFraction::from_full_value(info.block_epoch.into()),
+                        info.block_hash.pack(),
+                        u64::from(info.index) as usize,
+                    ),
+                );
+            }
+            chain
+                .cells
+                .insert(out_point, (cell.output.into(), cell.data.into_bytes()));
+        }
+        for header in fixture.headers {
+            chain.insert_header(packed::Header::from(header.inner).into_view());
+        }
+        for epoch in fixture.epochs {
+            chain.epoches.insert(
+                epoch.block_hash.pack(),
+                EpochExt::new_builder()
+                    .number(epoch.number.into())
+                    .start_number(epoch.start_number.into())
+                    .length(epoch.length.into())
+                    .compact_target(epoch.compact_target.into())
+                    .build(),
+            );
+        }
+        for block in fixture.blocks {
+            let hash = block.hash.pack();
+            chain.block_hashes.insert(block.number.into(), hash.clone());
+            chain.block_txs.insert(
+                hash,
+                block
+                    .transactions
+                    .into_iter()
+                    .map(|tx| packed::Transaction::from(tx).into_view())
+                    .collect(),
+            );
+        }
+        for entry in fixture.cells_by_data_hash {
+            chain
+                .cells_by_data_hash
+                .insert(entry.data_hash.pack(), entry.out_point.into());
+        }
+
+        chain.tip = chain
+            .headers
+            .get(&fixture.tip.pack())
+            .cloned()
+            .ok_or_else(|| {
+                MockChainError::InvalidFixture(format!("tip header {:#x} is missing", fixture.tip))
+            })?;
+        Ok(chain)
+    }
+
+    /// Write the full chain state to `path` as JSON. Debug messages and snapshots are not saved.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> MockChainResult<()> {
+        let json = serde_json::to_string_pretty(&self.to_fixture())?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load a chain previously written with [`MockChain::save_to`].
+    pub fn load_from<P: AsRef<Path>>(path: P) -> MockChainResult<Self> {
+        let json = fs::read_to_string(path)?;
+        Self::from_fixture(serde_json::from_str(&json)?)
+    }
+}
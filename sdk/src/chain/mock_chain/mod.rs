@@ -1,30 +1,56 @@
 use crate::chain::*;
 use crate::contract::generator::{
-    CellQuery, CellQueryAttribute, QueryProvider, QueryStatement, TransactionProvider,
+    CellQuery, CellQueryAttribute, ProviderError, ProviderResult, QueryProvider, QueryStatement,
+    TransactionProvider,
 };
+use crate::rpc::{ScriptType, SearchKey};
 use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
 use ckb_error::Error as CKBError;
 use ckb_jsonrpc_types::TransactionView as JsonTransaction;
-use ckb_script::{TransactionScriptsVerifier, TxVerifyEnv};
+use ckb_script::{
+    ScriptError, ScriptGroup, ScriptGroupType, TransactionScriptsVerifier, TxVerifyEnv,
+};
 use ckb_traits::{CellDataProvider, HeaderProvider};
 use ckb_types::{
     bytes::Bytes,
     core::{
         cell::{CellMeta, CellMetaBuilder, ResolvedTransaction},
         hardfork::HardForkSwitch,
-        Capacity, Cycle, DepType, EpochExt, EpochNumberWithFraction, HeaderView, ScriptHashType,
-        TransactionInfo, TransactionView,
+        BlockNumber, BlockView, Capacity, Cycle, DepType, EpochExt, EpochNumberWithFraction,
+        HeaderView, ScriptHashType, TransactionBuilder, TransactionInfo, TransactionView,
     },
-    packed::{Byte32, CellDep, CellOutput, OutPoint, Script},
+    packed::{Byte32, CellDep, CellInput, CellOutput, OutPoint, Script},
 };
 use ckb_util::LinkedHashSet;
 use rand::{thread_rng, Rng};
 
 use ckb_always_success_script::ALWAYS_SUCCESS;
+use ckb_system_scripts::BUNDLED_CELL;
 use std::sync::{Arc, Mutex};
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashSet};
+
+mod error;
+mod fixture;
+mod rpc_server;
+pub use error::*;
+pub use fixture::*;
+pub use rpc_server::*;
+
 const MAX_CYCLES: u64 = 500_0000;
 
+/// Number of blocks in every epoch of the mock chain
+pub const EPOCH_LENGTH: u64 = 1000;
+/// Milliseconds between two consecutive mock blocks
+pub const BLOCK_INTERVAL: u64 = 8000;
+
+const SINCE_RELATIVE_FLAG: u64 = 1 << 63;
+const SINCE_METRIC_MASK: u64 = 0b0110_0000 << 56;
+const SINCE_REMAIN_MASK: u64 = 0b0001_1111 << 56;
+const SINCE_VALUE_MASK: u64 = 0x00ff_ffff_ffff_ffff;
+const SINCE_METRIC_BLOCK_NUMBER: u64 = 0;
+const SINCE_METRIC_EPOCH: u64 = 0b0010_0000 << 56;
+const SINCE_METRIC_TIMESTAMP: u64 = 0b0100_0000 << 56;
+
 pub fn random_hash() -> Byte32 {
     let mut rng = thread_rng();
     let mut buf = [0u8; 32];
@@ -38,16 +64,62 @@ pub fn random_out_point() -> OutPoint {
 
 pub type CellOutputWithData = (CellOutput, Bytes);
 
+// Chain state is kept in persistent maps, which share their structure between clones
 pub struct MockChain {
-    pub cells: HashMap<OutPoint, CellOutputWithData>,
-    pub outpoint_txs: HashMap<OutPoint, TransactionInfo>,
-    pub headers: HashMap<Byte32, HeaderView>,
-    pub epoches: HashMap<Byte32, EpochExt>,
-    pub cells_by_data_hash: HashMap<Byte32, OutPoint>,
-    pub cells_by_lock_hash: HashMap<Byte32, Vec<OutPoint>>,
-    pub cells_by_type_hash: HashMap<Byte32, Vec<OutPoint>>,
+    pub cells: im::HashMap<OutPoint, CellOutputWithData>,
+    pub outpoint_txs: im::HashMap<OutPoint, TransactionInfo>,
+    pub headers: im::HashMap<Byte32, HeaderView>,
+    pub epoches: im::HashMap<Byte32, EpochExt>,
+    // One live cell per data hash, used to find code cells for cell deps
+    pub cells_by_data_hash: im::HashMap<Byte32, OutPoint>,
+    // Every live cell per data hash, used by data hash queries
+    pub all_cells_by_data_hash: im::HashMap<Byte32, Vec<OutPoint>>,
+    pub cells_by_lock_hash: im::HashMap<Byte32, Vec<OutPoint>>,
+    pub cells_by_type_hash: im::HashMap<Byte32, Vec<OutPoint>>,
+    pub dead_cells: im::HashSet<OutPoint>,
+    pub block_hashes: im::OrdMap<BlockNumber, Byte32>,
+    pub block_txs: im::HashMap<Byte32, Vec<TransactionView>>,
+    pub pending_txs: Vec<TransactionView>,
     pub debug: bool,
+    tip: HeaderView,
     messages: Arc<Mutex<Vec<Message>>>,
+    snapshots: Vec<Arc<MockChain>>,
+}
+
+/// Identifies a state saved with [`MockChain::snapshot`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SnapshotId(usize);
+
+impl std::fmt::Display for SnapshotId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// Cloning forks the chain: the state maps are persistent so the copy is shallow, and each fork
+// only copies the parts of a map it later changes. Captured debug messages are copied so the
+// two chains don't share a log.
+impl Clone for MockChain {
+    fn clone(&self) -> Self {
+        Self {
+            cells: self.cells.clone(),
+            outpoint_txs: self.outpoint_txs.clone(),
+            headers: self.headers.clone(),
+            epoches: self.epoches.clone(),
+            cells_by_data_hash: self.cells_by_data_hash.clone(),
+            all_cells_by_data_hash: self.all_cells_by_data_hash.clone(),
+            cells_by_lock_hash: self.cells_by_lock_hash.clone(),
+            cells_by_type_hash: self.cells_by_type_hash.clone(),
+            dead_cells: self.dead_cells.clone(),
+            block_hashes: self.block_hashes.clone(),
+            block_txs: self.block_txs.clone(),
+            pending_txs: self.pending_txs.clone(),
+            debug: self.debug,
+            tip: self.tip.clone(),
+            messages: Arc::new(Mutex::new(self.captured_messages())),
+            snapshots: self.snapshots.clone(),
+        }
+    }
 }
 
 impl Default for MockChain {
@@ -58,18 +130,47 @@ impl Default for MockChain {
             headers: Default::default(),
             epoches: Default::default(),
             cells_by_data_hash: Default::default(),
+            all_cells_by_data_hash: Default::default(),
             cells_by_lock_hash: Default::default(),
             cells_by_type_hash: Default::default(),
+            dead_cells: Default::default(),
+            block_hashes: Default::default(),
+            block_txs: Default::default(),
+            pending_txs: Default::default(),
             debug: Default::default(),
+            tip: HeaderView::new_advanced_builder().build(),
             messages: Default::default(),
+            snapshots: Default::default(),
         };
 
+        chain.insert_block(chain.build_header(0, 0), vec![]);
         chain.deploy_cell_with_data(Bytes::from(ALWAYS_SUCCESS.to_vec()));
         chain
     }
 }
 
 impl MockChain {
+    /// Save the current state of the chain. It can be restored any number of times.
+    pub fn snapshot(&mut self) -> SnapshotId {
+        let mut state = self.clone();
+        state.snapshots.clear();
+        self.snapshots.push(Arc::new(state));
+        SnapshotId(self.snapshots.len() - 1)
+    }
+
+    /// Reset the chain to a saved state. Snapshots, including those taken after `id`, are kept.
+    pub fn restore(&mut self, id: SnapshotId) -> MockChainResult<()> {
+        let state = self
+            .snapshots
+            .get(id.0)
+            .cloned()
+            .ok_or(MockChainError::UnknownSnapshot(id))?;
+        let snapshots = std::mem::take(&mut self.snapshots);
+        *self = (*state).clone();
+        self.snapshots = snapshots;
+        Ok(())
+    }
+
     pub fn deploy_cell_with_data(&mut self, data: Bytes) -> OutPoint {
         let data_hash = CellOutput::calc_data_hash(&data);
         if let Some(out_point) = self.cells_by_data_hash.get(&data_hash) {
@@ -82,7 +183,12 @@ impl MockChain {
             .build();
 
         self.cells.insert(out_point.clone(), (cell, data));
+        self.all_cells_by_data_hash
+            .entry(data_hash.clone())
+            .or_default()
+            .push(out_point.clone());
         self.cells_by_data_hash.insert(data_hash, out_point.clone());
+        self.link_cell_with_block(out_point.clone(), self.tip.hash(), 0);
         out_point
     }
 
@@ -105,6 +211,21 @@ impl MockChain {
             .clone()
     }
 
+    // Deploy the secp256k1 blake160 sighash-all system script along with the secp256k1 data
+    // it loads at runtime. Returns the out points of the script code cell and of the data cell;
+    // transactions unlocking sighash-all cells need both as cell deps.
+    pub fn deploy_secp256k1_sighash_all(&mut self) -> (OutPoint, OutPoint) {
+        let code = BUNDLED_CELL
+            .get("specs/cells/secp256k1_blake160_sighash_all")
+            .expect("load secp256k1_blake160_sighash_all");
+        let data = BUNDLED_CELL
+            .get("specs/cells/secp256k1_data")
+            .expect("load secp256k1_data");
+        let code_out_point = self.deploy_cell_with_data(Bytes::from(code.to_vec()));
+        let data_out_point = self.deploy_cell_with_data(Bytes::from(data.to_vec()));
+        (code_out_point, data_out_point)
+    }
+
     pub fn deploy_random_cell_with_default_lock(
         &mut self,
         capacity: usize,
@@ -139,6 +260,207 @@ impl MockChain {
         );
     }
 
+    pub fn tip_header(&self) -> &HeaderView {
+        &self.tip
+    }
+
+    pub fn get_block_hash(&self, number: BlockNumber) -> Option<Byte32> {
+        self.block_hashes.get(&number).cloned()
+    }
+
+    pub fn get_block_txs(&self, hash: &Byte32) -> Option<Vec<TransactionView>> {
+        self.block_txs.get(hash).cloned()
+    }
+
+    // Look up a committed or pending transaction, along with the hash of the block committing it
+    pub fn get_transaction(&self, hash: &Byte32) -> Option<(TransactionView, Option<Byte32>)> {
+        self.block_txs
+            .iter()
+            .find_map(|(block_hash, txs)| {
+                txs.iter()
+                    .find(|tx| &tx.hash() == hash)
+                    .map(|tx| (tx.clone(), Some(block_hash.clone())))
+            })
+            .or_else(|| {
+                self.pending_txs
+                    .iter()
+                    .find(|tx| &tx.hash() == hash)
+                    .map(|tx| (tx.clone(), None))
+            })
+    }
+
+    // Assemble a block from its header and committed transactions, preceded by an empty cellbase
+    pub fn get_block(&self, hash: &Byte32) -> Option<BlockView> {
+        let header = self.headers.get(hash)?;
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(header.number()))
+            .build();
+        Some(
+            BlockView::new_advanced_builder()
+                .header(header.clone())
+                .transaction(cellbase)
+                .transactions(self.block_txs.get(hash).cloned().unwrap_or_default())
+                .build_unchecked(),
+        )
+    }
+
+    // Live cells matching the indexer search key, ordered by block number, transaction index and
+    // output index. Scripts are matched on code hash, hash type and args prefix, as ckb-indexer does.
+    pub fn search_cells(&self, search_key: &SearchKey) -> Vec<OutPoint> {
+        let prefix_match = |search: &Script, script: &Script| {
+            search.code_hash() == script.code_hash()
+                && search.hash_type() == script.hash_type()
+                && script
+                    .args()
+                    .raw_data()
+                    .starts_with(&search.args().raw_data())
+        };
+        let in_range = |range: &Option<[ckb_jsonrpc_types::Uint64; 2]>, value: u64| {
+            range.map_or(true, |[start, end]| {
+                u64::from(start) <= value && value < u64::from(end)
+            })
+        };
+        let search = Script::from(search_key.script.clone());
+        let filter = search_key.filter.clone().unwrap_or_default();
+        let filter_script = filter.script.map(Script::from);
+
+        let mut cells = self
+            .cells
+            .iter()
+            .filter(|(outp, _)| !self.dead_cells.contains(*outp))
+            .filter(|(_, (output, data))| {
+                let (searched, other) = match search_key.script_type {
+                    ScriptType::Lock => (Some(output.lock()), output.type_().to_opt()),
+                    ScriptType::Type => (output.type_().to_opt(), Some(output.lock())),
+                };
+                let capacity: u64 = output.capacity().unpack();
+                searched.map_or(false, |script| prefix_match(&search, &script))
+                    && filter_script.as_ref().map_or(true, |filter| {
+                        other.map_or(false, |script| prefix_match(filter, &script))
+                    })
+                    && in_range(&filter.output_data_len_range, data.len() as u64)
+                    && in_range(&filter.output_capacity_range, capacity)
+            })
+            .map(|(outp, _)| {
+                let (block_number, tx_index) = self
+                    .outpoint_txs
+                    .get(outp)
+                    .map(|info| (info.block_number, info.index))
+                    .unwrap_or_default();
+                let index: u32 = outp.index().unpack();
+                ((block_number, tx_index, index), outp.clone())
+            })
+            .filter(|((block_number, _, _), _)| in_range(&filter.block_range, *block_number))
+            .collect::<Vec<_>>();
+        cells.sort_by(|(a, a_outp), (b, b_outp)| {
+            a.cmp(b)
+                .then_with(|| a_outp.as_slice().cmp(b_outp.as_slice()))
+        });
+        cells.into_iter().map(|(_, outp)| outp).collect()
+    }
+
+    // Header of the mock block at `number`; blocks are BLOCK_INTERVAL apart and epochs EPOCH_LENGTH long
+    fn build_header(&self, number: BlockNumber, timestamp: u64) -> HeaderView {
+        let epoch = EpochNumberWithFraction::new(
+            number / EPOCH_LENGTH,
+            number % EPOCH_LENGTH,
+            EPOCH_LENGTH,
+        );
+        let builder = HeaderView::new_advanced_builder()
+            .number(number.pack())
+            .epoch(epoch.pack())
+            .timestamp(timestamp.pack());
+        if number == 0 {
+            builder.build()
+        } else {
+            builder.parent_hash(self.tip.hash()).build()
+        }
+    }
+
+    // Header the next produced block will have, used as the verification context for new transactions
+    fn next_header(&self) -> HeaderView {
+        self.build_header(self.tip.number() + 1, self.tip.timestamp() + BLOCK_INTERVAL)
+    }
+
+    // Commit the transactions into the block with the given header and make it the new tip.
+    // Transaction 0 of every block is reserved for the cellbase, so committed transactions start at 1.
+    fn insert_block(&mut self, header: HeaderView, txs: Vec<TransactionView>) -> HeaderView {
+        let hash = header.hash();
+        let epoch = header.epoch();
+        self.epoches.insert(
+            hash.clone(),
+            EpochExt::new_builder()
+                .number(epoch.number())
+                .start_number(epoch.number() * EPOCH_LENGTH)
+                .length(EPOCH_LENGTH)
+                .build(),
+        );
+        self.block_hashes.insert(header.number(), hash.clone());
+        self.insert_header(header.clone());
+        self.tip = header.clone();
+
+        for (tx_idx, tx) in txs.iter().enumerate() {
+            let tx_hash = tx.hash();
+            tx.input_pts_iter()
+                .for_each(|outp| self.consume_cell(&outp));
+            for (idx, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+                let outpoint = OutPoint::new(tx_hash.clone(), idx as u32);
+                self.create_cell_with_outpoint(outpoint.clone(), output, data);
+                self.link_cell_with_block(outpoint, hash.clone(), tx_idx + 1);
+            }
+        }
+        self.block_txs.insert(hash, txs);
+        header
+    }
+
+    // Produce a block at `number` containing every pending transaction
+    fn produce_block_at(&mut self, number: BlockNumber, timestamp: u64) -> HeaderView {
+        let header = self.build_header(number, timestamp);
+        let txs = std::mem::take(&mut self.pending_txs);
+        self.insert_block(header, txs)
+    }
+
+    /// Commit the pending transactions into a new block on top of the tip.
+    pub fn commit_block(&mut self) -> HeaderView {
+        let next = self.next_header();
+        self.produce_block_at(next.number(), next.timestamp())
+    }
+
+    // Produce `blocks` consecutive blocks BLOCK_INTERVAL apart, the last one at `timestamp`.
+    // Pending transactions are committed in the first block, the others are empty.
+    fn produce_blocks(&mut self, blocks: u64, timestamp: u64) -> HeaderView {
+        let start = self.tip.number();
+        for offset in 1..=blocks {
+            let block_timestamp = timestamp - (blocks - offset) * BLOCK_INTERVAL;
+            self.produce_block_at(start + offset, block_timestamp);
+        }
+        self.tip.clone()
+    }
+
+    /// Produce `blocks` blocks on top of the tip. Pending transactions are committed in the
+    /// first of them. With no blocks to produce, the tip is returned unchanged.
+    pub fn advance_blocks(&mut self, blocks: u64) -> HeaderView {
+        self.produce_blocks(blocks, self.tip.timestamp() + blocks * BLOCK_INTERVAL)
+    }
+
+    /// Produce blocks up to the first block of the epoch `epochs` epochs after the tip's epoch.
+    /// Advancing zero epochs leaves the tip unchanged.
+    pub fn advance_epochs(&mut self, epochs: u64) -> HeaderView {
+        if epochs == 0 {
+            return self.tip.clone();
+        }
+        let target = (self.tip.epoch().number() + epochs) * EPOCH_LENGTH;
+        self.advance_blocks(target - self.tip.number())
+    }
+
+    /// Move the chain `millis` milliseconds into the future, producing the blocks that
+    /// would have been mined in that time. Less than `BLOCK_INTERVAL` produces no block and
+    /// leaves the tip unchanged.
+    pub fn advance_time(&mut self, millis: u64) -> HeaderView {
+        let blocks = millis / BLOCK_INTERVAL;
+        self.produce_blocks(blocks, self.tip.timestamp() + millis)
+    }
+
     pub fn get_cell_by_data_hash(&self, data_hash: &Byte32) -> Option<OutPoint> {
         self.cells_by_data_hash.get(data_hash).cloned()
     }
@@ -149,10 +471,16 @@ impl MockChain {
         outpoint
     }
 
+    // Cells created outside of a committed transaction are linked to the current tip
     pub fn create_cell_with_outpoint(&mut self, outp: OutPoint, cell: CellOutput, data: Bytes) {
         let data_hash = CellOutput::calc_data_hash(&data);
+        self.all_cells_by_data_hash
+            .entry(data_hash.clone())
+            .or_default()
+            .push(outp.clone());
         self.cells_by_data_hash.insert(data_hash, outp.clone());
         self.cells.insert(outp.clone(), (cell.clone(), data));
+        self.link_cell_with_block(outp.clone(), self.tip.hash(), 0);
         let cells = self.get_cells_by_lock_hash(cell.calc_lock_hash());
         if let Some(mut cells) = cells {
             cells.push(outp.clone());
@@ -178,17 +506,196 @@ impl MockChain {
         self.cells.get(out_point).cloned()
     }
 
+    pub fn is_live(&self, out_point: &OutPoint) -> bool {
+        self.cells.contains_key(out_point) && !self.dead_cells.contains(out_point)
+    }
+
+    pub fn get_live_cell(&self, out_point: &OutPoint) -> Option<CellOutputWithData> {
+        if self.is_live(out_point) {
+            self.get_cell(out_point)
+        } else {
+            None
+        }
+    }
+
+    // Mark a cell as spent and remove it from every index
+    pub fn consume_cell(&mut self, out_point: &OutPoint) {
+        let (cell, data) = match self.cells.get(out_point) {
+            Some(cell) => cell.clone(),
+            None => return,
+        };
+        self.dead_cells.insert(out_point.clone());
+
+        if let Some(cells) = self.cells_by_lock_hash.get_mut(&cell.calc_lock_hash()) {
+            cells.retain(|outp| outp != out_point);
+        }
+        if let Some(script) = cell.type_().to_opt() {
+            if let Some(cells) = self.cells_by_type_hash.get_mut(&script.calc_script_hash()) {
+                cells.retain(|outp| outp != out_point);
+            }
+        }
+
+        // Point the data hash index at another live cell with the same data, if there is one
+        let data_hash = CellOutput::calc_data_hash(&data);
+        let mut replacement = None;
+        if let Some(cells) = self.all_cells_by_data_hash.get_mut(&data_hash) {
+            cells.retain(|outp| outp != out_point);
+            replacement = cells.first().cloned();
+        }
+        if self.cells_by_data_hash.get(&data_hash) == Some(out_point) {
+            match replacement {
+                Some(outp) => {
+                    self.cells_by_data_hash.insert(data_hash, outp);
+                }
+                None => {
+                    self.cells_by_data_hash.remove(&data_hash);
+                }
+            }
+        }
+    }
+
+    // Check that every input and cell dep of the transaction refers to a known, live cell
+    pub fn check_tx_cells(&self, tx: &TransactionView) -> MockChainResult<()> {
+        for outp in tx.input_pts_iter() {
+            if !self.cells.contains_key(&outp) {
+                return Err(MockChainError::UnknownInput(outp));
+            }
+            if self.dead_cells.contains(&outp) {
+                return Err(MockChainError::DeadInput(outp));
+            }
+        }
+        for dep in tx.cell_deps_iter() {
+            let outp = dep.out_point();
+            if !self.cells.contains_key(&outp) {
+                return Err(MockChainError::UnknownCellDep(outp));
+            }
+            if self.dead_cells.contains(&outp) {
+                return Err(MockChainError::DeadCellDep(outp));
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the live cell at `outp` satisfies a single query attribute
+    pub fn cell_matches(&self, outp: &OutPoint, attr: &CellQueryAttribute) -> bool {
+        let (output, data) = match self.get_live_cell(outp) {
+            Some(cell) => cell,
+            None => return false,
+        };
+        let capacity: u64 = output.capacity().unpack();
+        match attr {
+            CellQueryAttribute::LockHash(hash) => {
+                output.calc_lock_hash() == Byte32::from(hash.clone())
+            }
+            CellQueryAttribute::LockScript(script) => output.lock() == Script::from(script.clone()),
+            CellQueryAttribute::TypeScript(script) => {
+                output.type_().to_opt() == Some(Script::from(script.clone()))
+            }
+            CellQueryAttribute::TypeHash(hash) => {
+                output
+                    .type_()
+                    .to_opt()
+                    .map(|script| script.calc_script_hash())
+                    == Some(Byte32::from(hash.clone()))
+            }
+            CellQueryAttribute::MinCapacity(min) => capacity >= u64::from(*min),
+            CellQueryAttribute::MaxCapacity(max) => capacity <= u64::from(*max),
+            CellQueryAttribute::DataHash(hash) => {
+                CellOutput::calc_data_hash(&data) == Byte32::from(hash.clone())
+            }
+        }
+    }
+
+    // Live cells matching the attribute. Lock, type and data hash lookups only visit the cells
+    // of their index, in insertion order; capacity ranges scan every cell, in block order.
+    fn query_attribute(&self, attr: &CellQueryAttribute) -> Vec<OutPoint> {
+        let indexed = match attr {
+            CellQueryAttribute::LockHash(hash) => self.get_cells_by_lock_hash(hash.clone().into()),
+            CellQueryAttribute::LockScript(script) => {
+                self.get_cells_by_lock_hash(Script::from(script.clone()).calc_script_hash())
+            }
+            CellQueryAttribute::TypeScript(script) => {
+                self.get_cells_by_type_hash(Script::from(script.clone()).calc_script_hash())
+            }
+            CellQueryAttribute::TypeHash(hash) => self.get_cells_by_type_hash(hash.clone().into()),
+            CellQueryAttribute::DataHash(hash) => self.get_cells_by_data_hash(hash.clone().into()),
+            CellQueryAttribute::MinCapacity(_) | CellQueryAttribute::MaxCapacity(_) => {
+                let mut cells = self
+                    .cells
+                    .keys()
+                    .filter(|outp| self.cell_matches(outp, attr))
+                    .map(|outp| {
+                        let position = self
+                            .outpoint_txs
+                            .get(outp)
+                            .map(|info| (info.block_number, info.index))
+                            .unwrap_or_default();
+                        (position, outp.clone())
+                    })
+                    .collect::<Vec<_>>();
+                cells.sort_by(|(a, a_outp), (b, b_outp)| {
+                    a.cmp(b)
+                        .then_with(|| a_outp.as_slice().cmp(b_outp.as_slice()))
+                });
+                return cells.into_iter().map(|(_, outp)| outp).collect();
+            }
+        };
+
+        indexed
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|outp| self.cell_matches(outp, attr))
+            .collect()
+    }
+
+    /// Live cells matching the query, up to its limit.
+    ///
+    /// `All` and `FilterFrom` return the cells matching every attribute, in the order of the
+    /// first one; `Any` returns the cells matching at least one attribute, without duplicates.
+    pub fn query_cells(&self, query: &CellQuery) -> Vec<OutPoint> {
+        let intersect = |attrs: &[CellQueryAttribute]| match attrs.split_first() {
+            Some((first, rest)) => self
+                .query_attribute(first)
+                .into_iter()
+                .filter(|outp| rest.iter().all(|attr| self.cell_matches(outp, attr)))
+                .collect(),
+            None => vec![],
+        };
+        let cells: Vec<OutPoint> = match &query._query {
+            QueryStatement::Single(attr) => self.query_attribute(attr),
+            QueryStatement::FilterFrom(source, filter) => {
+                intersect([source.clone(), filter.clone()].as_slice())
+            }
+            QueryStatement::All(attrs) => intersect(attrs.as_slice()),
+            QueryStatement::Any(attrs) => {
+                let mut seen = HashSet::new();
+                attrs
+                    .iter()
+                    .flat_map(|attr| self.query_attribute(attr))
+                    .filter(|outp| seen.insert(outp.clone()))
+                    .collect()
+            }
+        };
+        let limit = usize::try_from(query._limit).unwrap_or(usize::MAX);
+        cells.into_iter().take(limit).collect()
+    }
+
+    /// A script running the code stored at `outp`. With `ScriptHashType::Type` the code is
+    /// referenced by the hash of the code cell's type script, None if it has none.
     pub fn build_script_with_hash_type(
         &self,
         outp: &OutPoint,
         typ: ScriptHashType,
         args: Bytes,
     ) -> Option<Script> {
-        let (_, contract_data) = self.cells.get(outp)?;
-        let data_hash = CellOutput::calc_data_hash(contract_data);
+        let (contract_output, contract_data) = self.cells.get(outp)?;
+        let code_hash = match typ {
+            ScriptHashType::Type => contract_output.type_().to_opt()?.calc_script_hash(),
+            _ => CellOutput::calc_data_hash(contract_data),
+        };
         Some(
             Script::new_builder()
-                .code_hash(data_hash)
+                .code_hash(code_hash)
                 .hash_type(typ.into())
                 .args(args.pack())
                 .build(),
@@ -203,29 +710,30 @@ impl MockChain {
         self.cells_by_type_hash.get(&hash).cloned()
     }
 
+    pub fn get_cells_by_data_hash(&self, hash: Byte32) -> Option<Vec<OutPoint>> {
+        self.all_cells_by_data_hash.get(&hash).cloned()
+    }
+
     pub fn build_script(&self, outp: &OutPoint, args: Bytes) -> Option<Script> {
         self.build_script_with_hash_type(outp, ScriptHashType::Data1, args)
     }
 
-    pub fn find_cell_dep_for_script(&self, script: &Script) -> CellDep {
-        if script.hash_type() != ScriptHashType::Data.into()
-            && script.hash_type() != ScriptHashType::Data1.into()
-        {
-            panic!("do not support hash_type {} yet", script.hash_type());
-        }
-
-        let out_point = self
-            .get_cell_by_data_hash(&script.code_hash())
-            .unwrap_or_else(|| {
-                panic!(
-                    "Cannot find contract out point with data_hash: {}",
-                    &script.code_hash()
-                )
-            });
-        CellDep::new_builder()
-            .out_point(out_point)
-            .dep_type(DepType::Code.into())
-            .build()
+    // Code dep for the live cell holding the script's code, looked up by type hash or data hash
+    // depending on the script's hash type
+    pub fn find_cell_dep_for_script(&self, script: &Script) -> Option<CellDep> {
+        let out_point = if script.hash_type() == ScriptHashType::Type.into() {
+            self.get_cells_by_type_hash(script.code_hash())?
+                .into_iter()
+                .find(|outp| self.is_live(outp))?
+        } else {
+            self.get_cell_by_data_hash(&script.code_hash())?
+        };
+        Some(
+            CellDep::new_builder()
+                .out_point(out_point)
+                .dep_type(DepType::Code.into())
+                .build(),
+        )
     }
 
     pub fn complete_tx(&mut self, tx: TransactionView) -> TransactionView {
@@ -334,6 +842,9 @@ impl MockChain {
 
     /// Verify the transaction by given context (Consensus, TxVerifyEnv) in CKB-VM
     ///
+    /// Script groups are verified one at a time so that a failure reports the group
+    /// that failed along with the script's exit code.
+    ///
     /// Please see below links for more details:
     ///   - https://docs.rs/ckb-chain-spec/0.101.2/ckb_chain_spec/consensus/struct.Consensus.html
     ///   - https://docs.rs/ckb-types/0.101.2/ckb_types/core/hardfork/struct.HardForkSwitch.html
@@ -344,10 +855,28 @@ impl MockChain {
         max_cycles: u64,
         consensus: &Consensus,
         tx_env: &TxVerifyEnv,
-    ) -> Result<Cycle, CKBError> {
-        self.verify_tx_consensus(tx)?;
-        let resolved_tx = self.build_resolved_tx(tx);
-        let mut verifier = TransactionScriptsVerifier::new(&resolved_tx, consensus, self, tx_env);
+    ) -> MockChainResult<Cycle> {
+        let resolved_tx = self.resolve_tx(tx)?;
+        self.verify_resolved_tx_by_context(&resolved_tx, max_cycles, consensus, tx_env)
+    }
+
+    /// Check that the inputs and cell deps of the transaction are live cells and resolve them
+    pub fn resolve_tx(&self, tx: &TransactionView) -> MockChainResult<ResolvedTransaction> {
+        self.check_tx_cells(tx)?;
+        Ok(self.build_resolved_tx(tx))
+    }
+
+    /// Like [`MockChain::verify_tx_by_context`], for a transaction resolved by
+    /// [`MockChain::resolve_tx`]
+    pub fn verify_resolved_tx_by_context(
+        &self,
+        resolved_tx: &ResolvedTransaction,
+        max_cycles: u64,
+        consensus: &Consensus,
+        tx_env: &TxVerifyEnv,
+    ) -> MockChainResult<Cycle> {
+        self.verify_tx_consensus(&resolved_tx.transaction)?;
+        let mut verifier = TransactionScriptsVerifier::new(resolved_tx, consensus, self, tx_env);
         if self.debug {
             let captured_messages = self.messages.clone();
             verifier.set_debug_printer(move |id, message| {
@@ -362,52 +891,171 @@ impl MockChain {
                 println!("[contract debug] {}", msg);
             });
         }
-        verifier.verify(max_cycles)
+
+        let mut cycles: Cycle = 0;
+        for (script_hash, group) in verifier.groups() {
+            let result = verifier
+                .verify_single(group.group_type, script_hash, max_cycles - cycles)
+                .and_then(|used| match cycles.checked_add(used) {
+                    Some(total) if total <= max_cycles => Ok(total),
+                    _ => Err(ScriptError::ExceededMaximumCycles(max_cycles)),
+                });
+            match result {
+                Ok(total) => cycles = total,
+                Err(e) => return Err(script_failure(e, script_hash, group)),
+            }
+        }
+        Ok(cycles)
     }
 
-    /// Verify the transaction in CKB-VM
+    /// Verify the transaction in CKB-VM as if it were committed in the next block
     ///
     /// This method use a default verify context with:
-    ///   - use HardForkSwitch to set `rfc_0032` field to 200 (means enable VM selection feature after epoch 200)
-    ///   - use TxVerifyEnv to set currently transaction `epoch` number to 300
-    pub fn verify_tx(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, CKBError> {
+    ///   - use HardForkSwitch to set `rfc_0032` field to 0 (means enable VM selection feature from genesis)
+    ///   - use TxVerifyEnv with the header of the block following the current tip
+    pub fn verify_tx(&self, tx: &TransactionView, max_cycles: u64) -> MockChainResult<Cycle> {
+        let resolved_tx = self.resolve_tx(tx)?;
+        self.verify_resolved_tx(&resolved_tx, max_cycles)
+    }
+
+    // Verify a resolved transaction the way `verify_tx` does
+    fn verify_resolved_tx(
+        &self,
+        resolved_tx: &ResolvedTransaction,
+        max_cycles: u64,
+    ) -> MockChainResult<Cycle> {
         let consensus = {
             let hardfork_switch = HardForkSwitch::new_without_any_enabled()
                 .as_builder()
-                .rfc_0032(200)
+                .rfc_0032(0)
                 .build()
                 .unwrap();
             ConsensusBuilder::default()
                 .hardfork_switch(hardfork_switch)
                 .build()
         };
-        let tx_env = {
-            let epoch = EpochNumberWithFraction::new(300, 0, 1);
-            let header = HeaderView::new_advanced_builder()
-                .epoch(epoch.pack())
-                .build();
-            TxVerifyEnv::new_commit(&header)
-        };
-        self.verify_tx_by_context(tx, max_cycles, &consensus, &tx_env)
-    }
-
-    pub fn receive_tx(&mut self, tx: &TransactionView) -> Result<Byte32, CKBError> {
-        match self.verify_tx(tx, MAX_CYCLES) {
-            Ok(_) => {
-                let tx_hash = tx.hash();
-                let mut idx: u32 = 0;
-                tx.outputs_with_data_iter().for_each(|out| {
-                    let outpoint = OutPoint::new_builder()
-                        .tx_hash(tx_hash.clone())
-                        .index(idx.pack())
-                        .build();
-                    self.create_cell_with_outpoint(outpoint, out.0, out.1);
-                    idx += 1;
-                });
-                Ok(tx_hash)
+        let tx_env = TxVerifyEnv::new_commit(&self.next_header());
+        self.check_tx_since(&resolved_tx.transaction)?;
+        self.verify_resolved_tx_by_context(resolved_tx, max_cycles, &consensus, &tx_env)
+    }
+
+    // Check the `since` field of every input against the block the transaction would be committed in.
+    // Timestamps are compared with the tip's timestamp instead of the median of past blocks.
+    pub fn check_tx_since(&self, tx: &TransactionView) -> MockChainResult<()> {
+        let next = self.next_header();
+        for (index, input) in tx.inputs().into_iter().enumerate() {
+            let since: u64 = input.since().unpack();
+            if since == 0 {
+                continue;
+            }
+            let out_point = input.previous_output();
+            if since & SINCE_REMAIN_MASK != 0 {
+                return Err(MockChainError::InvalidSince { index, out_point });
+            }
+            let value = since & SINCE_VALUE_MASK;
+            let mature = if since & SINCE_RELATIVE_FLAG == 0 {
+                match since & SINCE_METRIC_MASK {
+                    SINCE_METRIC_BLOCK_NUMBER => next.number() >= value,
+                    SINCE_METRIC_EPOCH => epoch_le(
+                        EpochNumberWithFraction::from_full_value(value),
+                        next.epoch(),
+                    ),
+                    SINCE_METRIC_TIMESTAMP => self.tip.timestamp() >= value * 1000,
+                    _ => return Err(MockChainError::InvalidSince { index, out_point }),
+                }
+            } else {
+                let info = match self.outpoint_txs.get(&out_point) {
+                    Some(info) => info,
+                    None => return Err(MockChainError::ImmatureInput { index, out_point }),
+                };
+                match since & SINCE_METRIC_MASK {
+                    SINCE_METRIC_BLOCK_NUMBER => next.number() >= info.block_number + value,
+                    SINCE_METRIC_EPOCH => {
+                        let delay = EpochNumberWithFraction::from_full_value(value);
+                        epoch_le_sum(info.block_epoch, delay, next.epoch())
+                    }
+                    SINCE_METRIC_TIMESTAMP => self
+                        .headers
+                        .get(&info.block_hash)
+                        .map(|header| self.tip.timestamp() >= header.timestamp() + value * 1000)
+                        .unwrap_or(false),
+                    _ => return Err(MockChainError::InvalidSince { index, out_point }),
+                }
+            };
+            if !mature {
+                return Err(MockChainError::ImmatureInput { index, out_point });
             }
-            Err(_) => todo!(),
         }
+        Ok(())
+    }
+
+    /// Verify the transaction and add it to the pending transactions committed by the next block.
+    pub fn submit_tx(&mut self, tx: &TransactionView) -> MockChainResult<Byte32> {
+        let resolved_tx = self.resolve_tx(tx)?;
+        let pending_inputs: HashSet<OutPoint> = self
+            .pending_txs
+            .iter()
+            .flat_map(|pending| pending.input_pts_iter())
+            .collect();
+        if let Some(outp) = tx
+            .input_pts_iter()
+            .find(|outp| pending_inputs.contains(outp))
+        {
+            return Err(MockChainError::DeadInput(outp));
+        }
+        self.verify_resolved_tx(&resolved_tx, MAX_CYCLES)?;
+        self.pending_txs.push(tx.clone());
+        Ok(tx.hash())
+    }
+
+    // Verify the transaction and commit it in a new block: its inputs are consumed and its outputs become live cells
+    pub fn receive_tx(&mut self, tx: &TransactionView) -> MockChainResult<Byte32> {
+        let tx_hash = self.submit_tx(tx)?;
+        self.commit_block();
+        Ok(tx_hash)
+    }
+}
+
+// Whether epoch `a` is not after epoch `b`
+fn epoch_le(a: EpochNumberWithFraction, b: EpochNumberWithFraction) -> bool {
+    epoch_le_sum(a, EpochNumberWithFraction::new(0, 0, 1), b)
+}
+
+// Whether `a + delay` is not after `b`, comparing the fractional epochs exactly
+fn epoch_le_sum(
+    a: EpochNumberWithFraction,
+    delay: EpochNumberWithFraction,
+    b: EpochNumberWithFraction,
+) -> bool {
+    let parts = |e: EpochNumberWithFraction| {
+        let length = e.length().max(1) as u128;
+        (e.number() as u128 * length + e.index() as u128, length)
+    };
+    let (a, a_len) = parts(a);
+    let (delay, delay_len) = parts(delay);
+    let (b, b_len) = parts(b);
+    (a * delay_len + delay * a_len) * b_len <= b * a_len * delay_len
+}
+
+fn script_failure(error: ScriptError, script_hash: &Byte32, group: &ScriptGroup) -> MockChainError {
+    let exit_code = match &error {
+        ScriptError::ValidationFailure(_, code) => Some(*code),
+        _ => None,
+    };
+    let cell = match (
+        group.group_type,
+        group.input_indices.first(),
+        group.output_indices.first(),
+    ) {
+        (ScriptGroupType::Type, None, Some(idx)) => ScriptCell::Output(*idx),
+        (_, idx, _) => ScriptCell::Input(idx.copied().unwrap_or_default()),
+    };
+    MockChainError::Script {
+        error: error.source(group).into(),
+        group_type: group.group_type,
+        cell,
+        script_hash: script_hash.clone(),
+        exit_code,
     }
 }
 
@@ -453,114 +1101,60 @@ impl MockChainTxProvider {
 }
 
 impl TransactionProvider for MockChainTxProvider {
-    fn send_tx(&self, tx: JsonTransaction) -> Option<ckb_jsonrpc_types::Byte32> {
+    fn send_tx(&self, tx: JsonTransaction) -> ProviderResult<ckb_jsonrpc_types::Byte32> {
         let mut chain = self.chain.borrow_mut();
         let inner_tx = tx.inner;
         let inner_tx = ckb_types::packed::Transaction::from(inner_tx);
         let converted_tx_view = inner_tx.as_advanced_builder().build();
         let tx = chain.complete_tx(converted_tx_view);
-        if let Ok(hash) = chain.receive_tx(&tx) {
-            let tx_hash: ckb_jsonrpc_types::Byte32 = hash.into();
-            Some(tx_hash)
-        } else {
-            None
-        }
+        let tx_hash = chain.receive_tx(&tx)?;
+        Ok(tx_hash.into())
     }
 
-    fn verify_tx(&self, tx: JsonTransaction) -> bool {
+    fn verify_tx(&self, tx: JsonTransaction) -> ProviderResult<Cycle> {
         let mut chain = self.chain.borrow_mut();
         let inner_tx = tx.inner;
         let inner_tx = ckb_types::packed::Transaction::from(inner_tx);
         let converted_tx_view = inner_tx.as_advanced_builder().build();
         let tx = chain.complete_tx(converted_tx_view);
-        println!(
-            "TX AFTER CHAIN COMPLETE {:#?}",
-            ckb_jsonrpc_types::TransactionView::from(tx.clone())
-        );
-        let result = chain.verify_tx(&tx, MAX_CYCLES);
-        match result {
-            Ok(_) => true,
-            Err(e) => {
-                println!("Error in tx verify: {:?}", e);
-                false
-            }
-        }
+        Ok(chain.verify_tx(&tx, MAX_CYCLES)?)
     }
 }
 
 impl QueryProvider for MockChainTxProvider {
-    fn query_cell_meta(&self, query: CellQuery) -> Option<Vec<CellMeta>> {
-        if let Some(outpoints) = self.query(query) {
-            println!("OUTPOINTS TO CREATE CELL META: {:?}", outpoints);
-            Some(
-                outpoints
-                    .iter()
-                    .map(|outp| {
-                        let outp = ckb_types::packed::OutPoint::from(outp.clone());
-                        let cell_output = self.chain.borrow().get_cell(&outp).unwrap();
-                        CellMetaBuilder::from_cell_output(cell_output.0, cell_output.1)
-                            .out_point(outp)
-                            .build()
-                    })
-                    .collect(),
-            )
-        } else {
-            println!("NO OUTPOINTS TO RESOLVE IN QUERY CELL META");
-            None
-        }
+    fn query_cell_meta(&self, query: CellQuery) -> ProviderResult<Vec<CellMeta>> {
+        let outpoints = self.query(query)?;
+        println!("OUTPOINTS TO CREATE CELL META: {:?}", outpoints);
+        Ok(outpoints
+            .iter()
+            .map(|outp| {
+                let outp = ckb_types::packed::OutPoint::from(outp.clone());
+                let cell_output = self.chain.borrow().get_cell(&outp).unwrap();
+                CellMetaBuilder::from_cell_output(cell_output.0, cell_output.1)
+                    .out_point(outp)
+                    .build()
+            })
+            .collect())
     }
-    fn query(&self, query: CellQuery) -> Option<Vec<ckb_jsonrpc_types::OutPoint>> {
-        let CellQuery { _query, _limit } = query;
-        println!("QUERY FROM QUERY PROVIDER: {:?}", _query);
-        match _query {
-            QueryStatement::Single(query_attr) => match query_attr {
-                CellQueryAttribute::LockHash(hash) => {
-                    let cells = self.chain.borrow().get_cells_by_lock_hash(hash.into());
-                    Some(
-                        cells
-                            .unwrap()
-                            .into_iter()
-                            .map(|outp| outp.into())
-                            .collect::<Vec<ckb_jsonrpc_types::OutPoint>>(),
-                    )
-                }
-                CellQueryAttribute::LockScript(script) => {
-                    let script = ckb_types::packed::Script::from(script);
-                    let cells = self
-                        .chain
-                        .borrow()
-                        .get_cells_by_lock_hash(script.calc_script_hash());
-                    Some(
-                        cells
-                            .unwrap()
-                            .into_iter()
-                            .map(|outp| outp.into())
-                            .collect::<Vec<ckb_jsonrpc_types::OutPoint>>(),
-                    )
-                }
-                CellQueryAttribute::TypeScript(script) => {
-                    let script = ckb_types::packed::Script::from(script);
-                    let cells = self
-                        .chain
-                        .borrow()
-                        .get_cells_by_type_hash(script.calc_script_hash());
-                    Some(
-                        cells
-                            .unwrap()
-                            .into_iter()
-                            .map(|outp| outp.into())
-                            .collect::<Vec<ckb_jsonrpc_types::OutPoint>>(),
-                    )
-                }
-                CellQueryAttribute::DataHash(hash) => Some(vec![self
-                    .chain
-                    .borrow()
-                    .get_cell_by_data_hash(&hash.into())
-                    .unwrap()
-                    .into()]),
-                _ => panic!("Capacity based queries currently unsupported!"),
-            },
-            _ => panic!("Compund queries currently unsupported!"),
-        }
+    fn resolve_cell(&self, out_point: OutPoint) -> ProviderResult<CellMeta> {
+        let (output, data) = self
+            .chain
+            .borrow()
+            .get_live_cell(&out_point)
+            .ok_or_else(|| ProviderError::DeadCell(out_point.clone()))?;
+        Ok(CellMetaBuilder::from_cell_output(output, data)
+            .out_point(out_point)
+            .build())
+    }
+
+    fn query(&self, query: CellQuery) -> ProviderResult<Vec<ckb_jsonrpc_types::OutPoint>> {
+        println!("QUERY FROM QUERY PROVIDER: {:?}", query);
+        Ok(self
+            .chain
+            .borrow()
+            .query_cells(&query)
+            .into_iter()
+            .map(Into::into)
+            .collect())
     }
 }
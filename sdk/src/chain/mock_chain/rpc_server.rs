@@ -0,0 +1,321 @@
+use super::*;
+use crate::rpc::{CkbErrorCode, IndexerCell, Order, Pagination};
+use ckb_jsonrpc_types::{
+    CellData, CellInfo, CellWithStatus, DryRunResult, JsonBytes, OutPoint as JsonOutPoint,
+    Transaction as JsonTx, TransactionWithStatus, TxStatus, Uint32, Uint64,
+};
+use ckb_types::{packed, H256};
+use jsonrpc_core::{Error as RpcError, ErrorCode, IoHandler, Params, Value};
+use jsonrpc_http_server::{Server, ServerBuilder};
+use serde::{de::DeserializeOwned, Serialize};
+use std::net::SocketAddr;
+
+type RpcResult = jsonrpc_core::Result<Value>;
+
+/// HTTP server exposing a [`MockChain`] through the CKB node and ckb-indexer JSON-RPC methods.
+///
+/// Transactions sent to the server are committed in a new block immediately.
+pub struct MockChainRpcServer {
+    server: Server,
+    chain: Arc<Mutex<MockChain>>,
+}
+
+impl MockChainRpcServer {
+    /// Start serving `chain` on `addr`. Use port 0 to let the OS pick a free port.
+    pub fn start(chain: MockChain, addr: &SocketAddr) -> MockChainResult<Self> {
+        let chain = Arc::new(Mutex::new(chain));
+        let server = ServerBuilder::new(rpc_handler(&chain)).start_http(addr)?;
+        Ok(Self { server, chain })
+    }
+
+    pub fn address(&self) -> &SocketAddr {
+        self.server.address()
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.server.address())
+    }
+
+    /// The served chain, for inspecting or changing its state while the server runs.
+    pub fn chain(&self) -> Arc<Mutex<MockChain>> {
+        self.chain.clone()
+    }
+
+    pub fn close(self) {
+        self.server.close()
+    }
+
+    /// Block the current thread until the server is closed.
+    pub fn wait(self) {
+        self.server.wait()
+    }
+}
+
+fn rpc_handler(chain: &Arc<Mutex<MockChain>>) -> IoHandler {
+    let mut io = IoHandler::new();
+    register(&mut io, chain, "send_transaction", send_transaction);
+    register(&mut io, chain, "dry_run_transaction", dry_run_transaction);
+    register(&mut io, chain, "get_transaction", get_transaction);
+    register(&mut io, chain, "get_live_cell", get_live_cell);
+    register(&mut io, chain, "get_tip_header", get_tip_header);
+    register(&mut io, chain, "get_tip_block_number", get_tip_block_number);
+    register(&mut io, chain, "get_header", get_header);
+    register(&mut io, chain, "get_block_hash", get_block_hash);
+    register(&mut io, chain, "get_block", get_block);
+    register(&mut io, chain, "get_block_by_number", get_block_by_number);
+    register(&mut io, chain, "get_cells", get_cells);
+    register(&mut io, chain, "generate_block", generate_block);
+    io
+}
+
+fn register<F>(io: &mut IoHandler, chain: &Arc<Mutex<MockChain>>, name: &str, method: F)
+where
+    F: Fn(&mut MockChain, Params) -> RpcResult + Send + Sync + 'static,
+{
+    let chain = chain.clone();
+    io.add_sync_method(name, move |params| {
+        let mut chain = chain
+            .lock()
+            .map_err(|_| internal_error("mock chain lock poisoned"))?;
+        method(&mut chain, params)
+    });
+}
+
+// Parse positional params; missing trailing params are treated as null so optional ones can be left out
+fn positional<T: DeserializeOwned>(params: Params, arity: usize) -> jsonrpc_core::Result<T> {
+    let mut values = match params {
+        Params::Array(values) => values,
+        Params::None => vec![],
+        Params::Map(_) => return Err(RpcError::invalid_params("expected positional params")),
+    };
+    if values.len() > arity {
+        return Err(RpcError::invalid_params(format!(
+            "expected at most {} params, got {}",
+            arity,
+            values.len()
+        )));
+    }
+    values.resize(arity, Value::Null);
+    serde_json::from_value(Value::Array(values))
+        .map_err(|e| RpcError::invalid_params(e.to_string()))
+}
+
+fn to_value<T: Serialize>(value: T) -> RpcResult {
+    serde_json::to_value(value).map_err(|e| internal_error(e.to_string()))
+}
+
+fn internal_error(message: impl Into<String>) -> RpcError {
+    RpcError {
+        code: ErrorCode::InternalError,
+        message: message.into(),
+        data: None,
+    }
+}
+
+// Mirrors the messages of a node, which `CkbRpcError` parses the details from
+fn transaction_error(e: MockChainError) -> RpcError {
+    let (code, message) = match e {
+        MockChainError::UnknownInput(outp) | MockChainError::UnknownCellDep(outp) => (
+            CkbErrorCode::TransactionFailedToResolve,
+            format!("Resolve failed Unknown({})", outp),
+        ),
+        MockChainError::DeadInput(outp) | MockChainError::DeadCellDep(outp) => (
+            CkbErrorCode::TransactionFailedToResolve,
+            format!("Resolve failed Dead({})", outp),
+        ),
+        MockChainError::Script {
+            error,
+            group_type,
+            cell,
+            script_hash,
+            exit_code,
+        } => {
+            let source = match (cell, group_type) {
+                (ScriptCell::Input(idx), ScriptGroupType::Lock) => format!("Inputs[{}].Lock", idx),
+                (ScriptCell::Input(idx), ScriptGroupType::Type) => format!("Inputs[{}].Type", idx),
+                (ScriptCell::Output(idx), _) => format!("Outputs[{}].Type", idx),
+            };
+            let cause = match exit_code {
+                Some(code) => format!(
+                    "ValidationFailure: see the error code {} in the page \
+                     https://nervosnetwork.github.io/ckb-script-error-codes/{}.html#{}",
+                    code, script_hash, code
+                ),
+                None => error.to_string(),
+            };
+            (
+                CkbErrorCode::TransactionFailedToVerify,
+                format!(
+                    "Verification failed Script(TransactionScriptError {{ source: {}, cause: {} }})",
+                    source, cause
+                ),
+            )
+        }
+        e => (
+            CkbErrorCode::TransactionFailedToVerify,
+            format!("Verification failed {}", e),
+        ),
+    };
+    RpcError {
+        code: ErrorCode::ServerError(code.code()),
+        message: format!("{}: {}", code, message),
+        data: None,
+    }
+}
+
+fn send_transaction(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (tx, _outputs_validator): (JsonTx, Option<String>) = positional(params, 2)?;
+    let tx = packed::Transaction::from(tx).into_view();
+    let hash: H256 = chain.receive_tx(&tx).map_err(transaction_error)?.unpack();
+    to_value(hash)
+}
+
+fn dry_run_transaction(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (tx,): (JsonTx,) = positional(params, 1)?;
+    let tx = packed::Transaction::from(tx).into_view();
+    let cycles = chain
+        .verify_tx(&tx, MAX_CYCLES)
+        .map_err(transaction_error)?;
+    to_value(DryRunResult {
+        cycles: cycles.into(),
+    })
+}
+
+fn get_transaction(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (hash,): (H256,) = positional(params, 1)?;
+    let tx = chain
+        .get_transaction(&hash.pack())
+        .map(|(tx, block_hash)| TransactionWithStatus {
+            transaction: tx.into(),
+            tx_status: match block_hash {
+                Some(block_hash) => TxStatus::committed(block_hash.unpack()),
+                None => TxStatus::pending(),
+            },
+        });
+    to_value(tx)
+}
+
+fn get_live_cell(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (out_point, with_data): (JsonOutPoint, Option<bool>) = positional(params, 2)?;
+    let out_point = OutPoint::from(out_point);
+    let cell = chain
+        .get_live_cell(&out_point)
+        .map(|(output, data)| CellInfo {
+            output: output.into(),
+            data: with_data.unwrap_or(false).then(|| CellData {
+                hash: CellOutput::calc_data_hash(&data).unpack(),
+                content: JsonBytes::from_bytes(data),
+            }),
+        });
+    let status = if cell.is_some() {
+        "live"
+    } else if chain.get_cell(&out_point).is_some() {
+        "dead"
+    } else {
+        "unknown"
+    };
+    to_value(CellWithStatus {
+        cell,
+        status: status.to_owned(),
+    })
+}
+
+fn get_tip_header(chain: &mut MockChain, params: Params) -> RpcResult {
+    params.expect_no_params()?;
+    to_value(ckb_jsonrpc_types::HeaderView::from(
+        chain.tip_header().clone(),
+    ))
+}
+
+fn get_tip_block_number(chain: &mut MockChain, params: Params) -> RpcResult {
+    params.expect_no_params()?;
+    to_value(Uint64::from(chain.tip_header().number()))
+}
+
+fn get_header(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (hash,): (H256,) = positional(params, 1)?;
+    let header = chain
+        .get_header(&hash.pack())
+        .map(ckb_jsonrpc_types::HeaderView::from);
+    to_value(header)
+}
+
+fn get_block_hash(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (number,): (Uint64,) = positional(params, 1)?;
+    let hash: Option<H256> = chain
+        .get_block_hash(number.into())
+        .map(|hash| hash.unpack());
+    to_value(hash)
+}
+
+fn get_block(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (hash,): (H256,) = positional(params, 1)?;
+    let block = chain
+        .get_block(&hash.pack())
+        .map(ckb_jsonrpc_types::BlockView::from);
+    to_value(block)
+}
+
+fn get_block_by_number(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (number,): (Uint64,) = positional(params, 1)?;
+    let block = chain
+        .get_block_hash(number.into())
+        .and_then(|hash| chain.get_block(&hash))
+        .map(ckb_jsonrpc_types::BlockView::from);
+    to_value(block)
+}
+
+// The block assembler arguments are accepted, the mock chain's blocks have no cellbase
+fn generate_block(chain: &mut MockChain, params: Params) -> RpcResult {
+    let _: (Option<ckb_jsonrpc_types::Script>, Option<JsonBytes>) = positional(params, 2)?;
+    let hash: H256 = chain.commit_block().hash().unpack();
+    to_value(hash)
+}
+
+fn get_cells(chain: &mut MockChain, params: Params) -> RpcResult {
+    let (search_key, order, limit, after_cursor): (SearchKey, Order, Uint32, Option<JsonBytes>) =
+        positional(params, 4)?;
+    let mut out_points = chain.search_cells(&search_key);
+    if order == Order::Desc {
+        out_points.reverse();
+    }
+    // The cursor is the serialized out point of the last cell returned
+    let start = match after_cursor {
+        Some(cursor) => {
+            let cursor = cursor.into_bytes();
+            out_points
+                .iter()
+                .position(|outp| outp.as_slice() == cursor.as_ref())
+                .map_or(out_points.len(), |idx| idx + 1)
+        }
+        None => 0,
+    };
+    let objects = out_points
+        .into_iter()
+        .skip(start)
+        .take(u32::from(limit) as usize)
+        .map(|outp| {
+            let (output, data) = chain.get_cell(&outp).expect("indexed cell");
+            let (block_number, tx_index) = chain
+                .outpoint_txs
+                .get(&outp)
+                .map(|info| (info.block_number, info.index as u32))
+                .unwrap_or_default();
+            IndexerCell {
+                output: output.into(),
+                output_data: JsonBytes::from_bytes(data),
+                out_point: outp.into(),
+                block_number: block_number.into(),
+                tx_index: tx_index.into(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let last_cursor = objects
+        .last()
+        .map(|cell| JsonBytes::from_vec(OutPoint::from(cell.out_point.clone()).as_slice().to_vec()))
+        .unwrap_or_default();
+    to_value(Pagination {
+        objects,
+        last_cursor,
+    })
+}
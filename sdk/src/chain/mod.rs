@@ -7,7 +7,9 @@ use ckb_types::{
 use ckb_verification::TransactionError;
 use std::prelude::v1::*;
 pub type CellOutputWithData = (CellOutput, Bytes);
+mod confirmation;
 mod mock_chain;
+pub use confirmation::*;
 pub use mock_chain::*;
 
 // Most of this is taken from https://github.com/nervosnetwork/ckb-tool.
@@ -0,0 +1,184 @@
+use super::generator::{
+    CellMetaTransaction, CellQuery, CellQueryAttribute, Generator, GeneratorError, ProviderError,
+    QueryStatement, TransactionProvider,
+};
+use super::type_id::{fill_type_id_args, type_id_script, TYPE_ID_ARGS_LEN};
+use super::ContractSource;
+use crate::chain::{ConfirmationError, TxStatusProvider};
+use crate::ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, CapacityError, TransactionBuilder},
+    packed::{self, CellInput, CellOutput},
+    prelude::*,
+    H256,
+};
+use ckb_jsonrpc_types::{CellDep, DepType, JsonBytes, OutPoint, Script, ScriptHashType};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DeployError {
+    #[error("Contract has no code to deploy")]
+    NoCode,
+    #[error("Contract has no lock for its code cell")]
+    NoLock,
+    #[error("Contract was deployed without a Type ID and cannot be upgraded")]
+    NotUpgradeable,
+    #[error("No live code cell found for the deployment")]
+    CodeCellNotFound,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Capacity(#[from] CapacityError),
+    #[error(transparent)]
+    Generator(#[from] GeneratorError),
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+    #[error(transparent)]
+    Confirmation(#[from] ConfirmationError),
+}
+
+pub type DeployResult<T> = std::result::Result<T, DeployError>;
+
+/// Where a contract's code lives on chain and how scripts reference it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Deployment {
+    pub out_point: OutPoint,
+    /// Data hash of the code, or the hash of the code cell's Type ID script
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+    pub dep_type: DepType,
+    /// Type ID script of the code cell, set for upgradeable deployments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_script: Option<Script>,
+}
+
+impl Deployment {
+    /// Creates a cell holding `code` and waits until it is committed.
+    ///
+    /// The generator's query service and change lock fund the cell and its signers sign the
+    /// transaction. With `type_id` the cell gets a Type ID type script, so scripts
+    /// referencing the code by its type hash keep working after the code is upgraded.
+    pub fn deploy<P>(
+        code: Bytes,
+        lock: packed::Script,
+        type_id: bool,
+        generator: &Generator,
+        provider: &P,
+    ) -> DeployResult<Self>
+    where
+        P: TransactionProvider + TxStatusProvider,
+    {
+        // Args are filled in once the inputs are known, the placeholder has the same size
+        let type_ = type_id.then(|| type_id_script(Bytes::from(vec![0u8; TYPE_ID_ARGS_LEN])));
+        let tx = TransactionBuilder::default()
+            .output(code_cell(lock, type_, &code)?)
+            .output_data(code.pack())
+            .build();
+        let tx = Self::send(tx.into(), generator, provider)?;
+        Ok(Self::from_code_cell(&tx, &code))
+    }
+
+    /// Replaces the code of a deployment made with a Type ID and waits until it is committed.
+    ///
+    /// The live code cell is looked up through the generator's query service and consumed.
+    /// The new code cell keeps its lock and Type ID, so the code hash of the deployment and
+    /// every script referencing it stay the same while the out point changes.
+    pub fn upgrade<P>(&self, code: Bytes, generator: &Generator, provider: &P) -> DeployResult<Self>
+    where
+        P: TransactionProvider + TxStatusProvider,
+    {
+        let type_script = self
+            .type_script
+            .clone()
+            .ok_or(DeployError::NotUpgradeable)?;
+        let cell = generator
+            .query(CellQuery {
+                _query: QueryStatement::Single(CellQueryAttribute::TypeScript(type_script.clone())),
+                _limit: 1,
+            })?
+            .into_iter()
+            .next()
+            .ok_or(DeployError::CodeCellNotFound)?;
+
+        let output = code_cell(cell.cell_output.lock(), Some(type_script.into()), &code)?;
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new(cell.out_point.clone(), 0))
+            .output(output)
+            .output_data(code.pack())
+            .build();
+        let tx = CellMetaTransaction::from(tx).with_inputs(vec![cell]);
+        let tx = Self::send(tx, generator, provider)?;
+        Ok(Self::from_code_cell(&tx, &code))
+    }
+
+    // Balance, sign and send a transaction creating the code cell as its first output
+    fn send<P>(
+        tx: CellMetaTransaction,
+        generator: &Generator,
+        provider: &P,
+    ) -> DeployResult<CellMetaTransaction>
+    where
+        P: TransactionProvider + TxStatusProvider,
+    {
+        let tx = fill_type_id_args(generator.balance(tx)?);
+        let tx = generator.sign(tx)?;
+        provider.send_tx(tx.tx.clone().into())?;
+        let tx_hash: H256 = tx.tx.hash().unpack();
+        provider.wait_for_commit(&tx_hash)?;
+        Ok(tx)
+    }
+
+    fn from_code_cell(tx: &CellMetaTransaction, code: &Bytes) -> Self {
+        let type_script = tx.tx.output(0).and_then(|output| output.type_().to_opt());
+        let (code_hash, hash_type) = match &type_script {
+            Some(type_) => (type_.calc_script_hash().unpack(), ScriptHashType::Type),
+            None => (
+                CellOutput::calc_data_hash(code).unpack(),
+                ScriptHashType::Data1,
+            ),
+        };
+        Self {
+            out_point: packed::OutPoint::new(tx.tx.hash(), 0).into(),
+            code_hash,
+            hash_type,
+            dep_type: DepType::Code,
+            type_script: type_script.map(Into::into),
+        }
+    }
+
+    /// Source of a contract whose code is this deployment.
+    pub fn source(&self) -> ContractSource {
+        ContractSource::Chain(self.out_point.clone())
+    }
+
+    pub fn cell_dep(&self) -> CellDep {
+        CellDep {
+            out_point: self.out_point.clone(),
+            dep_type: self.dep_type.clone(),
+        }
+    }
+
+    /// A script running the deployed code with `args`.
+    pub fn script(&self, args: impl Into<JsonBytes>) -> Script {
+        Script {
+            code_hash: self.code_hash.clone(),
+            hash_type: self.hash_type.clone(),
+            args: args.into(),
+        }
+    }
+}
+
+// A cell holding `code` with exactly the capacity it occupies
+fn code_cell(
+    lock: packed::Script,
+    type_: Option<packed::Script>,
+    code: &Bytes,
+) -> DeployResult<CellOutput> {
+    let output = CellOutput::new_builder()
+        .lock(lock)
+        .type_(type_.pack())
+        .build();
+    let capacity = output.occupied_capacity(Capacity::bytes(code.len())?)?;
+    Ok(output.as_builder().capacity(capacity.pack()).build())
+}
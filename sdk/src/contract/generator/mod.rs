@@ -3,15 +3,24 @@ use ckb_types::packed::CellDepBuilder;
 use std::prelude::v1::*;
 
 use crate::ckb_types::{
-    core::{cell::CellMeta, TransactionBuilder, TransactionView},
-    packed::CellInputBuilder,
+    bytes::Bytes,
+    core::{
+        cell::CellMeta, Capacity as CoreCapacity, CapacityError, Cycle, ScriptHashType,
+        TransactionBuilder, TransactionView,
+    },
+    packed::{self, CellInputBuilder, CellOutput, WitnessArgs},
     prelude::*,
 };
 
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use crate::chain::CellOutputWithData;
+use thiserror::Error;
+
+use super::type_id::{fill_type_id_args, is_type_id};
+use crate::account::{ScriptGroup, ScriptGroupType, ScriptSignError, ScriptSigner};
+use crate::chain::{CellOutputWithData, MockChainError};
+use crate::rpc::{CkbRpcError, RpcError};
 
 #[derive(Clone, Debug)]
 pub struct CellMetaTransaction {
@@ -109,11 +118,30 @@ impl CellMetaTransaction {
     }
 }
 
+#[derive(Error, Debug)]
+pub enum ProviderError {
+    #[error(transparent)]
+    MockChain(#[from] MockChainError),
+    #[error("Transaction rejected by node (code {}): {0}", .0.code())]
+    Rejected(CkbRpcError),
+    #[error("No node URL configured")]
+    NoNodeUrl,
+    #[error(transparent)]
+    Rpc(RpcError),
+    #[error("Query not supported by the provider: {0:?}")]
+    UnsupportedQuery(Vec<CellQueryAttribute>),
+    #[error("Cell {0} is not live")]
+    DeadCell(packed::OutPoint),
+}
+
+pub type ProviderResult<T> = std::result::Result<T, ProviderError>;
+
 // Note: Uses ckb_jsonrpc_types
 pub trait TransactionProvider {
-    fn send_tx(&self, tx: JsonTransaction) -> Option<Byte32>;
+    fn send_tx(&self, tx: JsonTransaction) -> ProviderResult<Byte32>;
 
-    fn verify_tx(&self, tx: JsonTransaction) -> bool;
+    /// Verifies the transaction, returning the cycles consumed by its scripts.
+    fn verify_tx(&self, tx: JsonTransaction) -> ProviderResult<Cycle>;
 }
 
 // Note: Uses ckb_types::core::TransactionView; not ckb_jsonrpc_types::TransactionView
@@ -124,6 +152,17 @@ pub trait GeneratorMiddleware {
         query_register: Arc<Mutex<Vec<CellQuery>>>,
     ) -> CellMetaTransaction;
 
+    // Fallible version of `pipe` which the generator runs, with the generator's query service
+    // to resolve cells through. Defaults to `pipe`.
+    fn try_pipe(
+        &self,
+        tx: CellMetaTransaction,
+        query_register: Arc<Mutex<Vec<CellQuery>>>,
+        _query_service: Option<&dyn QueryProvider>,
+    ) -> GeneratorResult<CellMetaTransaction> {
+        Ok(self.pipe(tx, query_register))
+    }
+
     fn update_query_register(
         &self,
         tx: CellMetaTransaction,
@@ -137,6 +176,7 @@ pub enum CellQueryAttribute {
     LockHash(Byte32),
     LockScript(Script),
     TypeScript(Script),
+    TypeHash(Byte32),
     MinCapacity(Capacity),
     MaxCapacity(Capacity),
     DataHash(Byte32),
@@ -156,9 +196,43 @@ pub struct CellQuery {
     pub _limit: u64,
 }
 
+// Default fee rate in shannons per KB, matching the minimum fee rate accepted by CKB nodes
+pub const DEFAULT_FEE_RATE: u64 = 1000;
+
+// Size of a secp256k1 recoverable signature, reserved in the lock field of each lock group's witness
+pub const SIGNATURE_PLACEHOLDER_LEN: usize = 65;
+
+#[derive(Error, Debug)]
+pub enum GeneratorError {
+    #[error(
+        "Insufficient capacity to balance transaction: required {required}, available {available}"
+    )]
+    InsufficientCapacity { required: u64, available: u64 },
+    #[error("Capacity overflow while balancing transaction: {0}")]
+    Capacity(#[from] CapacityError),
+    #[error("No query service set on generator")]
+    NoQueryService,
+    #[error("No change lock set on generator")]
+    NoChangeLock,
+    #[error(transparent)]
+    Sign(#[from] ScriptSignError),
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+    #[error("No code cell found for script {0}")]
+    UnresolvedCellDep(packed::Script),
+    #[error("Contract rule returned a field outside of its scope")]
+    RuleScopeMismatch,
+    #[error("Fee overflow for a transaction of {size} bytes at {fee_rate} shannons per KB")]
+    FeeOverflow { size: usize, fee_rate: u64 },
+}
+
+pub type GeneratorResult<T> = std::result::Result<T, GeneratorError>;
+
 pub trait QueryProvider {
-    fn query(&self, query: CellQuery) -> Option<Vec<OutPoint>>;
-    fn query_cell_meta(&self, query: CellQuery) -> Option<Vec<CellMeta>>;
+    fn query(&self, query: CellQuery) -> ProviderResult<Vec<OutPoint>>;
+    fn query_cell_meta(&self, query: CellQuery) -> ProviderResult<Vec<CellMeta>>;
+    // The live cell at `out_point`, or `ProviderError::DeadCell` if there is none
+    fn resolve_cell(&self, out_point: packed::OutPoint) -> ProviderResult<CellMeta>;
 }
 
 #[derive(Default)]
@@ -168,6 +242,10 @@ pub struct Generator<'a, 'b> {
     query_service: Option<&'b dyn QueryProvider>,
     tx: Option<CellMetaTransaction>,
     query_queue: Arc<Mutex<Vec<CellQuery>>>,
+    fee_rate: u64,
+    change_lock: Option<packed::Script>,
+    max_change_as_fee: CoreCapacity,
+    signers: Vec<&'b dyn ScriptSigner>,
 }
 
 impl<'a, 'b> Generator<'a, 'b> {
@@ -178,9 +256,52 @@ impl<'a, 'b> Generator<'a, 'b> {
             query_service: None,
             tx: Some(TransactionBuilder::default().build().into()),
             query_queue: Arc::new(Mutex::new(vec![])),
+            fee_rate: DEFAULT_FEE_RATE,
+            change_lock: None,
+            max_change_as_fee: CoreCapacity::zero(),
+            signers: vec![],
         }
     }
 
+    // Register a signer. When at least one signer is set, the generated transaction is signed:
+    // each lock group whose args match a signer is signed by it.
+    pub fn signer(mut self, signer: &'b dyn ScriptSigner) -> Self {
+        self.signers.push(signer);
+        self
+    }
+
+    // Add a cell dep to the transaction before the pipeline runs, e.g. data cells loaded by a lock
+    pub fn cell_dep(mut self, cell_dep: packed::CellDep) -> Self {
+        let tx = self
+            .tx
+            .take()
+            .unwrap_or_else(|| TransactionBuilder::default().build().into());
+        let inner_tx = tx.as_advanced_builder().cell_dep(cell_dep).build();
+        self.tx = Some(tx.tx(inner_tx));
+        self
+    }
+
+    // Fee rate in shannons per KB used when balancing the transaction
+    pub fn fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    // Lock of the change output. Setting it enables the balancing stage,
+    // which also pulls additional capacity-only cells locked by it when inputs fall short.
+    pub fn change_lock(mut self, lock: impl Into<packed::Script>) -> Self {
+        self.change_lock = Some(lock.into());
+        self
+    }
+
+    // Largest remainder paid as fee when balancing runs out of cells and the remainder is too
+    // small for a change cell, which occupies at least 61 CKB. Defaults to zero, so such
+    // transactions fail with `GeneratorError::InsufficientCapacity` instead.
+    pub fn max_change_as_fee(mut self, max: CoreCapacity) -> Self {
+        self.max_change_as_fee = max;
+        self
+    }
+
     pub fn pipeline(mut self, pipes: Vec<&'a dyn GeneratorMiddleware>) -> Self {
         self.middleware = pipes;
         self
@@ -196,46 +317,37 @@ impl<'a, 'b> Generator<'a, 'b> {
         self
     }
 
-    pub fn query(&self, query: CellQuery) -> Option<Vec<CellMeta>> {
-        let res = self.query_service.unwrap().query_cell_meta(query.clone());
+    pub fn query(&self, query: CellQuery) -> GeneratorResult<Vec<CellMeta>> {
+        let query_service = self.query_service.ok_or(GeneratorError::NoQueryService)?;
+        let res = query_service.query_cell_meta(query.clone());
         println!(
             "Res in generator.query for cell_query {:?} is {:?}",
             query, res
         );
-        res
+        Ok(res?)
     }
 
+    // Run the pipeline, panicking if the transaction cannot be balanced or signed.
+    // See `try_generate` for the fallible version.
     pub fn generate(&self) -> CellMetaTransaction {
-        self.pipe(self.tx.as_ref().unwrap().clone(), self.query_queue.clone())
+        self.try_generate()
+            .unwrap_or_else(|e| panic!("Unable to generate transaction: {}", e))
     }
 
-    pub fn resolve_queries(&self, query_register: Arc<Mutex<Vec<CellQuery>>>) -> Vec<CellMeta> {
-        query_register
-            .lock()
-            .unwrap()
-            .iter()
-            .flat_map(|query| self.query(query.to_owned()).unwrap())
-            .collect::<Vec<_>>()
+    // Run the pipeline. With a change lock set, the transaction is balanced with a change
+    // output; a remainder too small for one is only paid as fee up to `max_change_as_fee`.
+    pub fn try_generate(&self) -> GeneratorResult<CellMetaTransaction> {
+        self.try_pipe(self.tx.as_ref().unwrap().clone(), self.query_queue.clone())
     }
-}
 
-impl GeneratorMiddleware for Generator<'_, '_> {
-    fn update_query_register(
-        &self,
-        tx: CellMetaTransaction,
-        query_register: Arc<Mutex<Vec<CellQuery>>>,
-    ) {
-        self.middleware
-            .iter()
-            .for_each(|m| m.update_query_register(tx.clone(), query_register.clone()));
-    }
-    fn pipe(
+    // Resolve the queued inputs, run every middleware, then add cell deps, balance and sign
+    pub fn try_pipe(
         &self,
         tx: CellMetaTransaction,
         query_register: Arc<Mutex<Vec<CellQuery>>>,
-    ) -> CellMetaTransaction {
+    ) -> GeneratorResult<CellMetaTransaction> {
         self.update_query_register(tx.clone(), query_register.clone());
-        let inputs = self.resolve_queries(query_register.clone());
+        let inputs = self.resolve_queries(query_register.clone())?;
         println!("RESOLVED INPUTS IN GENERATOR PIPE: {:?}", inputs);
         let inner_tx = tx
             .as_advanced_builder()
@@ -251,47 +363,299 @@ impl GeneratorMiddleware for Generator<'_, '_> {
             )
             .build();
         let tx = tx.tx(inner_tx).with_inputs(inputs);
-        let tx = self.middleware.iter().fold(tx, |tx, middleware| {
-            middleware.pipe(tx, query_register.clone())
-        });
+        let tx = self.middleware.iter().try_fold(tx, |tx, middleware| {
+            middleware.try_pipe(tx, query_register.clone(), self.query_service)
+        })?;
+        let tx = self.resolve_cell_deps(tx)?;
+        let tx = if self.change_lock.is_some() {
+            // Balancing may add the first input, which Type ID args are computed from
+            fill_type_id_args(self.balance(tx)?)
+        } else {
+            tx
+        };
+        let tx = if !self.signers.is_empty() {
+            self.sign(tx)?
+        } else {
+            tx
+        };
+
+        println!(
+            "FINAL TX GENERATED: {:#?}",
+            ckb_jsonrpc_types::TransactionView::from(tx.clone().tx)
+        );
+        Ok(tx)
+    }
+
+    // Add cell deps for the lock and type scripts of every input not already covered by the transaction.
+    // Fails if the query service finds no code cell for one of the scripts.
+    // TO DO: Will have to accommodate some cells being deptype of depgroup
+    pub fn resolve_cell_deps(
+        &self,
+        tx: CellMetaTransaction,
+    ) -> GeneratorResult<CellMetaTransaction> {
+        // Scripts sharing their code are looked up once
         #[allow(clippy::mutable_key_type)]
-        let mut queries = HashSet::new();
+        let mut queries = HashMap::new();
         tx.inputs.iter().for_each(|cell| {
-            if let Some(script) = cell.cell_output.type_().to_opt() {
-                let query = CellQuery {
-                    _query: QueryStatement::Single(CellQueryAttribute::DataHash(
-                        script.code_hash().into(),
-                    )),
-                    _limit: 1,
-                };
-                queries.insert(query);
+            // Type IDs are verified by the node itself and have no code cell
+            if let Some(script) = cell.cell_output.type_().to_opt().filter(|s| !is_type_id(s)) {
+                queries.entry(code_cell_query(&script)).or_insert(script);
             }
-            queries.insert(CellQuery {
-                _query: QueryStatement::Single(CellQueryAttribute::DataHash(
-                    cell.cell_output.lock().code_hash().into(),
-                )),
-                _limit: 1,
-            });
+            let lock = cell.cell_output.lock();
+            queries.entry(code_cell_query(&lock)).or_insert(lock);
         });
-        let deps = queries.into_iter().flat_map(|q| {
-            self.query(q).unwrap().into_iter().map(|cell_dep_meta| {
-                CellDepBuilder::default()
-                    .out_point(cell_dep_meta.out_point)
-                    .build()
+        let mut deps = tx.cell_deps_iter().collect::<Vec<_>>();
+        for (q, script) in queries {
+            let code_cells = self.query(q)?;
+            if code_cells.is_empty() {
+                return Err(GeneratorError::UnresolvedCellDep(script));
+            }
+            code_cells
+                .into_iter()
+                .map(|cell_dep_meta| {
+                    CellDepBuilder::default()
+                        .out_point(cell_dep_meta.out_point)
+                        .build()
+                })
+                .for_each(|dep| {
+                    if !deps.contains(&dep) {
+                        deps.push(dep);
+                    }
+                })
+        }
+
+        let inner_tx = tx.as_advanced_builder().set_cell_deps(deps).build();
+        Ok(tx.tx(inner_tx))
+    }
+
+    // Ensure inputs cover outputs plus fee, adding a change output locked by the change lock.
+    // The fee is estimated from the serialized size of the transaction including the change
+    // output and one signature placeholder per lock group. When no candidate is left and the
+    // remainder is too small for a change cell, the transaction is kept without one and the
+    // remainder is paid as fee, up to `max_change_as_fee`.
+    pub fn balance(&self, tx: CellMetaTransaction) -> GeneratorResult<CellMetaTransaction> {
+        let change_lock = self
+            .change_lock
+            .clone()
+            .ok_or(GeneratorError::NoChangeLock)?;
+        let query_service = self.query_service.ok_or(GeneratorError::NoQueryService)?;
+
+        let change_output = CellOutput::new_builder().lock(change_lock.clone()).build();
+        let change_occupied = change_output.occupied_capacity(CoreCapacity::zero())?;
+
+        // Capacity-only cells owned by the change lock which can fund the transaction
+        let used = tx
+            .inputs
+            .iter()
+            .map(|cell| cell.out_point.clone())
+            .collect::<Vec<_>>();
+        let mut candidates = query_service
+            .query_cell_meta(CellQuery {
+                _query: QueryStatement::Single(CellQueryAttribute::LockScript(change_lock.into())),
+                _limit: u64::MAX,
+            })?
+            .into_iter()
+            .filter(|cell| {
+                cell.cell_output.type_().is_none()
+                    && cell.data_bytes == 0
+                    && !used.contains(&cell.out_point)
             })
-        });
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let mut tx = tx;
+        loop {
+            let candidate_tx = self.resolve_cell_deps(tx.clone())?;
+            let inner_tx = candidate_tx
+                .as_advanced_builder()
+                .output(change_output.clone())
+                .output_data(Bytes::new().pack())
+                .set_witnesses(witness_placeholders(&candidate_tx))
+                .build();
+            let fee = self.fee(inner_tx.data().serialized_size_in_block())?;
+            let inputs_capacity = tx
+                .inputs
+                .iter()
+                .try_fold(CoreCapacity::zero(), |acc, cell| {
+                    acc.safe_add(cell.capacity())
+                })?;
+            let required = tx.outputs_capacity()?.safe_add(fee)?;
+
+            if inputs_capacity >= required {
+                let change = inputs_capacity.safe_sub(required)?;
+                if change >= change_occupied {
+                    let outputs = inner_tx
+                        .outputs()
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, output)| {
+                            if idx == inner_tx.outputs().len() - 1 {
+                                output.as_builder().capacity(change.pack()).build()
+                            } else {
+                                output
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let inner_tx = inner_tx.as_advanced_builder().set_outputs(outputs).build();
+                    return Ok(candidate_tx.tx(inner_tx));
+                }
+            }
 
-        let inner_tx = tx
+            match candidates.next() {
+                Some(cell) => {
+                    let input = CellInputBuilder::default()
+                        .previous_output(cell.out_point.clone())
+                        .build();
+                    let inner_tx = tx.as_advanced_builder().input(input).build();
+                    let mut inputs = tx.inputs.clone();
+                    inputs.push(cell);
+                    tx = tx.tx(inner_tx).with_inputs(inputs);
+                }
+                None => {
+                    // What is left cannot pay for a change cell, so it may go to the fee instead
+                    let inner_tx = candidate_tx
+                        .as_advanced_builder()
+                        .set_witnesses(witness_placeholders(&candidate_tx))
+                        .build();
+                    let fee = self.fee(inner_tx.data().serialized_size_in_block())?;
+                    let without_change = tx.outputs_capacity()?.safe_add(fee)?;
+                    if inputs_capacity >= without_change
+                        && inputs_capacity.safe_sub(without_change)? <= self.max_change_as_fee
+                    {
+                        return Ok(candidate_tx.tx(inner_tx));
+                    }
+                    return Err(GeneratorError::InsufficientCapacity {
+                        required: required.safe_add(change_occupied)?.as_u64(),
+                        available: inputs_capacity.as_u64(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Fill witness placeholders and sign every lock group matched by a registered signer.
+    // Groups without a matching signer (e.g. always success locks) are left untouched.
+    pub fn sign(&self, tx: CellMetaTransaction) -> GeneratorResult<CellMetaTransaction> {
+        let mut signed_tx = tx
             .as_advanced_builder()
-            .cell_deps(tx.cell_deps().as_builder().extend(deps).build())
+            .set_witnesses(witness_placeholders(&tx))
             .build();
-        // TO DO: Resolve cell deps of inputs
-        //         Will have to accommodate some cells being deptype of depgroup
+        for group in lock_groups(&tx) {
+            let args = group.script.args().raw_data();
+            if let Some(signer) = self
+                .signers
+                .iter()
+                .find(|signer| signer.match_args(args.as_ref()))
+            {
+                signed_tx = signer.sign_tx(&signed_tx, &group)?;
+            }
+        }
+        Ok(tx.tx(signed_tx))
+    }
 
-        println!(
-            "FINAL TX GENERATED: {:#?}",
-            ckb_jsonrpc_types::TransactionView::from(tx.clone().tx)
-        );
-        tx.tx(inner_tx)
+    // Fee in shannons for a transaction of `size` bytes, rounded up
+    fn fee(&self, size: usize) -> GeneratorResult<CoreCapacity> {
+        (size as u64)
+            .checked_mul(self.fee_rate)
+            .and_then(|fee| fee.checked_add(999))
+            .map(|fee| CoreCapacity::shannons(fee / 1000))
+            .ok_or(GeneratorError::FeeOverflow {
+                size,
+                fee_rate: self.fee_rate,
+            })
+    }
+
+    pub fn resolve_queries(
+        &self,
+        query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) -> GeneratorResult<Vec<CellMeta>> {
+        let mut inputs = vec![];
+        for query in query_register.lock().unwrap().iter() {
+            inputs.extend(self.query(query.to_owned())?);
+        }
+        Ok(inputs)
+    }
+}
+
+impl GeneratorMiddleware for Generator<'_, '_> {
+    fn update_query_register(
+        &self,
+        tx: CellMetaTransaction,
+        query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) {
+        self.middleware
+            .iter()
+            .for_each(|m| m.update_query_register(tx.clone(), query_register.clone()));
+    }
+    fn pipe(
+        &self,
+        tx: CellMetaTransaction,
+        query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) -> CellMetaTransaction {
+        Generator::try_pipe(self, tx, query_register)
+            .unwrap_or_else(|e| panic!("Unable to generate transaction: {}", e))
+    }
+
+    // A nested generator resolves cells through its own query service
+    fn try_pipe(
+        &self,
+        tx: CellMetaTransaction,
+        query_register: Arc<Mutex<Vec<CellQuery>>>,
+        _query_service: Option<&dyn QueryProvider>,
+    ) -> GeneratorResult<CellMetaTransaction> {
+        Generator::try_pipe(self, tx, query_register)
+    }
+}
+
+// Query for the cell holding the code of `script`, by data hash or by type hash
+fn code_cell_query(script: &packed::Script) -> CellQuery {
+    let attribute = if script.hash_type() == ScriptHashType::Type.into() {
+        CellQueryAttribute::TypeHash(script.code_hash().into())
+    } else {
+        CellQueryAttribute::DataHash(script.code_hash().into())
+    };
+    CellQuery {
+        _query: QueryStatement::Single(attribute),
+        _limit: 1,
     }
 }
+
+// Group inputs by lock script, in order of first appearance
+pub fn lock_groups(tx: &CellMetaTransaction) -> Vec<ScriptGroup> {
+    let mut groups: Vec<ScriptGroup> = vec![];
+    tx.inputs.iter().enumerate().for_each(|(idx, cell)| {
+        let lock = cell.cell_output.lock();
+        match groups.iter_mut().find(|group| group.script == lock) {
+            Some(group) => group.input_indices.push(idx),
+            None => groups.push(ScriptGroup {
+                script: lock,
+                group_type: ScriptGroupType::Lock,
+                input_indices: vec![idx],
+                output_indices: vec![],
+            }),
+        }
+    });
+    groups
+}
+
+// Witnesses with a zero-filled signature placeholder in the lock field of the first witness of
+// each lock group. Witnesses already present in the transaction are kept.
+pub fn witness_placeholders(tx: &CellMetaTransaction) -> Vec<packed::Bytes> {
+    let mut witnesses = tx.witnesses().into_iter().collect::<Vec<_>>();
+    while witnesses.len() < tx.inputs.len() {
+        witnesses.push(packed::Bytes::default());
+    }
+    lock_groups(tx).into_iter().for_each(|group| {
+        let idx = group.input_indices[0];
+        let witness = WitnessArgs::from_slice(&witnesses[idx].raw_data()).unwrap_or_default();
+        if witness.lock().is_none() {
+            let placeholder = Bytes::from(vec![0u8; SIGNATURE_PLACEHOLDER_LEN]).pack();
+            let lock = packed::BytesOpt::new_builder()
+                .set(Some(placeholder))
+                .build();
+            let witness = witness.as_builder().lock(lock).build();
+            witnesses[idx] = witness.as_bytes().pack();
+        }
+    });
+    witnesses
+}
@@ -1,6 +1,8 @@
 use std::prelude::v1::*;
 pub mod builtins;
+pub mod deploy;
 pub mod schema;
+pub mod type_id;
 use self::generator::CellMetaTransaction;
 use self::schema::*;
 
@@ -9,17 +11,21 @@ use crate::ckb_types::{bytes::Bytes, packed, prelude::*};
 
 pub mod generator;
 
-use self::generator::{CellQuery, GeneratorMiddleware};
+use self::deploy::{DeployError, DeployResult, Deployment};
+use self::generator::{
+    CellQuery, Generator, GeneratorError, GeneratorMiddleware, GeneratorResult, QueryProvider,
+    TransactionProvider,
+};
 
-use crate::chain::CellOutputWithData;
+use crate::chain::{CellOutputWithData, TxStatusProvider};
 
-use crate::ckb_types::core::TransactionView;
+use crate::ckb_types::core::{Capacity, TransactionView};
 
 use crate::ckb_types::{core::TransactionBuilder, H256};
 
 use ckb_hash::blake2b_256;
 
-use ckb_jsonrpc_types::{CellDep, DepType, JsonBytes, OutPoint, Script};
+use ckb_jsonrpc_types::{CellDep, DepType, JsonBytes, OutPoint, Script, ScriptHashType};
 use ckb_types::core::cell::CellMeta;
 
 use std::fs;
@@ -83,6 +89,7 @@ pub struct RuleContext {
     inner: CellMetaTransaction,
     pub idx: usize,
     pub curr_field: TransactionField,
+    pub script_hash: Option<packed::Byte32>,
 }
 
 impl RuleContext {
@@ -91,6 +98,7 @@ impl RuleContext {
             inner: tx.into(),
             idx: 0,
             curr_field: TransactionField::Outputs,
+            script_hash: None,
         }
     }
     pub fn tx(mut self, tx: impl Into<CellMetaTransaction>) -> Self {
@@ -109,31 +117,68 @@ impl RuleContext {
         self.curr_field = field;
     }
 
+    // Hash of the script (lock or type) through which the current cell references the contract
+    pub fn script_hash(&mut self, hash: packed::Byte32) {
+        self.script_hash = Some(hash);
+    }
+
+    // The cell currently being processed, read from either the outputs or the resolved inputs
+    pub fn current_cell(&self) -> Option<CellOutputWithData> {
+        match self.curr_field {
+            TransactionField::Outputs => self.inner.output_with_data(self.idx),
+            TransactionField::ResolvedInputs => self.inner.inputs.get(self.idx).map(|cell| {
+                let data = cell.mem_cell_data.clone().unwrap_or_default();
+                (cell.cell_output.clone(), data)
+            }),
+            _ => None,
+        }
+    }
+
+    // Returns whether the contract script is the cell's type script (true) or lock script (false)
+    fn is_type_script(&self, output: &CellOutput) -> bool {
+        match (&self.script_hash, output.type_().to_opt()) {
+            (Some(hash), Some(type_)) => &type_.calc_script_hash() == hash,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+
     pub fn load<A, D>(&self, scope: impl Into<RuleScope>) -> ContractCellField<A, D>
     where
         D: JsonByteConversion + MolConversion + BytesConversion + Clone + Default,
-        A: JsonByteConversion + MolConversion + BytesConversion + Clone,
+        A: JsonByteConversion + MolConversion + BytesConversion + Clone + Default,
     {
         match scope.into() {
-            RuleScope::ContractField(field) => match field {
-                ContractField::Args => todo!(),
-                ContractField::Data => match self.curr_field {
-                    TransactionField::Outputs => {
-                        let data_reader = self.inner.outputs_data();
-                        let data_reader = data_reader.as_reader();
-                        let data = data_reader.get(self.idx);
-                        if let Some(data) = data {
-                            ContractCellField::Data(D::from_bytes(data.raw_data().to_vec().into()))
-                        } else {
-                            ContractCellField::Data(D::default())
+            RuleScope::ContractField(field) => {
+                let cell = self.current_cell();
+                match field {
+                    ContractField::Args => match cell {
+                        Some((output, _)) => {
+                            let script = if self.is_type_script(&output) {
+                                output.type_().to_opt().unwrap()
+                            } else {
+                                output.lock()
+                            };
+                            ContractCellField::Args(A::from_bytes(script.args().raw_data()))
                         }
-                    }
-                    _ => ContractCellField::Data(D::default()),
-                },
-                ContractField::LockScript => todo!(),
-                ContractField::TypeScript => todo!(),
-                ContractField::Capacity => todo!(),
-            },
+                        None => ContractCellField::Args(A::default()),
+                    },
+                    ContractField::Data => match cell {
+                        Some((_, data)) => ContractCellField::Data(D::from_bytes(data)),
+                        None => ContractCellField::Data(D::default()),
+                    },
+                    ContractField::LockScript => ContractCellField::LockScript(
+                        cell.map(|(output, _)| output.lock()).unwrap_or_default(),
+                    ),
+                    ContractField::TypeScript => ContractCellField::TypeScript(
+                        cell.and_then(|(output, _)| output.type_().to_opt()),
+                    ),
+                    ContractField::Capacity => ContractCellField::Capacity(
+                        cell.map(|(output, _)| output.capacity())
+                            .unwrap_or_default(),
+                    ),
+                }
+            }
             RuleScope::TransactionField(field) => match field {
                 TransactionField::Inputs => ContractCellField::Inputs(
                     self.inner.inputs().into_iter().collect::<Vec<CellInput>>(),
@@ -154,6 +199,154 @@ impl RuleContext {
             },
         }
     }
+
+    // Replace the output at the current index, keeping every other output untouched
+    fn update_output(&mut self, updated: CellOutputWithData) {
+        let inner_tx = self.inner.tx.clone();
+        let outputs = inner_tx
+            .outputs_with_data_iter()
+            .enumerate()
+            .map(|(i, output)| {
+                if i == self.idx {
+                    updated.clone()
+                } else {
+                    output
+                }
+            })
+            .collect::<Vec<CellOutputWithData>>();
+        let updated_inner_tx = inner_tx
+            .as_advanced_builder()
+            .set_outputs(outputs.iter().map(|o| o.0.clone()).collect::<Vec<_>>())
+            .set_outputs_data(outputs.iter().map(|o| o.1.pack()).collect::<Vec<_>>())
+            .build();
+        self.inner = self.inner.clone().tx(updated_inner_tx);
+    }
+
+    // Apply a cell-level update returned by a rule scoped to `field` to the current output.
+    // Returns the hash of the script in the place of the contract script after the update,
+    // or None if the update removed the type script the contract was referenced through.
+    fn update_cell_field<A, D>(
+        &mut self,
+        field: &ContractField,
+        updated: ContractCellField<A, D>,
+    ) -> GeneratorResult<Option<packed::Byte32>>
+    where
+        D: BytesConversion,
+        A: BytesConversion,
+    {
+        let (cell, data) = self
+            .current_cell()
+            .expect("Rule context has no current output");
+        let is_type_script = self.is_type_script(&cell);
+        let updated_output = match (field, updated) {
+            (ContractField::Args, ContractCellField::Args(a)) => {
+                let cell = if is_type_script {
+                    let type_ = cell
+                        .type_()
+                        .to_opt()
+                        .unwrap()
+                        .as_builder()
+                        .args(a.to_bytes().pack())
+                        .build();
+                    cell.as_builder().type_(Some(type_).pack()).build()
+                } else {
+                    let lock = cell.lock().as_builder().args(a.to_bytes().pack()).build();
+                    cell.as_builder().lock(lock).build()
+                };
+                (cell, data)
+            }
+            (ContractField::Data, ContractCellField::Data(d)) => (cell, d.to_bytes()),
+            (ContractField::LockScript, ContractCellField::LockScript(lock)) => {
+                (cell.as_builder().lock(lock).build(), data)
+            }
+            (ContractField::TypeScript, ContractCellField::TypeScript(type_)) => {
+                (cell.as_builder().type_(type_.pack()).build(), data)
+            }
+            (ContractField::Capacity, ContractCellField::Capacity(capacity)) => {
+                (cell.as_builder().capacity(capacity).build(), data)
+            }
+            _ => return Err(GeneratorError::RuleScopeMismatch),
+        };
+        let new_hash = if is_type_script {
+            updated_output.0.type_().to_opt()
+        } else {
+            Some(updated_output.0.lock())
+        }
+        .map(|script| script.calc_script_hash());
+        self.update_output(updated_output);
+        if let Some(hash) = new_hash.clone() {
+            self.script_hash(hash);
+        }
+        Ok(new_hash)
+    }
+
+    // Apply a transaction-level update returned by a rule scoped to `field`.
+    // Inputs added by an Inputs rule are resolved through `query_service`.
+    fn update_tx_field<A, D>(
+        &mut self,
+        field: &TransactionField,
+        updated: ContractCellField<A, D>,
+        query_service: Option<&dyn QueryProvider>,
+    ) -> GeneratorResult<()> {
+        let inner_tx = self.inner.tx.clone();
+        match (field, updated) {
+            (TransactionField::Outputs, ContractCellField::Outputs(outputs)) => {
+                let updated_inner_tx = inner_tx
+                    .as_advanced_builder()
+                    .set_outputs(outputs.iter().map(|o| o.0.clone()).collect::<Vec<_>>())
+                    .set_outputs_data(outputs.iter().map(|o| o.1.pack()).collect::<Vec<_>>())
+                    .build();
+                self.inner = self.inner.clone().tx(updated_inner_tx);
+            }
+            (TransactionField::Inputs, ContractCellField::Inputs(inputs)) => {
+                // Keep resolved inputs in step with the inputs of the transaction
+                let resolved_inputs = inputs
+                    .iter()
+                    .map(|input| {
+                        let out_point = input.previous_output();
+                        match self
+                            .inner
+                            .inputs
+                            .iter()
+                            .find(|cell| cell.out_point == out_point)
+                        {
+                            Some(cell) => Ok(cell.clone()),
+                            None => Ok(query_service
+                                .ok_or(GeneratorError::NoQueryService)?
+                                .resolve_cell(out_point)?),
+                        }
+                    })
+                    .collect::<GeneratorResult<Vec<CellMeta>>>()?;
+                let updated_inner_tx = inner_tx.as_advanced_builder().set_inputs(inputs).build();
+                self.inner = self
+                    .inner
+                    .clone()
+                    .tx(updated_inner_tx)
+                    .with_inputs(resolved_inputs);
+            }
+            (TransactionField::ResolvedInputs, ContractCellField::ResolvedInputs(cells)) => {
+                // Inputs already in the transaction keep their since
+                let inputs = cells
+                    .iter()
+                    .map(|cell| {
+                        inner_tx
+                            .inputs()
+                            .into_iter()
+                            .find(|input| input.previous_output() == cell.out_point)
+                            .unwrap_or_else(|| CellInput::new(cell.out_point.clone(), 0))
+                    })
+                    .collect::<Vec<CellInput>>();
+                let updated_inner_tx = inner_tx.as_advanced_builder().set_inputs(inputs).build();
+                self.inner = self.inner.clone().tx(updated_inner_tx).with_inputs(cells);
+            }
+            (TransactionField::Dependencies, ContractCellField::CellDeps(deps)) => {
+                let updated_inner_tx = inner_tx.as_advanced_builder().set_cell_deps(deps).build();
+                self.inner = self.inner.clone().tx(updated_inner_tx);
+            }
+            _ => return Err(GeneratorError::RuleScopeMismatch),
+        }
+        Ok(())
+    }
 }
 
 pub struct OutputRule<A, D> {
@@ -180,7 +373,7 @@ pub enum ContractCellField<A, D> {
     Args(A),
     Data(D),
     LockScript(ckb_types::packed::Script),
-    TypeScript(ckb_types::packed::Script),
+    TypeScript(Option<ckb_types::packed::Script>),
     Capacity(Uint64),
     Inputs(Vec<CellInput>),
     ResolvedInputs(Vec<CellMeta>),
@@ -195,6 +388,7 @@ pub struct Contract<A, D> {
     pub lock: Option<Script>,
     pub type_: Option<Script>,
     pub code: Option<JsonBytes>,
+    pub deployment: Option<Deployment>,
     #[allow(clippy::type_complexity)]
     pub output_rules: Vec<OutputRule<A, D>>,
     pub input_rules: Vec<Box<dyn Fn(TransactionView) -> CellQuery>>,
@@ -214,6 +408,7 @@ where
             lock: Default::default(),
             type_: Default::default(),
             code: Default::default(),
+            deployment: Default::default(),
             output_rules: Default::default(),
             input_rules: Default::default(),
             outputs_count: 1,
@@ -238,6 +433,13 @@ where
         self
     }
 
+    // Use code deployed on chain, referenced the way the deployment was made
+    pub fn deployment(mut self, deployment: Deployment) -> Self {
+        self.source = Some(deployment.source());
+        self.deployment = Some(deployment);
+        self
+    }
+
     pub fn data_hash(&self) -> Option<H256> {
         let data = self.data.to_mol();
         let data = data.as_slice();
@@ -256,16 +458,27 @@ where
     }
 
     // Returns a script structure which can be used as a lock or type script on other cells.
-    // This is an easy way to let other cells use this contract
+    // This is an easy way to let other cells use this contract.
+    // Code whose cell has a type script, such as a Type ID, is referenced by type hash so the
+    // script keeps working when the code is upgraded.
     pub fn as_script(&self) -> Option<ckb_jsonrpc_types::Script> {
-        self.code_hash().map(|data_hash| {
-            Script::from(
-                packed::ScriptBuilder::default()
-                    .args(self.args.to_bytes().pack())
-                    .code_hash(data_hash.pack())
-                    .hash_type(ckb_types::core::ScriptHashType::Data1.into())
-                    .build(),
-            )
+        let args = JsonBytes::from_bytes(self.args.to_bytes());
+        if let Some(deployment) = &self.deployment {
+            return Some(deployment.script(args));
+        }
+        let (code_hash, hash_type) = match &self.type_ {
+            Some(type_) => (
+                packed::Script::from(type_.clone())
+                    .calc_script_hash()
+                    .unpack(),
+                ScriptHashType::Type,
+            ),
+            None => (self.code_hash()?, ScriptHashType::Data1),
+        };
+        Some(Script {
+            code_hash,
+            hash_type,
+            args,
         })
     }
 
@@ -282,13 +495,42 @@ where
         };
 
         let cell_output = CellOutputBuilder::default()
-            .capacity((data.len() as u64).pack())
             .lock(self.lock.clone().unwrap_or_default().into())
             .type_(type_script.pack())
             .build();
+        let capacity = Capacity::bytes(data.len())
+            .and_then(|data_capacity| cell_output.occupied_capacity(data_capacity))
+            .expect("Code cell capacity overflow");
+        let cell_output = cell_output.as_builder().capacity(capacity.pack()).build();
         (cell_output, data)
     }
 
+    // Code of the contract, from `code` or else from its source
+    pub fn load_code(&self) -> DeployResult<Bytes> {
+        match (&self.code, &self.source) {
+            (Some(code), _) => Ok(code.clone().into_bytes()),
+            (None, Some(ContractSource::Immediate(code))) => Ok(code.clone()),
+            (None, Some(ContractSource::LocalPath(path))) => {
+                Ok(ContractSource::load_from_path(path.clone())?)
+            }
+            _ => Err(DeployError::NoCode),
+        }
+    }
+
+    // Deploy the contract code in a cell locked by the contract's lock. See [`Deployment::deploy`]
+    pub fn deploy<P>(
+        &self,
+        type_id: bool,
+        generator: &Generator,
+        provider: &P,
+    ) -> DeployResult<Deployment>
+    where
+        P: TransactionProvider + TxStatusProvider,
+    {
+        let lock = self.lock.clone().ok_or(DeployError::NoLock)?;
+        Deployment::deploy(self.load_code()?, lock.into(), type_id, generator, provider)
+    }
+
     pub fn script_hash(&self) -> Option<ckb_jsonrpc_types::Byte32> {
         let script: ckb_types::packed::Script = self.as_script().unwrap().into();
         Some(script.calc_script_hash().into())
@@ -384,6 +626,31 @@ where
     }
 }
 
+// Indices of the outputs referencing one of the given script hashes, either as lock or type script,
+// paired with the matching hash
+fn contract_outputs(
+    tx: &CellMetaTransaction,
+    script_hashes: &[packed::Byte32],
+) -> Vec<(usize, packed::Byte32)> {
+    tx.outputs()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, output)| {
+            if let Some(type_) = output.type_().to_opt() {
+                let type_hash = type_.calc_script_hash();
+                if script_hashes.contains(&type_hash) {
+                    return Some((idx, type_hash));
+                }
+            }
+            let lock_hash = output.lock().calc_script_hash();
+            if script_hashes.contains(&lock_hash) {
+                return Some((idx, lock_hash));
+            }
+            None
+        })
+        .collect()
+}
+
 impl<A, D> GeneratorMiddleware for Contract<A, D>
 where
     D: JsonByteConversion + MolConversion + BytesConversion + Clone,
@@ -401,10 +668,18 @@ where
     fn pipe(
         &self,
         tx_meta: CellMetaTransaction,
-        _query_queue: Arc<Mutex<Vec<CellQuery>>>,
+        query_queue: Arc<Mutex<Vec<CellQuery>>>,
     ) -> CellMetaTransaction {
-        type OutputWithData = (CellOutput, Bytes);
+        self.try_pipe(tx_meta, query_queue, None)
+            .unwrap_or_else(|e| panic!("Unable to apply contract rules: {}", e))
+    }
 
+    fn try_pipe(
+        &self,
+        tx_meta: CellMetaTransaction,
+        _query_queue: Arc<Mutex<Vec<CellQuery>>>,
+        query_service: Option<&dyn QueryProvider>,
+    ) -> GeneratorResult<CellMetaTransaction> {
         let tx = tx_meta.tx.clone();
         let tx_template = self.tx_template();
 
@@ -452,89 +727,43 @@ where
             )
             .build();
 
-        let outputs = tx
-            .clone()
-            .outputs()
-            .into_iter()
-            .enumerate()
-            .filter_map(|(idx, output)| {
-                let self_script_hash: ckb_types::packed::Byte32 =
-                    self.script_hash().unwrap().into();
-
-                if let Some(type_) = output.type_().to_opt() {
-                    if type_.calc_script_hash() == self_script_hash {
-                        return Some((idx, tx.output_with_data(idx).unwrap()));
-                    }
-                }
+        let contract_hash: packed::Byte32 = self.script_hash().unwrap().into();
+        let mut ctx = RuleContext::new(tx_meta.tx(tx));
 
-                if output.lock().calc_script_hash() == self_script_hash {
-                    return Some((idx, tx.output_with_data(idx).unwrap()));
-                }
+        // Outputs referencing this contract, paired with the hash of the script they reference
+        // it through. Tracked by index, so rules rewriting that script keep applying to the same
+        // outputs, and matched again after an Outputs rule rebuilds the outputs.
+        let mut matched = contract_outputs(&ctx.get_tx(), &[contract_hash.clone()]);
 
-                None
-            });
-
-        let mut ctx = RuleContext::new(tx_meta.clone());
-
-        let outputs = outputs
-            .map(|output_with_idx| {
-                ctx.idx(output_with_idx.0);
-                let processed = self.output_rules.iter().fold(output_with_idx.1, |output, rule| {
-                    let data = self.read_raw_data(output.1.clone());
-                    println!("Data before update {:?}", data.to_mol());
+        for rule in self.output_rules.iter() {
+            match &rule.scope {
+                RuleScope::TransactionField(field) => {
+                    ctx.curr_field(field.clone());
                     let updated_field = rule.exec(&ctx);
-                    match updated_field {
-                        ContractCellField::Args(_) => todo!(),
-                        ContractCellField::Data(d) => {
-                            if rule.scope != ContractField::Data.into() {
-                                panic!("Error, mismatch of output rule scope and returned field");
-                            }
-                            let updated_tx = ctx.get_tx();
-                            let inner_tx_view = updated_tx.tx.clone();
-                            let updated_outputs_data = inner_tx_view.outputs_with_data_iter()
-                                .enumerate().map(|(i, output)| {
-                                    if i == ctx.idx {
-                                       (output.0, d.to_bytes())
-                                    } else {
-                                        output
-                                    }
-                                }).collect::<Vec<CellOutputWithData>>();
-                            let updated_inner_tx = inner_tx_view.as_advanced_builder()
-                                .set_outputs(updated_outputs_data.iter().map(|o| o.0.clone()).collect::<Vec<_>>())
-                                .set_outputs_data(updated_outputs_data.iter().map(|o| o.1.pack()).collect::<Vec<_>>())
-                                .build();
-                            let updated_tx = updated_tx.tx(updated_inner_tx);
-                            ctx = ctx.clone().tx(updated_tx);
-                            (output.0, d.to_bytes())
-                        },
-                        ContractCellField::LockScript(_) => todo!(),
-                        ContractCellField::TypeScript(_) => todo!(),
-                        ContractCellField::Capacity(_) => todo!(),
-                        _ => {
-                            panic!("Error: Contract-level rule attempted transaction-level update.")
+                    ctx.update_tx_field(field, updated_field, query_service)?;
+                    if *field == TransactionField::Outputs {
+                        let mut hashes = vec![contract_hash.clone()];
+                        hashes.extend(matched.into_iter().map(|(_, hash)| hash));
+                        matched = contract_outputs(&ctx.get_tx(), &hashes);
+                    }
+                }
+                RuleScope::ContractField(field) => {
+                    ctx.curr_field(TransactionField::Outputs);
+                    let mut still_matched = vec![];
+                    for (idx, script_hash) in matched {
+                        ctx.idx(idx);
+                        ctx.script_hash(script_hash);
+                        let updated_field = rule.exec(&ctx);
+                        if let Some(hash) = ctx.update_cell_field(field, updated_field)? {
+                            still_matched.push((idx, hash));
                         }
                     }
-                });
-                println!("Output bytes of processed output: {:?}", processed.1.pack());
-                processed
-            })
-            .collect::<Vec<OutputWithData>>();
+                    matched = still_matched;
+                }
+            }
+        }
 
-        let final_inner_tx = tx
-            .as_advanced_builder()
-            .set_outputs(
-                outputs
-                    .iter()
-                    .map(|out| out.0.clone())
-                    .collect::<Vec<CellOutput>>(),
-            )
-            .set_outputs_data(
-                outputs
-                    .iter()
-                    .map(|out| out.1.clone().pack())
-                    .collect::<Vec<ckb_types::packed::Bytes>>(),
-            )
-            .build();
-        tx_meta.tx(final_inner_tx)
+        // Carries the resolved inputs updated by input rules along with the inner transaction
+        Ok(ctx.get_tx())
     }
 }
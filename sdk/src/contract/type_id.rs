@@ -0,0 +1,94 @@
+use super::generator::{CellMetaTransaction, CellQuery, GeneratorMiddleware};
+use crate::ckb_types::{
+    bytes::Bytes,
+    core::ScriptHashType,
+    packed::{self, CellInput},
+    prelude::*,
+};
+use ckb_hash::new_blake2b;
+use ckb_types::core::TYPE_ID_CODE_HASH;
+use std::sync::{Arc, Mutex};
+
+// Size of Type ID args, also used for the placeholder of a Type ID created without inputs
+pub const TYPE_ID_ARGS_LEN: usize = 32;
+
+/// Args of a Type ID script created in a transaction whose first input is `first_input`,
+/// for the cell at `output_index`.
+pub fn type_id_args(first_input: &CellInput, output_index: u64) -> Bytes {
+    let mut hasher = new_blake2b();
+    hasher.update(first_input.as_slice());
+    hasher.update(&output_index.to_le_bytes());
+    let mut args = [0u8; TYPE_ID_ARGS_LEN];
+    hasher.finalize(&mut args);
+    Bytes::from(args.to_vec())
+}
+
+pub fn type_id_script(args: Bytes) -> packed::Script {
+    packed::Script::new_builder()
+        .code_hash(TYPE_ID_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(args.pack())
+        .build()
+}
+
+pub fn is_type_id(script: &packed::Script) -> bool {
+    script.code_hash() == TYPE_ID_CODE_HASH.pack()
+        && script.hash_type() == ScriptHashType::Type.into()
+}
+
+/// Sets the args of every Type ID created by the transaction, i.e. Type ID outputs whose type
+/// script is not carried by one of the resolved inputs. Outputs of upgraded cells keep the
+/// args of their input.
+///
+/// Without inputs the args are zero-filled placeholders of the final size, so the transaction
+/// can be balanced before they are computed.
+pub fn fill_type_id_args(tx: CellMetaTransaction) -> CellMetaTransaction {
+    let input_types = tx
+        .inputs
+        .iter()
+        .filter_map(|cell| cell.cell_output.type_().to_opt())
+        .collect::<Vec<_>>();
+    let first_input = tx.tx.inputs().get(0);
+    let outputs = tx
+        .outputs()
+        .into_iter()
+        .enumerate()
+        .map(|(idx, output)| match output.type_().to_opt() {
+            Some(type_) if is_type_id(&type_) && !input_types.contains(&type_) => {
+                let args = match &first_input {
+                    Some(first_input) => type_id_args(first_input, idx as u64),
+                    None => Bytes::from(vec![0u8; TYPE_ID_ARGS_LEN]),
+                };
+                let type_ = type_.as_builder().args(args.pack()).build();
+                output.as_builder().type_(Some(type_).pack()).build()
+            }
+            _ => output,
+        })
+        .collect::<Vec<_>>();
+    let inner_tx = tx.as_advanced_builder().set_outputs(outputs).build();
+    tx.tx(inner_tx)
+}
+
+/// Middleware filling in the args of Type IDs created by the transaction.
+///
+/// Place it after the middleware adding inputs and outputs. The generator fills the args again
+/// after balancing, in case balancing added the first input.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TypeIdMiddleware;
+
+impl GeneratorMiddleware for TypeIdMiddleware {
+    fn pipe(
+        &self,
+        tx: CellMetaTransaction,
+        _query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) -> CellMetaTransaction {
+        fill_type_id_args(tx)
+    }
+
+    fn update_query_register(
+        &self,
+        _tx: CellMetaTransaction,
+        _query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) {
+    }
+}
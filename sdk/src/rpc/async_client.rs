@@ -0,0 +1,389 @@
+use super::*;
+use jsonrpc_core::response::{Output, Response};
+use jsonrpc_core::Id;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_RPC_RETRIES: u32 = 3;
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+pub const DEFAULT_MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// A single call of a JSON-RPC batch request.
+#[derive(Debug, Clone)]
+pub struct BatchCall {
+    method: String,
+    params: serde_json::Value,
+}
+
+impl BatchCall {
+    pub fn new<P: Serialize>(method: impl Into<String>, params: Vec<P>) -> RpcResult<Self> {
+        Ok(Self {
+            method: method.into(),
+            params: serde_json::to_value(params)?,
+        })
+    }
+}
+
+/// Async counterpart of [`RpcClient`], bound to a single node or indexer url.
+///
+/// Requests that fail in transport (connection errors, timeouts) are retried with
+/// exponential backoff; errors returned by the node are not. Note that a retried
+/// `send_transaction` may reach the node twice, the second attempt is then rejected as a
+/// duplicate. Clones share the connection pool and the request id sequence.
+#[derive(Clone, Debug)]
+pub struct AsyncRpcClient {
+    pub client: reqwest::Client,
+    url: reqwest::Url,
+    id: Arc<AtomicU64>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl AsyncRpcClient {
+    pub fn new(url: impl reqwest::IntoUrl) -> RpcResult<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: url.into_url()?,
+            id: Default::default(),
+            timeout: DEFAULT_RPC_TIMEOUT,
+            retries: DEFAULT_RPC_RETRIES,
+            backoff: DEFAULT_RETRY_BACKOFF,
+            max_backoff: DEFAULT_MAX_RETRY_BACKOFF,
+        })
+    }
+
+    /// Time allowed for each attempt of a request, including reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Attempts made after the first one fails in transport.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled for every further retry up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn url(&self) -> &reqwest::Url {
+        &self.url
+    }
+
+    fn next_id(&self) -> u64 {
+        self.id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn call_body(&self, method: &str, params: serde_json::Value) -> (u64, serde_json::Value) {
+        let id = self.next_id();
+        let body = serde_json::json!({
+            "id": id,
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        (id, body)
+    }
+
+    async fn post(&self, body: &serde_json::Value) -> RpcResult<Response> {
+        let mut attempt = 0;
+        loop {
+            match self.post_once(body).await {
+                Err(e) if attempt < self.retries && e.is_transport() => {
+                    tokio::time::sleep(self.retry_delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn post_once(&self, body: &serde_json::Value) -> RpcResult<Response> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .timeout(self.timeout)
+            .json(body)
+            .send()
+            .await?;
+        Ok(response.json::<Response>().await?)
+    }
+
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    pub async fn req<T: DeserializeOwned, P: Serialize>(
+        &self,
+        method: impl Into<String>,
+        payload: Vec<P>,
+    ) -> RpcResult<T> {
+        let (_, body) = self.call_body(&method.into(), serde_json::to_value(payload)?);
+        match self.post(&body).await? {
+            Response::Single(output) => output_result(output),
+            Response::Batch(_) => Err(RpcError::UnexpectedBatch),
+        }
+    }
+
+    /// Sends all calls in one JSON-RPC batch request. The outer result fails when the
+    /// request itself does; each call's result is returned in the order of `calls`.
+    pub async fn batch(
+        &self,
+        calls: Vec<BatchCall>,
+    ) -> RpcResult<Vec<RpcResult<serde_json::Value>>> {
+        if calls.is_empty() {
+            return Ok(vec![]);
+        }
+        let (ids, bodies): (Vec<u64>, Vec<serde_json::Value>) = calls
+            .into_iter()
+            .map(|call| self.call_body(&call.method, call.params))
+            .unzip();
+        let outputs = match self.post(&serde_json::Value::Array(bodies)).await? {
+            Response::Batch(outputs) => outputs,
+            Response::Single(output) => vec![output],
+        };
+        // Nodes may answer batch calls in any order
+        let mut by_id: HashMap<u64, Output> = outputs
+            .into_iter()
+            .filter_map(|output| match output.id() {
+                Id::Num(id) => Some((*id, output)),
+                _ => None,
+            })
+            .collect();
+        Ok(ids
+            .into_iter()
+            .map(|id| {
+                by_id
+                    .remove(&id)
+                    .ok_or(RpcError::MissingBatchResponse(id))
+                    .and_then(output_result)
+            })
+            .collect())
+    }
+
+    /// [`AsyncRpcClient::batch`] for calls that share a result type.
+    pub async fn batch_typed<T: DeserializeOwned>(
+        &self,
+        calls: Vec<BatchCall>,
+    ) -> RpcResult<Vec<RpcResult<T>>> {
+        Ok(self
+            .batch(calls)
+            .await?
+            .into_iter()
+            .map(|result| result.and_then(|value| Ok(serde_json::from_value(value)?)))
+            .collect())
+    }
+
+    // Chain module
+
+    pub async fn get_tip_header(&self) -> RpcResult<HeaderView> {
+        self.req("get_tip_header", no_params()).await
+    }
+
+    pub async fn get_tip_block_number(&self) -> RpcResult<BlockNumber> {
+        self.req("get_tip_block_number", no_params()).await
+    }
+
+    pub async fn get_header(&self, hash: H256) -> RpcResult<Option<HeaderView>> {
+        self.req("get_header", vec![hash]).await
+    }
+
+    pub async fn get_header_by_number(&self, number: BlockNumber) -> RpcResult<Option<HeaderView>> {
+        self.req("get_header_by_number", vec![number]).await
+    }
+
+    pub async fn get_block_hash(&self, number: BlockNumber) -> RpcResult<Option<H256>> {
+        self.req("get_block_hash", vec![number]).await
+    }
+
+    pub async fn get_block(&self, hash: H256) -> RpcResult<Option<BlockView>> {
+        self.req("get_block", vec![hash]).await
+    }
+
+    pub async fn get_block_by_number(&self, number: BlockNumber) -> RpcResult<Option<BlockView>> {
+        self.req("get_block_by_number", vec![number]).await
+    }
+
+    pub async fn get_current_epoch(&self) -> RpcResult<EpochView> {
+        self.req("get_current_epoch", no_params()).await
+    }
+
+    pub async fn get_epoch_by_number(&self, number: EpochNumber) -> RpcResult<Option<EpochView>> {
+        self.req("get_epoch_by_number", vec![number]).await
+    }
+
+    pub async fn get_transaction(&self, hash: H256) -> RpcResult<Option<TransactionWithStatus>> {
+        self.req("get_transaction", vec![hash]).await
+    }
+
+    pub async fn get_live_cell(
+        &self,
+        out_point: OutPoint,
+        with_data: bool,
+    ) -> RpcResult<CellWithStatus> {
+        self.req(
+            "get_live_cell",
+            vec![
+                serde_json::to_value(out_point)?,
+                serde_json::to_value(with_data)?,
+            ],
+        )
+        .await
+    }
+
+    pub async fn get_transaction_proof(
+        &self,
+        tx_hashes: Vec<H256>,
+        block_hash: Option<H256>,
+    ) -> RpcResult<TransactionProof> {
+        self.req(
+            "get_transaction_proof",
+            vec![
+                serde_json::to_value(tx_hashes)?,
+                serde_json::to_value(block_hash)?,
+            ],
+        )
+        .await
+    }
+
+    pub async fn verify_transaction_proof(&self, proof: TransactionProof) -> RpcResult<Vec<H256>> {
+        self.req("verify_transaction_proof", vec![proof]).await
+    }
+
+    // Experiment module
+
+    pub async fn dry_run_transaction(&self, tx: Transaction) -> RpcResult<DryRunResult> {
+        self.req("dry_run_transaction", vec![tx]).await
+    }
+
+    pub async fn estimate_cycles(&self, tx: Transaction) -> RpcResult<DryRunResult> {
+        self.req("estimate_cycles", vec![tx]).await
+    }
+
+    // Pool module
+
+    pub async fn send_transaction(&self, tx: Transaction) -> RpcResult<H256> {
+        self.req("send_transaction", vec![tx]).await
+    }
+
+    pub async fn tx_pool_info(&self) -> RpcResult<TxPoolInfo> {
+        self.req("tx_pool_info", no_params()).await
+    }
+
+    pub async fn get_raw_tx_pool(&self, verbose: Option<bool>) -> RpcResult<RawTxPool> {
+        self.req("get_raw_tx_pool", vec![verbose]).await
+    }
+
+    pub async fn clear_tx_pool(&self) -> RpcResult<()> {
+        self.req("clear_tx_pool", no_params()).await
+    }
+
+    // Stats module
+
+    pub async fn get_blockchain_info(&self) -> RpcResult<ChainInfo> {
+        self.req("get_blockchain_info", no_params()).await
+    }
+
+    // Net module
+
+    pub async fn local_node_info(&self) -> RpcResult<LocalNode> {
+        self.req("local_node_info", no_params()).await
+    }
+
+    pub async fn get_peers(&self) -> RpcResult<Vec<RemoteNode>> {
+        self.req("get_peers", no_params()).await
+    }
+
+    pub async fn get_banned_addresses(&self) -> RpcResult<Vec<BannedAddr>> {
+        self.req("get_banned_addresses", no_params()).await
+    }
+
+    pub async fn set_network_active(&self, state: bool) -> RpcResult<()> {
+        self.req("set_network_active", vec![state]).await
+    }
+
+    // IntegrationTest module
+
+    /// See [`RpcClient::generate_block`]. A retried attempt may produce an extra block.
+    pub async fn generate_block(&self) -> RpcResult<H256> {
+        self.req("generate_block", no_params()).await
+    }
+
+    pub async fn mine_blocks(&self, count: u64) -> RpcResult<Vec<H256>> {
+        let mut hashes = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            hashes.push(self.generate_block().await?);
+        }
+        Ok(hashes)
+    }
+
+    // Indexer module
+
+    pub async fn get_indexer_tip(&self) -> RpcResult<Option<IndexerTip>> {
+        self.req("get_tip", no_params()).await
+    }
+
+    pub async fn get_cells(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>,
+    ) -> RpcResult<Pagination<IndexerCell>> {
+        self.req(
+            "get_cells",
+            vec![
+                serde_json::to_value(search_key)?,
+                serde_json::to_value(order)?,
+                serde_json::to_value(limit)?,
+                serde_json::to_value(after_cursor)?,
+            ],
+        )
+        .await
+    }
+
+    pub async fn get_transactions(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>,
+    ) -> RpcResult<Pagination<IndexerTx>> {
+        self.req(
+            "get_transactions",
+            vec![
+                serde_json::to_value(search_key)?,
+                serde_json::to_value(order)?,
+                serde_json::to_value(limit)?,
+                serde_json::to_value(after_cursor)?,
+            ],
+        )
+        .await
+    }
+
+    pub async fn get_cells_capacity(
+        &self,
+        search_key: SearchKey,
+    ) -> RpcResult<Option<CellsCapacity>> {
+        self.req("get_cells_capacity", vec![search_key]).await
+    }
+}
+
+fn output_result<T: DeserializeOwned>(output: Output) -> RpcResult<T> {
+    match output {
+        Output::Success(success) => Ok(serde_json::from_value(success.result)?),
+        Output::Failure(failure) => Err(failure.error.into()),
+    }
+}
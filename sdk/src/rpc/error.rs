@@ -0,0 +1,298 @@
+use crate::chain::ScriptCell;
+use ckb_script::ScriptGroupType;
+use ckb_types::H256;
+use jsonrpc_core::ErrorCode;
+use std::fmt;
+use thiserror::Error;
+
+/// Error codes documented by CKB, see https://github.com/nervosnetwork/ckb/tree/develop/rpc#error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CkbErrorCode {
+    CkbInternalError = -1,
+    Deprecated = -2,
+    Invalid = -3,
+    RpcModuleIsDisabled = -4,
+    DaoError = -5,
+    IntegerOverflow = -6,
+    ConfigError = -7,
+    P2PFailedToBroadcast = -101,
+    DatabaseError = -200,
+    ChainIndexIsInconsistent = -201,
+    DatabaseIsCorrupt = -202,
+    TransactionFailedToResolve = -301,
+    TransactionFailedToVerify = -302,
+    AlertFailedToVerifySignatures = -1000,
+    PoolRejectedTransactionByOutputsValidator = -1102,
+    PoolRejectedTransactionByIllTransactionChecker = -1103,
+    PoolRejectedTransactionByMinFeeRate = -1104,
+    PoolRejectedTransactionByMaxAncestorsCountLimit = -1105,
+    PoolIsFull = -1106,
+    PoolRejectedDuplicatedTransaction = -1107,
+    PoolRejectedMalformedTransaction = -1108,
+}
+
+impl CkbErrorCode {
+    const ALL: [CkbErrorCode; 21] = [
+        CkbErrorCode::CkbInternalError,
+        CkbErrorCode::Deprecated,
+        CkbErrorCode::Invalid,
+        CkbErrorCode::RpcModuleIsDisabled,
+        CkbErrorCode::DaoError,
+        CkbErrorCode::IntegerOverflow,
+        CkbErrorCode::ConfigError,
+        CkbErrorCode::P2PFailedToBroadcast,
+        CkbErrorCode::DatabaseError,
+        CkbErrorCode::ChainIndexIsInconsistent,
+        CkbErrorCode::DatabaseIsCorrupt,
+        CkbErrorCode::TransactionFailedToResolve,
+        CkbErrorCode::TransactionFailedToVerify,
+        CkbErrorCode::AlertFailedToVerifySignatures,
+        CkbErrorCode::PoolRejectedTransactionByOutputsValidator,
+        CkbErrorCode::PoolRejectedTransactionByIllTransactionChecker,
+        CkbErrorCode::PoolRejectedTransactionByMinFeeRate,
+        CkbErrorCode::PoolRejectedTransactionByMaxAncestorsCountLimit,
+        CkbErrorCode::PoolIsFull,
+        CkbErrorCode::PoolRejectedDuplicatedTransaction,
+        CkbErrorCode::PoolRejectedMalformedTransaction,
+    ];
+
+    pub fn from_code(code: i64) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.code() == code)
+    }
+
+    pub fn code(self) -> i64 {
+        self as i64
+    }
+
+    /// The node refused a submitted transaction.
+    pub fn is_rejection(self) -> bool {
+        matches!(
+            self,
+            CkbErrorCode::TransactionFailedToResolve | CkbErrorCode::TransactionFailedToVerify
+        ) || (-1199..=-1100).contains(&self.code())
+    }
+}
+
+impl fmt::Display for CkbErrorCode {
+    // Nodes prefix error messages with the Debug name of the code
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Why the inputs or cell deps of a transaction could not be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveFailure {
+    /// A cell has already been spent.
+    Dead,
+    /// A cell does not exist, or its transaction has not been committed yet.
+    Unknown,
+}
+
+/// Error returned by a CKB node or indexer, classified by its documented error code.
+///
+/// Details that nodes only report inside the message, like the failing script, are parsed
+/// on a best effort basis and are `None` when the message has an unexpected shape.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CkbRpcError {
+    #[error("{message}")]
+    TransactionFailedToResolve {
+        message: String,
+        failure: Option<ResolveFailure>,
+    },
+    #[error("{message}")]
+    TransactionFailedToVerify {
+        message: String,
+        group_type: Option<ScriptGroupType>,
+        cell: Option<ScriptCell>,
+        /// Exit code returned by the failing script, if it ran to completion.
+        exit_code: Option<i8>,
+    },
+    #[error("{message}")]
+    PoolRejectedDuplicatedTransaction {
+        message: String,
+        tx_hash: Option<H256>,
+    },
+    #[error("{message}")]
+    PoolRejectedLowFeeRate {
+        message: String,
+        /// Minimum fee rate of the pool in shannons per KB.
+        min_fee_rate: Option<u64>,
+        /// Fee in shannons the transaction needs at the minimum fee rate.
+        min_fee: Option<u64>,
+        fee: Option<u64>,
+    },
+    #[error("{message}")]
+    PoolIsFull { message: String },
+    #[error("{message}")]
+    PoolRejectedTransactionByMaxAncestorsCountLimit { message: String },
+    /// Any other documented CKB error.
+    #[error("{message}")]
+    Ckb { code: CkbErrorCode, message: String },
+    /// Errors outside of the CKB codes, like JSON-RPC protocol errors.
+    #[error(transparent)]
+    Other(jsonrpc_core::Error),
+}
+
+impl CkbRpcError {
+    pub fn code(&self) -> i64 {
+        let code = match self {
+            CkbRpcError::TransactionFailedToResolve { .. } => {
+                CkbErrorCode::TransactionFailedToResolve
+            }
+            CkbRpcError::TransactionFailedToVerify { .. } => {
+                CkbErrorCode::TransactionFailedToVerify
+            }
+            CkbRpcError::PoolRejectedDuplicatedTransaction { .. } => {
+                CkbErrorCode::PoolRejectedDuplicatedTransaction
+            }
+            CkbRpcError::PoolRejectedLowFeeRate { .. } => {
+                CkbErrorCode::PoolRejectedTransactionByMinFeeRate
+            }
+            CkbRpcError::PoolIsFull { .. } => CkbErrorCode::PoolIsFull,
+            CkbRpcError::PoolRejectedTransactionByMaxAncestorsCountLimit { .. } => {
+                CkbErrorCode::PoolRejectedTransactionByMaxAncestorsCountLimit
+            }
+            CkbRpcError::Ckb { code, .. } => *code,
+            CkbRpcError::Other(e) => return e.code.code(),
+        };
+        code.code()
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CkbRpcError::TransactionFailedToResolve { message, .. }
+            | CkbRpcError::TransactionFailedToVerify { message, .. }
+            | CkbRpcError::PoolRejectedDuplicatedTransaction { message, .. }
+            | CkbRpcError::PoolRejectedLowFeeRate { message, .. }
+            | CkbRpcError::PoolIsFull { message }
+            | CkbRpcError::PoolRejectedTransactionByMaxAncestorsCountLimit { message }
+            | CkbRpcError::Ckb { message, .. } => message,
+            CkbRpcError::Other(e) => &e.message,
+        }
+    }
+
+    /// The node refused a submitted transaction.
+    pub fn is_rejection(&self) -> bool {
+        CkbErrorCode::from_code(self.code()).map_or(false, CkbErrorCode::is_rejection)
+    }
+
+    /// Sending the same request again later may succeed: the pool is full, the
+    /// transaction has too many pending ancestors, or the node failed to broadcast it.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CkbRpcError::PoolIsFull { .. }
+                | CkbRpcError::PoolRejectedTransactionByMaxAncestorsCountLimit { .. }
+                | CkbRpcError::Ckb {
+                    code: CkbErrorCode::P2PFailedToBroadcast,
+                    ..
+                }
+        )
+    }
+}
+
+impl From<jsonrpc_core::Error> for CkbRpcError {
+    fn from(e: jsonrpc_core::Error) -> Self {
+        let code = match e.code {
+            ErrorCode::ServerError(code) => CkbErrorCode::from_code(code),
+            _ => None,
+        };
+        let code = match code {
+            Some(code) => code,
+            None => return CkbRpcError::Other(e),
+        };
+        let message = e.message;
+        match code {
+            CkbErrorCode::TransactionFailedToResolve => CkbRpcError::TransactionFailedToResolve {
+                failure: parse_resolve_failure(&message),
+                message,
+            },
+            CkbErrorCode::TransactionFailedToVerify => {
+                let (group_type, cell) = match parse_script_source(&message) {
+                    Some((group_type, cell)) => (Some(group_type), Some(cell)),
+                    None => (None, None),
+                };
+                CkbRpcError::TransactionFailedToVerify {
+                    group_type,
+                    cell,
+                    exit_code: number_after(&message, "error code ")
+                        .and_then(|code| i8::try_from(code).ok()),
+                    message,
+                }
+            }
+            CkbErrorCode::PoolRejectedDuplicatedTransaction => {
+                CkbRpcError::PoolRejectedDuplicatedTransaction {
+                    tx_hash: parse_hash(&message),
+                    message,
+                }
+            }
+            CkbErrorCode::PoolRejectedTransactionByMinFeeRate => {
+                let unsigned = |marker| number_after(&message, marker).map(|n| n as u64);
+                CkbRpcError::PoolRejectedLowFeeRate {
+                    min_fee_rate: unsigned("min fee rate is "),
+                    min_fee: unsigned("should be "),
+                    fee: unsigned("only got "),
+                    message,
+                }
+            }
+            CkbErrorCode::PoolIsFull => CkbRpcError::PoolIsFull { message },
+            CkbErrorCode::PoolRejectedTransactionByMaxAncestorsCountLimit => {
+                CkbRpcError::PoolRejectedTransactionByMaxAncestorsCountLimit { message }
+            }
+            code => CkbRpcError::Ckb { code, message },
+        }
+    }
+}
+
+// "TransactionFailedToResolve: Resolve failed Dead(OutPoint(0x...))"
+fn parse_resolve_failure(message: &str) -> Option<ResolveFailure> {
+    if message.contains("Dead(") {
+        Some(ResolveFailure::Dead)
+    } else if message.contains("Unknown(") {
+        Some(ResolveFailure::Unknown)
+    } else {
+        None
+    }
+}
+
+// "... TransactionScriptError { source: Inputs[0].Lock, cause: ValidationFailure: see the
+// error code -31 in the page ... }"
+fn parse_script_source(message: &str) -> Option<(ScriptGroupType, ScriptCell)> {
+    let source = &message[message.find("source: ")? + "source: ".len()..];
+    let (cell, rest): (fn(usize) -> ScriptCell, &str) =
+        if let Some(rest) = source.strip_prefix("Inputs[") {
+            (ScriptCell::Input, rest)
+        } else if let Some(rest) = source.strip_prefix("Outputs[") {
+            (ScriptCell::Output, rest)
+        } else {
+            return None;
+        };
+    let (index, rest) = rest.split_once("].")?;
+    let group_type = if rest.starts_with("Lock") {
+        ScriptGroupType::Lock
+    } else if rest.starts_with("Type") {
+        ScriptGroupType::Type
+    } else {
+        return None;
+    };
+    Some((group_type, cell(index.parse().ok()?)))
+}
+
+fn parse_hash(message: &str) -> Option<H256> {
+    message.match_indices("0x").find_map(|(start, _)| {
+        let hex = message.get(start + 2..start + 66)?;
+        let mut hash = [0u8; 32];
+        hex::decode_to_slice(hex, &mut hash).ok()?;
+        Some(H256(hash))
+    })
+}
+
+fn number_after(message: &str, marker: &str) -> Option<i64> {
+    let rest = &message[message.find(marker)? + marker.len()..];
+    let end = rest
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+        .map_or(rest.len(), |(i, _)| i);
+    rest[..end].parse().ok()
+}
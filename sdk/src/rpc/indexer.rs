@@ -0,0 +1,89 @@
+use ckb_jsonrpc_types::{
+    BlockNumber, Capacity, CellOutput, JsonBytes, OutPoint, Script, Uint32, Uint64,
+};
+use ckb_types::H256;
+use serde::{Deserialize, Serialize};
+
+// Request and response types of the ckb-indexer RPC (`get_cells`, `get_transactions`, ...).
+// See https://github.com/nervosnetwork/ckb-indexer#get_cells
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchKey {
+    pub script: Script,
+    pub script_type: ScriptType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<SearchKeyFilter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchKeyFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<Script>,
+    /// Half-open range `[start, end)` of the output data length
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_data_len_range: Option<[Uint64; 2]>,
+    /// Half-open range `[start, end)` of the output capacity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_capacity_range: Option<[Uint64; 2]>,
+    /// Half-open range `[start, end)` of the block number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_range: Option<[BlockNumber; 2]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+    Lock,
+    Type,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    Desc,
+    Asc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexerCell {
+    pub output: CellOutput,
+    pub output_data: JsonBytes,
+    pub out_point: OutPoint,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Pagination<T> {
+    pub objects: Vec<T>,
+    pub last_cursor: JsonBytes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexerTip {
+    pub block_hash: H256,
+    pub block_number: BlockNumber,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IoType {
+    Input,
+    Output,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexerTx {
+    pub tx_hash: H256,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+    pub io_index: Uint32,
+    pub io_type: IoType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CellsCapacity {
+    pub capacity: Capacity,
+    pub block_hash: H256,
+    pub block_number: BlockNumber,
+}
@@ -1,13 +1,34 @@
 use ckb_jsonrpc_types::{
-    BlockNumber, BlockView, CellWithStatus, OutPoint, Transaction, TransactionWithStatus,
+    BannedAddr, BlockNumber, BlockView, CellWithStatus, ChainInfo, DryRunResult, EpochNumber,
+    EpochView, HeaderView, JsonBytes, LocalNode, OutPoint, RawTxPool, RemoteNode, Transaction,
+    TransactionProof, TransactionWithStatus, TxPoolInfo, Uint32,
 };
 use ckb_types::H256;
+use std::path::{Path, PathBuf};
 use std::prelude::v1::*;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 use serde::{Deserialize, Serialize};
 use serde_json;
 use thiserror::Error;
 
+mod async_client;
+mod error;
+mod indexer;
+mod query_provider;
+mod recording;
+mod subscription;
+mod tx_provider;
+pub use async_client::*;
+pub use error::*;
+pub use indexer::*;
+pub use query_provider::*;
+pub use recording::{RecordedCall, RecordedResponse, RpcRecording};
+pub use subscription::*;
+
 #[derive(Error, Debug)]
 pub enum RpcError {
     #[error(transparent)]
@@ -15,75 +36,214 @@ pub enum RpcError {
     #[error(transparent)]
     Serialization(#[from] serde_json::Error),
     #[error(transparent)]
-    JsonRPC(#[from] jsonrpc_core::Error),
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error(transparent)]
+    Node(#[from] CkbRpcError),
+    #[error("node answered a single request with a batch response")]
+    UnexpectedBatch,
+    #[error("batch response is missing the answer to request {0}")]
+    MissingBatchResponse(u64),
+    #[error("node does not know block {0:#x}")]
+    MissingHeader(H256),
+    #[error("no recorded answer left for {method} with params {params}")]
+    NotRecorded {
+        method: String,
+        params: serde_json::Value,
+    },
+}
+
+impl From<jsonrpc_core::Error> for RpcError {
+    fn from(e: jsonrpc_core::Error) -> Self {
+        RpcError::Node(e.into())
+    }
+}
+
+impl RpcError {
+    /// The request never reached the node, or its answer got lost on the way back.
+    pub fn is_transport(&self) -> bool {
+        match self {
+            RpcError::Request(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            RpcError::Io(_) | RpcError::WebSocket(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Sending the same request again later may succeed, see [`CkbRpcError::is_transient`].
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RpcError::Node(e) => e.is_transient(),
+            e => e.is_transport(),
+        }
+    }
 }
 
 pub type RpcResult<T> = std::result::Result<T, RpcError>;
 
+// Clones share the request id sequence and the recording
 #[derive(Clone, Debug, Default)]
 pub struct RpcClient {
     pub client: reqwest::blocking::Client,
-    id: u64,
+    id: Arc<AtomicU64>,
+    node_url: Option<String>,
+    tape: Option<Arc<recording::Tape>>,
 }
 
 impl RpcClient {
     pub fn new() -> Self {
         Self {
             client: reqwest::blocking::Client::new(),
-            id: 0,
+            id: Default::default(),
+            node_url: None,
+            tape: None,
+        }
+    }
+
+    /// Writes every call and the node's answer to `path`, to be served later by
+    /// [`RpcClient::replaying`].
+    pub fn recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tape = Some(Arc::new(recording::Tape::record(path.into())));
+        self
+    }
+
+    /// Answers calls from a file written in recording mode instead of asking a node. The
+    /// urls passed to the calls are ignored.
+    pub fn replaying(mut self, path: impl AsRef<Path>) -> RpcResult<Self> {
+        let recording = RpcRecording::load_from(path)?;
+        self.tape = Some(Arc::new(recording::Tape::replay(recording)));
+        Ok(self)
+    }
+
+    /// A client whose `TransactionProvider` implementation sends to the node at `url`.
+    pub fn with_node_url(url: impl Into<String>) -> Self {
+        Self {
+            node_url: Some(url.into()),
+            ..Self::new()
         }
     }
 
+    pub fn node_url(&self) -> Option<&str> {
+        self.node_url.as_deref()
+    }
+
     pub fn req<T: for<'de> Deserialize<'de>, P: Serialize>(
-        &mut self,
+        &self,
         url: impl reqwest::IntoUrl,
         method: impl Into<String>,
         payload: Vec<P>,
     ) -> RpcResult<T> {
-        let payload = serde_json::to_value(payload).expect("Serialize payload");
-        let req_body = self.generate_json_rpc_req(method.into().as_str(), payload)?;
-        let response = self.client.post(url.into_url()?).json(&req_body).send()?;
-        let req_output = response.json::<jsonrpc_core::response::Output>()?;
-        match req_output {
-            jsonrpc_core::response::Output::Success(success) => {
-                serde_json::from_value(success.result).map_err(Into::into)
+        let method = method.into();
+        let params = serde_json::to_value(payload).expect("Serialize payload");
+        let response = match self.tape.as_ref().and_then(|t| t.answer(&method, &params)) {
+            Some(answer) => answer?,
+            None => {
+                let req_body = self.generate_json_rpc_req(&method, params.clone())?;
+                let response = self.client.post(url.into_url()?).json(&req_body).send()?;
+                let response: RecordedResponse =
+                    response.json::<jsonrpc_core::response::Output>()?.into();
+                if let Some(tape) = &self.tape {
+                    tape.append(RecordedCall {
+                        method,
+                        params,
+                        response: response.clone(),
+                    })?;
+                }
+                response
             }
-            jsonrpc_core::response::Output::Failure(failure) => Err(failure.error.into()),
+        };
+        match response {
+            RecordedResponse::Result(result) => serde_json::from_value(result).map_err(Into::into),
+            RecordedResponse::Error(error) => Err(error.into()),
         }
     }
 
     fn generate_json_rpc_req(
-        &mut self,
+        &self,
         method: &str,
         payload: serde_json::Value,
     ) -> RpcResult<serde_json::Map<String, serde_json::Value>> {
-        self.id += 1;
+        let id = self.id.fetch_add(1, Ordering::Relaxed) + 1;
         let mut map = serde_json::Map::new();
-        map.insert("id".to_owned(), serde_json::json!(self.id));
+        map.insert("id".to_owned(), serde_json::json!(id));
         map.insert("jsonrpc".to_owned(), serde_json::json!("2.0"));
         map.insert("method".to_owned(), serde_json::json!(method));
         map.insert("params".to_owned(), payload);
         Ok(map)
     }
 
-    pub fn get_transaction(
-        &mut self,
+    // Chain module
+
+    pub fn get_tip_header(&self, url: impl reqwest::IntoUrl) -> RpcResult<HeaderView> {
+        self.req(url, "get_tip_header", no_params())
+    }
+
+    pub fn get_tip_block_number(&self, url: impl reqwest::IntoUrl) -> RpcResult<BlockNumber> {
+        self.req(url, "get_tip_block_number", no_params())
+    }
+
+    pub fn get_header(
+        &self,
         hash: H256,
         url: impl reqwest::IntoUrl,
-    ) -> RpcResult<Option<TransactionWithStatus>> {
-        self.req(url, "get_transaction", vec![hash])
+    ) -> RpcResult<Option<HeaderView>> {
+        self.req(url, "get_header", vec![hash])
+    }
+
+    pub fn get_header_by_number(
+        &self,
+        number: BlockNumber,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Option<HeaderView>> {
+        self.req(url, "get_header_by_number", vec![number])
+    }
+
+    pub fn get_block_hash(
+        &self,
+        number: BlockNumber,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Option<H256>> {
+        self.req(url, "get_block_hash", vec![number])
     }
 
     pub fn get_block(
-        &mut self,
+        &self,
         hash: H256,
         url: impl reqwest::IntoUrl,
     ) -> RpcResult<Option<BlockView>> {
         self.req(url, "get_block", vec![hash])
     }
 
+    pub fn get_block_by_number(
+        &self,
+        number: BlockNumber,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Option<BlockView>> {
+        self.req(url, "get_block_by_number", vec![number])
+    }
+
+    pub fn get_current_epoch(&self, url: impl reqwest::IntoUrl) -> RpcResult<EpochView> {
+        self.req(url, "get_current_epoch", no_params())
+    }
+
+    pub fn get_epoch_by_number(
+        &self,
+        number: EpochNumber,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Option<EpochView>> {
+        self.req(url, "get_epoch_by_number", vec![number])
+    }
+
+    pub fn get_transaction(
+        &self,
+        hash: H256,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Option<TransactionWithStatus>> {
+        self.req(url, "get_transaction", vec![hash])
+    }
+
     pub fn get_live_cell(
-        &mut self,
+        &self,
         out_point: OutPoint,
         with_data: bool,
         url: impl reqwest::IntoUrl,
@@ -92,25 +252,173 @@ impl RpcClient {
             url,
             "get_live_cell",
             vec![
-                serde_json::to_string(&out_point)?,
-                serde_json::to_string(&with_data)?,
+                serde_json::to_value(out_point)?,
+                serde_json::to_value(with_data)?,
             ],
         )
     }
 
-    pub fn get_block_by_number(
-        &mut self,
-        number: BlockNumber,
+    pub fn get_transaction_proof(
+        &self,
+        tx_hashes: Vec<H256>,
+        block_hash: Option<H256>,
         url: impl reqwest::IntoUrl,
-    ) -> RpcResult<Option<BlockView>> {
-        self.req(url, "get_block_by_number", vec![number])
+    ) -> RpcResult<TransactionProof> {
+        self.req(
+            url,
+            "get_transaction_proof",
+            vec![
+                serde_json::to_value(tx_hashes)?,
+                serde_json::to_value(block_hash)?,
+            ],
+        )
+    }
+
+    pub fn verify_transaction_proof(
+        &self,
+        proof: TransactionProof,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Vec<H256>> {
+        self.req(url, "verify_transaction_proof", vec![proof])
+    }
+
+    // Experiment module
+
+    pub fn dry_run_transaction(
+        &self,
+        tx: Transaction,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<DryRunResult> {
+        self.req(url, "dry_run_transaction", vec![tx])
     }
 
-    pub fn send_transaction(
-        &mut self,
+    /// Cycles the transaction's scripts consume. Only served by nodes from v0.105 on;
+    /// older nodes provide the same result through `dry_run_transaction`.
+    pub fn estimate_cycles(
+        &self,
         tx: Transaction,
         url: impl reqwest::IntoUrl,
-    ) -> RpcResult<H256> {
+    ) -> RpcResult<DryRunResult> {
+        self.req(url, "estimate_cycles", vec![tx])
+    }
+
+    // Pool module
+
+    pub fn send_transaction(&self, tx: Transaction, url: impl reqwest::IntoUrl) -> RpcResult<H256> {
         self.req(url, "send_transaction", vec![tx])
     }
+
+    pub fn tx_pool_info(&self, url: impl reqwest::IntoUrl) -> RpcResult<TxPoolInfo> {
+        self.req(url, "tx_pool_info", no_params())
+    }
+
+    pub fn get_raw_tx_pool(
+        &self,
+        verbose: Option<bool>,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<RawTxPool> {
+        self.req(url, "get_raw_tx_pool", vec![verbose])
+    }
+
+    pub fn clear_tx_pool(&self, url: impl reqwest::IntoUrl) -> RpcResult<()> {
+        self.req(url, "clear_tx_pool", no_params())
+    }
+
+    // Stats module
+
+    pub fn get_blockchain_info(&self, url: impl reqwest::IntoUrl) -> RpcResult<ChainInfo> {
+        self.req(url, "get_blockchain_info", no_params())
+    }
+
+    // Net module
+
+    pub fn local_node_info(&self, url: impl reqwest::IntoUrl) -> RpcResult<LocalNode> {
+        self.req(url, "local_node_info", no_params())
+    }
+
+    pub fn get_peers(&self, url: impl reqwest::IntoUrl) -> RpcResult<Vec<RemoteNode>> {
+        self.req(url, "get_peers", no_params())
+    }
+
+    pub fn get_banned_addresses(&self, url: impl reqwest::IntoUrl) -> RpcResult<Vec<BannedAddr>> {
+        self.req(url, "get_banned_addresses", no_params())
+    }
+
+    pub fn set_network_active(&self, state: bool, url: impl reqwest::IntoUrl) -> RpcResult<()> {
+        self.req(url, "set_network_active", vec![state])
+    }
+
+    // IntegrationTest module, only served by nodes that enable it in `ckb.toml`
+
+    /// Produces a block with the node's block assembler. The node returns once the block
+    /// is processed, so it is committed when the call returns.
+    pub fn generate_block(&self, url: impl reqwest::IntoUrl) -> RpcResult<H256> {
+        self.req(url, "generate_block", no_params())
+    }
+
+    /// Produces exactly `count` blocks one after the other and returns their hashes.
+    pub fn mine_blocks(&self, count: u64, url: impl reqwest::IntoUrl) -> RpcResult<Vec<H256>> {
+        let url = url.into_url()?;
+        (0..count)
+            .map(|_| self.generate_block(url.clone()))
+            .collect()
+    }
+
+    // Indexer module, served by ckb-indexer rather than the node
+
+    pub fn get_indexer_tip(&self, url: impl reqwest::IntoUrl) -> RpcResult<Option<IndexerTip>> {
+        self.req(url, "get_tip", no_params())
+    }
+
+    pub fn get_cells(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Pagination<IndexerCell>> {
+        self.req(
+            url,
+            "get_cells",
+            vec![
+                serde_json::to_value(search_key)?,
+                serde_json::to_value(order)?,
+                serde_json::to_value(limit)?,
+                serde_json::to_value(after_cursor)?,
+            ],
+        )
+    }
+
+    pub fn get_transactions(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Pagination<IndexerTx>> {
+        self.req(
+            url,
+            "get_transactions",
+            vec![
+                serde_json::to_value(search_key)?,
+                serde_json::to_value(order)?,
+                serde_json::to_value(limit)?,
+                serde_json::to_value(after_cursor)?,
+            ],
+        )
+    }
+
+    pub fn get_cells_capacity(
+        &self,
+        search_key: SearchKey,
+        url: impl reqwest::IntoUrl,
+    ) -> RpcResult<Option<CellsCapacity>> {
+        self.req(url, "get_cells_capacity", vec![search_key])
+    }
+}
+
+fn no_params() -> Vec<serde_json::Value> {
+    vec![]
 }
@@ -0,0 +1,267 @@
+use super::*;
+use crate::contract::deploy::Deployment;
+use crate::contract::generator::{
+    CellQuery, CellQueryAttribute, ProviderError, ProviderResult, QueryProvider, QueryStatement,
+};
+use ckb_types::{
+    core::cell::{CellMeta, CellMetaBuilder},
+    packed,
+    prelude::*,
+};
+use std::collections::{HashMap, HashSet};
+
+// Number of cells requested from the indexer per `get_cells` call
+pub const DEFAULT_INDEXER_PAGE_SIZE: u32 = 100;
+
+/// `QueryProvider` backed by the ckb-indexer `get_cells` RPC.
+///
+/// The indexer can only search by script, so every statement needs a lock or type script to
+/// start from. Capacity ranges and a second script are passed on as indexer filters; the
+/// remaining attributes are checked on the returned cells. Lock and type hashes are
+/// translated through scripts registered with [`IndexerQueryProvider::register_script`], and
+/// data hashes through code cells registered with [`IndexerQueryProvider::register_code_cell`].
+/// Statements with no script to start from fail with [`ProviderError::UnsupportedQuery`].
+pub struct IndexerQueryProvider {
+    pub client: RpcClient,
+    url: String,
+    page_size: u32,
+    scripts: HashMap<packed::Byte32, packed::Script>,
+    // Lock of the registered code cell for each data hash
+    code_cells: HashMap<packed::Byte32, packed::Script>,
+}
+
+impl IndexerQueryProvider {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: RpcClient::new(),
+            url: url.into(),
+            page_size: DEFAULT_INDEXER_PAGE_SIZE,
+            scripts: HashMap::new(),
+            code_cells: HashMap::new(),
+        }
+    }
+
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Make `LockHash` and `TypeHash` queries for this script's hash searchable.
+    pub fn register_script(&mut self, script: impl Into<packed::Script>) {
+        let script = script.into();
+        self.scripts.insert(script.calc_script_hash(), script);
+    }
+
+    /// Make `DataHash` queries for the data of the live cell at `out_point` searchable, so the
+    /// generator can find the code cells of scripts referenced by data hash. If the cell has a
+    /// type script, `TypeHash` queries for it are made searchable too.
+    pub fn register_code_cell(&mut self, out_point: impl Into<OutPoint>) -> ProviderResult<()> {
+        let out_point = out_point.into();
+        let cell = self
+            .client
+            .get_live_cell(out_point.clone(), true, self.url.as_str())
+            .map_err(ProviderError::Rpc)?
+            .cell
+            .ok_or_else(|| ProviderError::DeadCell(out_point.into()))?;
+        let output = packed::CellOutput::from(cell.output);
+        let data = cell.data.map(|data| data.content).unwrap_or_default();
+        self.code_cells.insert(
+            packed::CellOutput::calc_data_hash(data.as_bytes()),
+            output.lock(),
+        );
+        if let Some(type_) = output.type_().to_opt() {
+            self.register_script(type_);
+        }
+        Ok(())
+    }
+
+    /// Make the code cell of a deployment searchable by the hash scripts reference it with.
+    /// Type ID deployments register their type script, which keeps finding the code cell
+    /// after upgrades.
+    pub fn register_deployment(&mut self, deployment: &Deployment) -> ProviderResult<()> {
+        match &deployment.type_script {
+            Some(type_script) => {
+                self.register_script(type_script.clone());
+                Ok(())
+            }
+            None => self.register_code_cell(deployment.out_point.clone()),
+        }
+    }
+
+    // The search key for a statement over `attrs`, failing if no attribute leads to a script
+    fn search_key(&self, attrs: &[CellQueryAttribute]) -> ProviderResult<SearchKey> {
+        let mut lock = None;
+        let mut type_ = None;
+        let mut min_capacity = 0;
+        let mut max_capacity = u64::MAX;
+        for attr in attrs {
+            match attr {
+                CellQueryAttribute::LockScript(script) => {
+                    lock.get_or_insert_with(|| script.clone());
+                }
+                CellQueryAttribute::LockHash(hash) => {
+                    if let Some(script) = self.scripts.get(&packed::Byte32::from(hash.clone())) {
+                        lock.get_or_insert_with(|| script.clone().into());
+                    }
+                }
+                CellQueryAttribute::TypeScript(script) => {
+                    type_.get_or_insert_with(|| script.clone());
+                }
+                CellQueryAttribute::TypeHash(hash) => {
+                    if let Some(script) = self.scripts.get(&packed::Byte32::from(hash.clone())) {
+                        type_.get_or_insert_with(|| script.clone().into());
+                    }
+                }
+                CellQueryAttribute::MinCapacity(min) => {
+                    min_capacity = min_capacity.max(u64::from(*min))
+                }
+                CellQueryAttribute::MaxCapacity(max) => {
+                    max_capacity = max_capacity.min(u64::from(*max))
+                }
+                CellQueryAttribute::DataHash(hash) => {
+                    if let Some(lock_script) =
+                        self.code_cells.get(&packed::Byte32::from(hash.clone()))
+                    {
+                        lock.get_or_insert_with(|| lock_script.clone().into());
+                    }
+                }
+            }
+        }
+        let capacity_range = (min_capacity > 0 || max_capacity < u64::MAX)
+            .then(|| [min_capacity.into(), max_capacity.saturating_add(1).into()]);
+        let (script, script_type, other) = match (lock, type_) {
+            (Some(lock), type_) => (lock, ScriptType::Lock, type_),
+            (None, Some(type_)) => (type_, ScriptType::Type, None),
+            (None, None) => return Err(ProviderError::UnsupportedQuery(attrs.to_vec())),
+        };
+        Ok(SearchKey {
+            script,
+            script_type,
+            filter: (other.is_some() || capacity_range.is_some()).then(|| SearchKeyFilter {
+                script: other,
+                output_capacity_range: capacity_range,
+                ..Default::default()
+            }),
+        })
+    }
+
+    // Page through the indexer until `limit` cells matching every attribute are found
+    fn search(&self, attrs: &[CellQueryAttribute], limit: u64) -> ProviderResult<Vec<IndexerCell>> {
+        let search_key = self.search_key(attrs)?;
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        let mut cells = vec![];
+        let mut cursor = None;
+        while cells.len() < limit {
+            let page = self
+                .client
+                .get_cells(
+                    search_key.clone(),
+                    Order::Asc,
+                    self.page_size.into(),
+                    cursor,
+                    self.url.as_str(),
+                )
+                .map_err(ProviderError::Rpc)?;
+            let page_len = page.objects.len();
+            cells.extend(
+                page.objects
+                    .into_iter()
+                    .filter(|cell| attrs.iter().all(|attr| cell_matches(cell, attr))),
+            );
+            if page_len < self.page_size as usize {
+                break;
+            }
+            cursor = Some(page.last_cursor);
+        }
+        cells.truncate(limit);
+        Ok(cells)
+    }
+
+    pub fn query_cells(&self, query: CellQuery) -> ProviderResult<Vec<IndexerCell>> {
+        let CellQuery { _query, _limit } = query;
+        match _query {
+            QueryStatement::Single(attr) => self.search(&[attr], _limit),
+            QueryStatement::FilterFrom(source, filter) => self.search(&[source, filter], _limit),
+            QueryStatement::All(attrs) => self.search(&attrs, _limit),
+            QueryStatement::Any(attrs) => {
+                let mut seen = HashSet::new();
+                let mut cells = vec![];
+                for attr in attrs {
+                    cells.extend(
+                        self.search(&[attr], _limit)?
+                            .into_iter()
+                            .filter(|cell| seen.insert(cell.out_point.clone())),
+                    );
+                }
+                cells.truncate(usize::try_from(_limit).unwrap_or(usize::MAX));
+                Ok(cells)
+            }
+        }
+    }
+}
+
+fn cell_matches(cell: &IndexerCell, attr: &CellQueryAttribute) -> bool {
+    let output = packed::CellOutput::from(cell.output.clone());
+    match attr {
+        CellQueryAttribute::LockHash(hash) => {
+            output.calc_lock_hash() == packed::Byte32::from(hash.clone())
+        }
+        CellQueryAttribute::LockScript(script) => {
+            output.lock() == packed::Script::from(script.clone())
+        }
+        CellQueryAttribute::TypeScript(script) => {
+            output.type_().to_opt() == Some(packed::Script::from(script.clone()))
+        }
+        CellQueryAttribute::TypeHash(hash) => {
+            output
+                .type_()
+                .to_opt()
+                .map(|script| script.calc_script_hash())
+                == Some(packed::Byte32::from(hash.clone()))
+        }
+        CellQueryAttribute::MinCapacity(min) => cell.output.capacity.value() >= min.value(),
+        CellQueryAttribute::MaxCapacity(max) => cell.output.capacity.value() <= max.value(),
+        CellQueryAttribute::DataHash(hash) => {
+            packed::CellOutput::calc_data_hash(cell.output_data.as_bytes())
+                == packed::Byte32::from(hash.clone())
+        }
+    }
+}
+
+impl QueryProvider for IndexerQueryProvider {
+    fn query(&self, query: CellQuery) -> ProviderResult<Vec<OutPoint>> {
+        self.query_cells(query)
+            .map(|cells| cells.into_iter().map(|cell| cell.out_point).collect())
+    }
+
+    fn query_cell_meta(&self, query: CellQuery) -> ProviderResult<Vec<CellMeta>> {
+        self.query_cells(query).map(|cells| {
+            cells
+                .into_iter()
+                .map(|cell| {
+                    CellMetaBuilder::from_cell_output(
+                        cell.output.into(),
+                        cell.output_data.into_bytes(),
+                    )
+                    .out_point(cell.out_point.into())
+                    .build()
+                })
+                .collect()
+        })
+    }
+
+    fn resolve_cell(&self, out_point: packed::OutPoint) -> ProviderResult<CellMeta> {
+        let cell = self
+            .client
+            .get_live_cell(out_point.clone().into(), true, self.url.as_str())
+            .map_err(ProviderError::Rpc)?
+            .cell
+            .ok_or_else(|| ProviderError::DeadCell(out_point.clone()))?;
+        let data = cell.data.map(|data| data.content).unwrap_or_default();
+        Ok(
+            CellMetaBuilder::from_cell_output(cell.output.into(), data.into_bytes())
+                .out_point(out_point)
+                .build(),
+        )
+    }
+}
@@ -0,0 +1,113 @@
+use super::*;
+use jsonrpc_core::response::Output;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Answer of the node to a recorded call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedResponse {
+    Result(serde_json::Value),
+    Error(jsonrpc_core::Error),
+}
+
+impl From<Output> for RecordedResponse {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Success(success) => RecordedResponse::Result(success.result),
+            Output::Failure(failure) => RecordedResponse::Error(failure.error),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedCall {
+    pub method: String,
+    pub params: serde_json::Value,
+    pub response: RecordedResponse,
+}
+
+/// Calls of an [`RpcClient`] session in the order they were made.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RpcRecording {
+    pub calls: Vec<RecordedCall>,
+}
+
+impl RpcRecording {
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> RpcResult<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> RpcResult<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Tape {
+    // Every call is appended and the file rewritten, so a failing test still leaves a
+    // complete recording behind
+    Record {
+        path: PathBuf,
+        recording: Mutex<RpcRecording>,
+    },
+    // Calls are answered by the first unused recorded call with the same method and params,
+    // so repeated calls replay the answers in the order they were recorded
+    Replay {
+        calls: Vec<RecordedCall>,
+        used: Mutex<Vec<bool>>,
+    },
+}
+
+impl Tape {
+    pub(crate) fn record(path: PathBuf) -> Self {
+        Tape::Record {
+            path,
+            recording: Default::default(),
+        }
+    }
+
+    pub(crate) fn replay(recording: RpcRecording) -> Self {
+        Tape::Replay {
+            used: Mutex::new(vec![false; recording.calls.len()]),
+            calls: recording.calls,
+        }
+    }
+
+    pub(crate) fn append(&self, call: RecordedCall) -> RpcResult<()> {
+        if let Tape::Record { path, recording } = self {
+            let mut recording = recording.lock().expect("Lock recording");
+            recording.calls.push(call);
+            recording.save_to(path)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn answer(
+        &self,
+        method: &str,
+        params: &serde_json::Value,
+    ) -> Option<RpcResult<RecordedResponse>> {
+        let (calls, used) = match self {
+            Tape::Replay { calls, used } => (calls, used),
+            Tape::Record { .. } => return None,
+        };
+        let mut used = used.lock().expect("Lock replayed calls");
+        let found = (0..calls.len())
+            .find(|&idx| !used[idx] && calls[idx].method == method && &calls[idx].params == params);
+        Some(match found {
+            Some(idx) => {
+                used[idx] = true;
+                Ok(calls[idx].response.clone())
+            }
+            None => Err(RpcError::NotRecorded {
+                method: method.to_owned(),
+                params: params.clone(),
+            }),
+        })
+    }
+}
@@ -0,0 +1,326 @@
+use super::*;
+use ckb_jsonrpc_types::PoolTransactionEntry;
+use futures_util::SinkExt;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::{Stream, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+// Events buffered for a slow consumer before the connection stops being read
+const EVENT_BUFFER: usize = 64;
+
+/// Subscription topics of a CKB node, see
+/// https://github.com/nervosnetwork/ckb/tree/develop/rpc#module-subscription
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    NewTipHeader,
+    NewTipBlock,
+    NewTransaction,
+    ProposedTransaction,
+    RejectedTransaction,
+}
+
+/// Why the pool rejected a transaction, as reported by `rejected_transaction`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PoolRejection {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum SubscriptionEvent {
+    NewTipHeader(HeaderView),
+    NewTipBlock(BlockView),
+    NewTransaction(PoolTransactionEntry),
+    ProposedTransaction(PoolTransactionEntry),
+    RejectedTransaction(PoolTransactionEntry, PoolRejection),
+    /// The connection was lost and has been restored. Events of the time in between are
+    /// missed and need to be caught up on through [`RpcClient`].
+    Reconnected,
+}
+
+impl SubscriptionEvent {
+    fn decode(topic: Topic, payload: serde_json::Value) -> RpcResult<Self> {
+        // Nodes send the payload as a string of JSON
+        fn parse<T: DeserializeOwned>(payload: serde_json::Value) -> RpcResult<T> {
+            Ok(match payload {
+                serde_json::Value::String(json) => serde_json::from_str(&json)?,
+                payload => serde_json::from_value(payload)?,
+            })
+        }
+        Ok(match topic {
+            Topic::NewTipHeader => SubscriptionEvent::NewTipHeader(parse(payload)?),
+            Topic::NewTipBlock => SubscriptionEvent::NewTipBlock(parse(payload)?),
+            Topic::NewTransaction => SubscriptionEvent::NewTransaction(parse(payload)?),
+            Topic::ProposedTransaction => SubscriptionEvent::ProposedTransaction(parse(payload)?),
+            Topic::RejectedTransaction => {
+                let (entry, rejection) = parse(payload)?;
+                SubscriptionEvent::RejectedTransaction(entry, rejection)
+            }
+        })
+    }
+}
+
+/// Client of the subscription API a node serves over TCP (`tcp_listen_address` in
+/// `ckb.toml`) or WebSocket (`ws_listen_address`).
+///
+/// Lost connections are restored with exponential backoff, after which the topics are
+/// subscribed again and [`SubscriptionEvent::Reconnected`] is delivered.
+#[derive(Clone, Debug)]
+pub struct SubscriptionClient {
+    addr: String,
+    topics: Vec<Topic>,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl SubscriptionClient {
+    /// `addr` is either a `ws://` or `wss://` URL of the node's WebSocket endpoint, or the
+    /// `host:port` it listens on for TCP subscriptions.
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            topics: vec![],
+            backoff: DEFAULT_RETRY_BACKOFF,
+            max_backoff: DEFAULT_MAX_RETRY_BACKOFF,
+        }
+    }
+
+    pub fn topic(mut self, topic: Topic) -> Self {
+        if !self.topics.contains(&topic) {
+            self.topics.push(topic);
+        }
+        self
+    }
+
+    /// Delay before the first reconnection attempt, doubled for every further attempt up
+    /// to `max`.
+    pub fn reconnect_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Connects and subscribes to the topics. Only this first connection reports errors,
+    /// later ones are retried until the [`Subscription`] is dropped.
+    pub async fn subscribe(self) -> RpcResult<Subscription> {
+        let connection = Connection::open(&self.addr, &self.topics).await?;
+        let (sender, events) = mpsc::channel(EVENT_BUFFER);
+        let task = tokio::spawn(self.run(connection, sender));
+        Ok(Subscription { events, task })
+    }
+
+    async fn run(
+        self,
+        mut connection: Connection,
+        sender: mpsc::Sender<RpcResult<SubscriptionEvent>>,
+    ) {
+        loop {
+            if !connection.forward(&sender).await {
+                return;
+            }
+            let mut attempt = 0;
+            connection = loop {
+                tokio::time::sleep(self.reconnect_delay(attempt)).await;
+                if sender.is_closed() {
+                    return;
+                }
+                match Connection::open(&self.addr, &self.topics).await {
+                    Ok(connection) => break connection,
+                    Err(_) => attempt += 1,
+                }
+            };
+            if sender
+                .send(Ok(SubscriptionEvent::Reconnected))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    fn reconnect_delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Stream of subscription events. Dropping it closes the connection.
+pub struct Subscription {
+    events: mpsc::Receiver<RpcResult<SubscriptionEvent>>,
+    task: JoinHandle<()>,
+}
+
+impl Stream for Subscription {
+    type Item = RpcResult<SubscriptionEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Deserialize)]
+struct Notification {
+    params: NotificationParams,
+}
+
+#[derive(Deserialize)]
+struct NotificationParams {
+    result: serde_json::Value,
+    subscription: serde_json::Value,
+}
+
+enum Transport {
+    // Newline delimited JSON
+    Tcp {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+    },
+    WebSocket(Box<WebSocketStream<MaybeTlsStream<TcpStream>>>),
+}
+
+impl Transport {
+    async fn connect(addr: &str) -> RpcResult<Self> {
+        if addr.starts_with("ws://") || addr.starts_with("wss://") {
+            let (stream, _) = tokio_tungstenite::connect_async(addr).await?;
+            Ok(Transport::WebSocket(Box::new(stream)))
+        } else {
+            let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+            Ok(Transport::Tcp {
+                lines: BufReader::new(reader).lines(),
+                writer,
+            })
+        }
+    }
+
+    async fn send(&mut self, message: String) -> RpcResult<()> {
+        match self {
+            Transport::Tcp { writer, .. } => {
+                writer
+                    .write_all(format!("{}\n", message).as_bytes())
+                    .await?
+            }
+            Transport::WebSocket(stream) => stream.send(Message::Text(message)).await?,
+        }
+        Ok(())
+    }
+
+    // Next message of the node, fails once the connection is lost
+    async fn receive(&mut self) -> RpcResult<String> {
+        match self {
+            Transport::Tcp { lines, .. } => lines.next_line().await?.ok_or_else(closed),
+            Transport::WebSocket(stream) => loop {
+                // Pings are answered by the stream itself
+                match stream.next().await.ok_or_else(closed)?? {
+                    Message::Text(text) => return Ok(text),
+                    Message::Binary(bytes) => {
+                        return String::from_utf8(bytes).map_err(|e| {
+                            std::io::Error::new(std::io::ErrorKind::InvalidData, e).into()
+                        })
+                    }
+                    Message::Close(_) => return Err(closed()),
+                    _ => continue,
+                }
+            },
+        }
+    }
+}
+
+fn closed() -> RpcError {
+    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed").into()
+}
+
+struct Connection {
+    transport: Transport,
+    topics: HashMap<String, Topic>,
+    // Notifications that arrived while subscribing
+    early: Vec<Notification>,
+}
+
+impl Connection {
+    async fn open(addr: &str, topics: &[Topic]) -> RpcResult<Self> {
+        let mut transport = Transport::connect(addr).await?;
+        let mut subscriptions = HashMap::new();
+        let mut early = vec![];
+        for (id, topic) in topics.iter().enumerate() {
+            let request = serde_json::json!({
+                "id": id,
+                "jsonrpc": "2.0",
+                "method": "subscribe",
+                "params": [topic],
+            });
+            transport.send(request.to_string()).await?;
+            let subscription = loop {
+                let message = transport.receive().await?;
+                match serde_json::from_str::<jsonrpc_core::response::Output>(&message) {
+                    Ok(jsonrpc_core::response::Output::Success(success)) => {
+                        break success.result.to_string()
+                    }
+                    Ok(jsonrpc_core::response::Output::Failure(failure)) => {
+                        return Err(failure.error.into())
+                    }
+                    Err(_) => early.push(serde_json::from_str(&message)?),
+                }
+            };
+            subscriptions.insert(subscription, *topic);
+        }
+        Ok(Self {
+            transport,
+            topics: subscriptions,
+            early,
+        })
+    }
+
+    // Forwards events until the connection is lost; false once nobody listens anymore
+    async fn forward(&mut self, sender: &mpsc::Sender<RpcResult<SubscriptionEvent>>) -> bool {
+        for notification in std::mem::take(&mut self.early) {
+            if let Some(event) = self.decode(notification) {
+                if sender.send(event).await.is_err() {
+                    return false;
+                }
+            }
+        }
+        while let Ok(message) = self.transport.receive().await {
+            if message.trim().is_empty() {
+                continue;
+            }
+            let event = match serde_json::from_str::<Notification>(&message) {
+                Ok(notification) => match self.decode(notification) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                Err(e) => Err(e.into()),
+            };
+            if sender.send(event).await.is_err() {
+                return false;
+            }
+        }
+        !sender.is_closed()
+    }
+
+    fn decode(&self, notification: Notification) -> Option<RpcResult<SubscriptionEvent>> {
+        let params = notification.params;
+        let topic = self.topics.get(&params.subscription.to_string())?;
+        Some(SubscriptionEvent::decode(*topic, params.result))
+    }
+}
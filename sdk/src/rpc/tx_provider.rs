@@ -0,0 +1,81 @@
+use super::*;
+use crate::chain::{TxState, TxStatusProvider};
+use crate::contract::generator::{ProviderError, ProviderResult, TransactionProvider};
+use ckb_jsonrpc_types::{Byte32, TransactionView as JsonTransaction};
+use ckb_types::{
+    core::{self, Cycle},
+    packed,
+    prelude::*,
+};
+
+impl From<RpcError> for ProviderError {
+    fn from(e: RpcError) -> Self {
+        match e {
+            RpcError::Node(e) if e.is_rejection() => ProviderError::Rejected(e),
+            e => ProviderError::Rpc(e),
+        }
+    }
+}
+
+// Sends to and verifies against the node configured with `RpcClient::with_node_url`
+impl TransactionProvider for RpcClient {
+    fn send_tx(&self, tx: JsonTransaction) -> ProviderResult<Byte32> {
+        let url = self.node_url().ok_or(ProviderError::NoNodeUrl)?;
+        let hash: packed::Byte32 = self.send_transaction(tx.inner, url)?.pack();
+        Ok(hash.into())
+    }
+
+    fn verify_tx(&self, tx: JsonTransaction) -> ProviderResult<Cycle> {
+        let url = self.node_url().ok_or(ProviderError::NoNodeUrl)?;
+        let result = self.dry_run_transaction(tx.inner, url)?;
+        Ok(result.cycles.into())
+    }
+}
+
+// Only the status of `get_transaction` results, which also accepts the `rejected` and
+// `unknown` states reported by newer nodes
+#[derive(Deserialize)]
+struct TransactionStatusOnly {
+    tx_status: RawTxStatus,
+}
+
+#[derive(Deserialize)]
+struct RawTxStatus {
+    status: String,
+    block_hash: Option<H256>,
+    reason: Option<String>,
+}
+
+impl TxStatusProvider for RpcClient {
+    fn tx_state(&self, hash: &H256) -> ProviderResult<TxState> {
+        let url = self.node_url().ok_or(ProviderError::NoNodeUrl)?;
+        let tx: Option<TransactionStatusOnly> =
+            self.req(url, "get_transaction", vec![hash.clone()])?;
+        let status = match tx {
+            Some(tx) => tx.tx_status,
+            None => return Ok(TxState::Unknown),
+        };
+        Ok(match (status.status.as_str(), status.block_hash) {
+            ("pending", _) => TxState::Pending,
+            ("proposed", _) => TxState::Proposed,
+            ("committed", Some(block_hash)) => {
+                let header = self
+                    .get_header(block_hash.clone(), url)?
+                    .ok_or_else(|| RpcError::MissingHeader(block_hash.clone()))?;
+                TxState::Committed {
+                    block_hash,
+                    block_number: header.inner.number.into(),
+                }
+            }
+            ("rejected", _) => TxState::Rejected {
+                reason: status.reason.unwrap_or_default(),
+            },
+            _ => TxState::Unknown,
+        })
+    }
+
+    fn tip_block_number(&self) -> ProviderResult<core::BlockNumber> {
+        let url = self.node_url().ok_or(ProviderError::NoNodeUrl)?;
+        Ok(self.get_tip_block_number(url)?.into())
+    }
+}
@@ -0,0 +1,101 @@
+use ckb_jsonrpc_types::BlockNumber;
+use jsonrpc_core::{ErrorCode, IoHandler, Value};
+use jsonrpc_http_server::ServerBuilder;
+use std::net::{SocketAddr, TcpListener};
+use std::time::{Duration, Instant};
+use trampoline_sdk::chain::{MockChain, MockChainRpcServer};
+use trampoline_sdk::ckb_types::H256;
+use trampoline_sdk::rpc::{AsyncRpcClient, BatchCall, CkbRpcError, RpcError};
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+// The stand-in nodes run their own runtime, which must not be started or dropped from
+// within the client's
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Runtime::new().unwrap().block_on(future)
+}
+
+#[test]
+fn test_typed_calls_and_batch() {
+    let mut chain = MockChain::default();
+    chain.advance_blocks(2);
+    let server = MockChainRpcServer::start(chain, &"127.0.0.1:0".parse().unwrap()).unwrap();
+    let client = AsyncRpcClient::new(server.url()).unwrap();
+    block_on(async {
+        let tip = client.get_tip_header().await.unwrap();
+        assert_eq!(u64::from(client.get_tip_block_number().await.unwrap()), 2);
+
+        let calls = vec![
+            BatchCall::new("get_block_hash", vec![BlockNumber::from(0u64)]).unwrap(),
+            BatchCall::new("get_block_hash", vec![tip.inner.number]).unwrap(),
+            BatchCall::new("get_block_hash", vec![BlockNumber::from(3u64)]).unwrap(),
+        ];
+        let hashes = client.batch_typed::<Option<H256>>(calls).await.unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert!(hashes[0].as_ref().unwrap().is_some());
+        assert_eq!(hashes[1].as_ref().unwrap(), &Some(tip.hash));
+        assert_eq!(hashes[2].as_ref().unwrap(), &None);
+
+        // A failing call does not fail the rest of the batch
+        let results = client
+            .batch(vec![
+                BatchCall::new("get_tip_block_number", Vec::<()>::new()).unwrap(),
+                BatchCall::new("no_such_method", Vec::<()>::new()).unwrap(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &Value::from("0x2"));
+        assert!(matches!(
+            &results[1],
+            Err(RpcError::Node(CkbRpcError::Other(e))) if e.code == ErrorCode::MethodNotFound
+        ));
+    });
+    server.close();
+}
+
+#[test]
+fn test_retries_until_node_is_up() {
+    let addr = free_addr();
+    let node = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        let mut io = IoHandler::new();
+        io.add_sync_method("get_tip_block_number", |_| Ok(Value::from("0x7")));
+        ServerBuilder::new(io).start_http(&addr).unwrap()
+    });
+
+    let client = AsyncRpcClient::new(format!("http://{}", addr))
+        .unwrap()
+        .retries(10)
+        .backoff(Duration::from_millis(50), Duration::from_millis(200));
+    let started = Instant::now();
+    let tip = block_on(client.get_tip_block_number()).unwrap();
+    assert_eq!(u64::from(tip), 7);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    node.join().unwrap().close();
+}
+
+#[test]
+fn test_gives_up_after_timeouts() {
+    let addr = free_addr();
+    let mut io = IoHandler::new();
+    io.add_sync_method("get_tip_block_number", |_| {
+        std::thread::sleep(Duration::from_millis(500));
+        Ok(Value::from("0x7"))
+    });
+    let server = ServerBuilder::new(io).start_http(&addr).unwrap();
+
+    let client = AsyncRpcClient::new(format!("http://{}", addr))
+        .unwrap()
+        .timeout(Duration::from_millis(100))
+        .retries(0);
+    match block_on(client.get_tip_block_number()) {
+        Err(RpcError::Request(e)) => assert!(e.is_timeout()),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    server.close();
+}
@@ -0,0 +1,202 @@
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
+use trampoline_sdk::chain::{
+    ConfirmationError, ConfirmationTracker, MockChain, MockChainRpcServer, MockChainTxProvider,
+    SnapshotId, TxState, TxStatusProvider,
+};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::{BlockNumber, Capacity, TransactionBuilder, TransactionView},
+    packed::{CellInput, CellOutput, Script},
+    prelude::*,
+    H256,
+};
+use trampoline_sdk::contract::generator::ProviderResult;
+use trampoline_sdk::rpc::RpcClient;
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+fn transfer_tx(chain: &mut MockChain) -> TransactionView {
+    let lock = always_success_lock(chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    TransactionBuilder::default()
+        .input(CellInput::new(input, 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(1000).unwrap().pack())
+                .lock(lock.clone())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_dep(chain.find_cell_dep_for_script(&lock).unwrap())
+        .build()
+}
+
+#[test]
+fn test_tracks_mock_chain_transaction_to_depth() {
+    let mut chain = MockChain::default();
+    let tx = transfer_tx(&mut chain);
+    let provider = MockChainTxProvider::new(chain);
+    provider.chain.borrow_mut().submit_tx(&tx).unwrap();
+    let hash: H256 = tx.hash().unpack();
+
+    let mut states = vec![];
+    let confirmation = ConfirmationTracker::new(&provider)
+        .confirmations(3)
+        .track(&hash, |state| states.push(state.clone()))
+        .unwrap();
+
+    let chain = provider.chain.borrow();
+    let (_, block_hash) = chain.get_transaction(&tx.hash()).unwrap();
+    assert_eq!(confirmation.block_hash, block_hash.unwrap().unpack());
+    assert_eq!(confirmation.confirmations, 3);
+    assert_eq!(chain.tip_header().number(), confirmation.block_number + 2);
+    assert_eq!(
+        states,
+        vec![
+            TxState::Pending,
+            TxState::Committed {
+                block_hash: confirmation.block_hash.clone(),
+                block_number: confirmation.block_number,
+            },
+        ]
+    );
+}
+
+// A pool that evicts every transaction before the next block
+struct EvictingPool {
+    provider: MockChainTxProvider,
+    before_submit: RefCell<Option<SnapshotId>>,
+}
+
+impl TxStatusProvider for EvictingPool {
+    fn tx_state(&self, hash: &H256) -> ProviderResult<TxState> {
+        self.provider.tx_state(hash)
+    }
+
+    fn tip_block_number(&self) -> ProviderResult<BlockNumber> {
+        self.provider.tip_block_number()
+    }
+
+    fn wait(&self, _interval: Duration) -> ProviderResult<()> {
+        if let Some(id) = self.before_submit.borrow_mut().take() {
+            self.provider.chain.borrow_mut().restore(id)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_detects_dropped_transaction() {
+    let mut chain = MockChain::default();
+    let tx = transfer_tx(&mut chain);
+    let id = chain.snapshot();
+    chain.submit_tx(&tx).unwrap();
+    let pool = EvictingPool {
+        provider: MockChainTxProvider::new(chain),
+        before_submit: RefCell::new(Some(id)),
+    };
+
+    match pool.wait_for_commit(&tx.hash().unpack()) {
+        Err(ConfirmationError::Dropped { last }) => assert_eq!(last, TxState::Pending),
+        res => panic!("Expected a dropped transaction, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_fails_fast_on_unknown_mock_chain_transaction() {
+    let provider = MockChainTxProvider::new(MockChain::default());
+    let tip = provider.tip_block_number().unwrap();
+
+    assert!(matches!(
+        provider.wait_for_commit(&H256([7u8; 32])),
+        Err(ConfirmationError::NotFound)
+    ));
+    assert_eq!(provider.tip_block_number().unwrap(), tip);
+}
+
+// A chain that never includes the pending transaction in a block
+struct StalledPool {
+    provider: MockChainTxProvider,
+    waits: Cell<u64>,
+}
+
+impl TxStatusProvider for StalledPool {
+    fn tx_state(&self, hash: &H256) -> ProviderResult<TxState> {
+        self.provider.tx_state(hash)
+    }
+
+    fn tip_block_number(&self) -> ProviderResult<BlockNumber> {
+        self.provider.tip_block_number()
+    }
+
+    fn wait(&self, _interval: Duration) -> ProviderResult<()> {
+        self.waits.set(self.waits.get() + 1);
+        Ok(())
+    }
+
+    fn max_waits(&self, confirmations: u64) -> Option<u64> {
+        self.provider.max_waits(confirmations)
+    }
+}
+
+#[test]
+fn test_bounds_pending_wait_by_blocks() {
+    let mut chain = MockChain::default();
+    let tx = transfer_tx(&mut chain);
+    chain.submit_tx(&tx).unwrap();
+    let pool = StalledPool {
+        provider: MockChainTxProvider::new(chain),
+        waits: Cell::new(0),
+    };
+
+    match ConfirmationTracker::new(&pool)
+        .confirmations(2)
+        .wait_for_commit(&tx.hash().unpack())
+    {
+        Err(ConfirmationError::Timeout { state }) => assert_eq!(state, TxState::Pending),
+        res => panic!("Expected a timeout, got {:?}", res),
+    }
+    assert_eq!(pool.waits.get(), 3);
+}
+
+#[test]
+fn test_waits_for_commit_over_rpc() {
+    let mut chain = MockChain::default();
+    let tx = transfer_tx(&mut chain);
+    let server = MockChainRpcServer::start(chain, &"127.0.0.1:0".parse().unwrap()).unwrap();
+    let client = RpcClient::with_node_url(server.url());
+    let url = server.url();
+
+    let hash = client
+        .send_transaction(tx.data().into(), url.as_str())
+        .unwrap();
+    let confirmation = client.wait_for_commit(&hash).unwrap();
+    assert_eq!(confirmation.confirmations, 1);
+
+    // The server only produces blocks for new transactions
+    let tracker = ConfirmationTracker::new(&client)
+        .confirmations(2)
+        .timeout(Duration::from_millis(200))
+        .poll_interval(Duration::from_millis(50));
+    match tracker.wait_for_commit(&hash) {
+        Err(ConfirmationError::Timeout { state }) => assert!(matches!(
+            state,
+            TxState::Committed { block_number, .. } if block_number == confirmation.block_number
+        )),
+        res => panic!("Expected a timeout, got {:?}", res),
+    }
+
+    let unknown = H256([7u8; 32]);
+    assert!(matches!(
+        tracker.wait_for_commit(&unknown),
+        Err(ConfirmationError::Timeout {
+            state: TxState::Unknown
+        })
+    ));
+    server.close();
+}
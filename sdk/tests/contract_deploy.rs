@@ -0,0 +1,77 @@
+use ckb_jsonrpc_types::{JsonBytes, ScriptHashType};
+use trampoline_sdk::chain::{MockChain, MockChainTxProvider};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    packed::{CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use trampoline_sdk::contract::deploy::Deployment;
+use trampoline_sdk::contract::generator::Generator;
+use trampoline_sdk::contract::type_id::{type_id_args, type_id_script};
+
+const CODE: &[u8] = b"not really a RISC-V binary";
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+// A chain with a single capacity cell owned by the deployer
+fn funded_provider() -> (MockChainTxProvider, Script) {
+    let mut chain = MockChain::default();
+    let deployer = always_success_lock(&chain, 1);
+    chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    (MockChainTxProvider::new(chain), deployer)
+}
+
+fn deploy(provider: &MockChainTxProvider, deployer: &Script, type_id: bool) -> Deployment {
+    let generator = Generator::new()
+        .query_service(provider)
+        .change_lock(deployer.clone());
+    Deployment::deploy(
+        Bytes::from_static(CODE),
+        deployer.clone(),
+        type_id,
+        &generator,
+        provider,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_deploy_code_by_data_hash() {
+    let (provider, deployer) = funded_provider();
+    let deployment = deploy(&provider, &deployer, false);
+
+    let chain = provider.chain.borrow();
+    let out_point = OutPoint::from(deployment.out_point.clone());
+    let (output, data) = chain.get_live_cell(&out_point).unwrap();
+    assert_eq!(data.as_ref(), CODE);
+    assert!(output.type_().is_none());
+    let code_hash: H256 = CellOutput::calc_data_hash(CODE).unpack();
+    assert_eq!(deployment.code_hash, code_hash);
+    assert_eq!(deployment.hash_type, ScriptHashType::Data1);
+    assert_eq!(deployment.cell_dep().out_point, deployment.out_point);
+}
+
+#[test]
+fn test_deploy_code_with_type_id() {
+    let (provider, deployer) = funded_provider();
+    let deployment = deploy(&provider, &deployer, true);
+
+    let chain = provider.chain.borrow();
+    let out_point = OutPoint::from(deployment.out_point.clone());
+    let (output, data) = chain.get_live_cell(&out_point).unwrap();
+    assert_eq!(data.as_ref(), CODE);
+    let (tx, _) = chain.get_transaction(&out_point.tx_hash()).unwrap();
+    let expected = type_id_script(type_id_args(&tx.inputs().get(0).unwrap(), 0));
+    assert_eq!(output.type_().to_opt(), Some(expected.clone()));
+    let code_hash: H256 = expected.calc_script_hash().unpack();
+    assert_eq!(deployment.code_hash, code_hash);
+    assert_eq!(deployment.hash_type, ScriptHashType::Type);
+
+    let script: Script = deployment.script(JsonBytes::from_vec(vec![7u8])).into();
+    assert_eq!(script.args().raw_data(), Bytes::from(vec![7u8]));
+}
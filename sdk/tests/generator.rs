@@ -0,0 +1,207 @@
+use std::sync::{Arc, Mutex};
+use trampoline_sdk::chain::{MockChain, MockChainRpcServer, MockChainTxProvider};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::Capacity,
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+use trampoline_sdk::contract::generator::{
+    CellMetaTransaction, CellQuery, Generator, GeneratorError, GeneratorMiddleware, ProviderError,
+    TransactionProvider,
+};
+use trampoline_sdk::rpc::IndexerQueryProvider;
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+// Adds a single output paying `capacity` to `lock`
+struct Payment {
+    lock: Script,
+    capacity: Capacity,
+}
+
+impl GeneratorMiddleware for Payment {
+    fn pipe(
+        &self,
+        tx: CellMetaTransaction,
+        _query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) -> CellMetaTransaction {
+        let output = CellOutput::new_builder()
+            .lock(self.lock.clone())
+            .capacity(self.capacity.pack())
+            .build();
+        let inner_tx = tx
+            .as_advanced_builder()
+            .output(output)
+            .output_data(Bytes::new().pack())
+            .build();
+        tx.tx(inner_tx)
+    }
+
+    fn update_query_register(
+        &self,
+        _tx: CellMetaTransaction,
+        _query_register: Arc<Mutex<Vec<CellQuery>>>,
+    ) {
+    }
+}
+
+// A chain where the payer owns a single capacity cell of `funds`
+fn funded_chain(funds: Capacity) -> (MockChainTxProvider, Script, Script) {
+    let mut chain = MockChain::default();
+    let payer = always_success_lock(&chain, 1);
+    let receiver = always_success_lock(&chain, 2);
+    chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(funds.pack())
+            .lock(payer.clone())
+            .build(),
+        Bytes::new(),
+    );
+    (MockChainTxProvider::new(chain), payer, receiver)
+}
+
+#[test]
+fn test_try_generate_reports_insufficient_capacity() {
+    let (provider, payer, receiver) = funded_chain(Capacity::bytes(100).unwrap());
+    let payment = Payment {
+        lock: receiver,
+        capacity: Capacity::bytes(1000).unwrap(),
+    };
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(payer)
+        .pipeline(vec![&payment]);
+
+    match generator.try_generate() {
+        Err(GeneratorError::InsufficientCapacity { available, .. }) => {
+            assert_eq!(available, Capacity::bytes(100).unwrap().as_u64())
+        }
+        res => panic!("Expected insufficient capacity, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_remainder_too_small_for_change_is_paid_as_fee() {
+    let funds = Capacity::bytes(1000).unwrap();
+    let (provider, payer, receiver) = funded_chain(funds);
+    // Leaves enough for the fee but far less than a change cell occupies
+    let payment = Payment {
+        lock: receiver.clone(),
+        capacity: funds.safe_sub(Capacity::shannons(1000)).unwrap(),
+    };
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(payer)
+        .pipeline(vec![&payment]);
+
+    // Only paid as fee when allowed
+    match generator.try_generate() {
+        Err(GeneratorError::InsufficientCapacity { available, .. }) => {
+            assert_eq!(available, funds.as_u64())
+        }
+        res => panic!("Expected insufficient capacity, got {:?}", res),
+    }
+    let generator = generator.max_change_as_fee(Capacity::shannons(1000));
+    let tx = generator.try_generate().unwrap();
+    assert_eq!(tx.tx.inputs().len(), 1);
+    assert_eq!(tx.tx.outputs().len(), 1);
+    assert_eq!(tx.tx.output(0).unwrap().lock(), receiver);
+    provider.verify_tx(tx.tx.into()).unwrap();
+
+    let generator = generator.max_change_as_fee(Capacity::shannons(100));
+    assert!(matches!(
+        generator.try_generate(),
+        Err(GeneratorError::InsufficientCapacity { .. })
+    ));
+}
+
+#[test]
+fn test_try_generate_reports_fee_overflow() {
+    let (provider, payer, receiver) = funded_chain(Capacity::bytes(1000).unwrap());
+    let payment = Payment {
+        lock: receiver,
+        capacity: Capacity::bytes(500).unwrap(),
+    };
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(payer)
+        .fee_rate(u64::MAX)
+        .pipeline(vec![&payment]);
+
+    match generator.try_generate() {
+        Err(GeneratorError::FeeOverflow { fee_rate, .. }) => assert_eq!(fee_rate, u64::MAX),
+        res => panic!("Expected a fee overflow, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_generate_through_indexer_query_provider() {
+    let (provider, payer, receiver) = funded_chain(Capacity::bytes(1000).unwrap());
+    let chain = provider.chain.borrow().clone();
+    let server = MockChainRpcServer::start(chain.clone(), &"127.0.0.1:0".parse().unwrap()).unwrap();
+    let mut indexer = IndexerQueryProvider::new(server.url());
+    let payment = Payment {
+        lock: receiver,
+        capacity: Capacity::bytes(500).unwrap(),
+    };
+
+    // The payer's lock references its code by data hash, which the indexer can't search
+    {
+        let generator = Generator::new()
+            .query_service(&indexer)
+            .change_lock(payer.clone())
+            .pipeline(vec![&payment]);
+        match generator.try_generate() {
+            Err(GeneratorError::Provider(ProviderError::UnsupportedQuery(_))) => {}
+            res => panic!("Expected an unsupported query, got {:?}", res),
+        }
+    }
+
+    indexer
+        .register_code_cell(chain.get_default_script_outpoint())
+        .unwrap();
+    let generator = Generator::new()
+        .query_service(&indexer)
+        .change_lock(payer)
+        .pipeline(vec![&payment]);
+    let tx = generator.try_generate().unwrap();
+    assert_eq!(tx.tx.inputs().len(), 1);
+    assert_eq!(
+        tx.tx.cell_deps().get(0).unwrap().out_point(),
+        chain.get_default_script_outpoint()
+    );
+    provider.verify_tx(tx.tx.into()).unwrap();
+    server.close();
+}
+
+#[test]
+fn test_try_generate_reports_missing_code_cells() {
+    let (provider, payer, receiver) = funded_chain(Capacity::bytes(1000).unwrap());
+    // A lock whose code was never deployed
+    let unknown_lock = payer.as_builder().code_hash([1u8; 32].pack()).build();
+    provider.chain.borrow_mut().create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(1000).unwrap().pack())
+            .lock(unknown_lock.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let payment = Payment {
+        lock: receiver,
+        capacity: Capacity::bytes(1500).unwrap(),
+    };
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(unknown_lock.clone())
+        .pipeline(vec![&payment]);
+
+    match generator.try_generate() {
+        Err(GeneratorError::UnresolvedCellDep(script)) => assert_eq!(script, unknown_lock),
+        res => panic!("Expected an unresolved cell dep, got {:?}", res),
+    }
+}
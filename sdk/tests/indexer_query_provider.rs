@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+use trampoline_sdk::chain::{MockChain, MockChainRpcServer, MockChainTxProvider};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::Capacity,
+    packed::{CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use trampoline_sdk::contract::generator::{
+    CellQuery, CellQueryAttribute, ProviderError, QueryProvider, QueryStatement,
+};
+use trampoline_sdk::rpc::IndexerQueryProvider;
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+fn outpoints(provider: &dyn QueryProvider, statement: QueryStatement, limit: u64) -> Vec<OutPoint> {
+    provider
+        .query(CellQuery {
+            _query: statement,
+            _limit: limit,
+        })
+        .unwrap()
+        .into_iter()
+        .map(OutPoint::from)
+        .collect()
+}
+
+#[test]
+fn test_indexer_queries_match_mock_chain() {
+    let mut chain = MockChain::default();
+    let owner = always_success_lock(&chain, 1);
+    let token = always_success_lock(&chain, 3);
+    for capacity in [100, 200, 300, 400, 500] {
+        chain.deploy_random_cell_with_default_lock(capacity, Some(vec![1_u8].into()));
+    }
+    // Shares the owner's lock code and args prefix, which the indexer matches but the query must not
+    chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8, 1_u8].into()));
+    let token_cell = chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(600).unwrap().pack())
+            .lock(owner.clone())
+            .type_(Some(token.clone()).pack())
+            .build(),
+        Bytes::from(vec![7u8; 16]),
+    );
+
+    let mock = MockChainTxProvider::new(chain.clone());
+    let server = MockChainRpcServer::start(chain, &"127.0.0.1:0".parse().unwrap()).unwrap();
+    let mut indexer = IndexerQueryProvider::new(server.url()).page_size(2);
+    indexer.register_script(owner.clone());
+
+    let at_least =
+        |ckb: usize| CellQueryAttribute::MinCapacity(Capacity::bytes(ckb).unwrap().as_u64().into());
+    let statements = vec![
+        QueryStatement::Single(CellQueryAttribute::LockScript(owner.clone().into())),
+        QueryStatement::Single(CellQueryAttribute::LockHash(
+            owner.calc_script_hash().into(),
+        )),
+        QueryStatement::Single(CellQueryAttribute::TypeScript(token.clone().into())),
+        QueryStatement::All(vec![
+            CellQueryAttribute::LockScript(owner.clone().into()),
+            at_least(300),
+        ]),
+        QueryStatement::FilterFrom(
+            CellQueryAttribute::LockScript(owner.clone().into()),
+            CellQueryAttribute::TypeScript(token.clone().into()),
+        ),
+        QueryStatement::All(vec![
+            CellQueryAttribute::LockScript(owner.clone().into()),
+            CellQueryAttribute::DataHash(CellOutput::calc_data_hash(&[7u8; 16]).into()),
+        ]),
+        QueryStatement::Any(vec![
+            CellQueryAttribute::TypeScript(token.into()),
+            CellQueryAttribute::LockScript(owner.into()),
+        ]),
+    ];
+    for statement in statements {
+        let expected = outpoints(&mock, statement.clone(), u64::MAX)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let found = outpoints(&indexer, statement.clone(), u64::MAX);
+        assert_eq!(found.len(), expected.len(), "{:?}", statement);
+        assert_eq!(found.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    // The limit is applied across pages
+    let statement = QueryStatement::Single(CellQueryAttribute::LockScript(
+        always_success_lock(&mock.chain.borrow(), 1).into(),
+    ));
+    assert_eq!(outpoints(&indexer, statement, 3).len(), 3);
+
+    // Data hashes can't be searched without a script until their cell is registered
+    let data_hash = QueryStatement::Single(CellQueryAttribute::DataHash(
+        CellOutput::calc_data_hash(&[7u8; 16]).into(),
+    ));
+    match indexer.query(CellQuery {
+        _query: data_hash.clone(),
+        _limit: 1,
+    }) {
+        Err(ProviderError::UnsupportedQuery(_)) => {}
+        res => panic!("Expected an unsupported query, got {:?}", res),
+    }
+    indexer.register_code_cell(token_cell.clone()).unwrap();
+    assert_eq!(outpoints(&indexer, data_hash, u64::MAX), vec![token_cell]);
+    server.close();
+}
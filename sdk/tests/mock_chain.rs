@@ -0,0 +1,393 @@
+use ckb_traits::HeaderProvider;
+use std::collections::HashSet;
+use trampoline_sdk::chain::{
+    MockChain, MockChainError, MockChainTxProvider, BLOCK_INTERVAL, EPOCH_LENGTH,
+};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType, TransactionBuilder, TransactionView},
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use trampoline_sdk::contract::generator::{
+    CellQuery, CellQueryAttribute, QueryProvider, QueryStatement,
+};
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+fn transfer_tx(
+    chain: &MockChain,
+    input: OutPoint,
+    lock: Script,
+    capacity: usize,
+) -> TransactionView {
+    TransactionBuilder::default()
+        .input(CellInput::new(input, 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(capacity).unwrap().pack())
+                .lock(lock.clone())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_dep(chain.find_cell_dep_for_script(&lock).unwrap())
+        .build()
+}
+
+#[test]
+fn test_spent_cells_are_removed_from_indexes() {
+    let mut chain = MockChain::default();
+    let sender = always_success_lock(&chain, 1);
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+
+    let tx = transfer_tx(&chain, input.clone(), receiver.clone(), 1000);
+    let tx_hash = chain.receive_tx(&tx).unwrap();
+
+    assert!(!chain.is_live(&input));
+    assert!(chain.get_live_cell(&input).is_none());
+    assert!(chain
+        .get_cells_by_lock_hash(sender.calc_script_hash())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        chain.get_cells_by_lock_hash(receiver.calc_script_hash()),
+        Some(vec![OutPoint::new(tx_hash.clone(), 0)])
+    );
+    let empty_data_cells = chain
+        .get_cells_by_data_hash(CellOutput::calc_data_hash(&[]))
+        .unwrap();
+    assert!(!empty_data_cells.contains(&input));
+    assert!(empty_data_cells.contains(&OutPoint::new(tx_hash, 0)));
+}
+
+#[test]
+fn test_find_cell_dep_for_script_without_code_cell() {
+    let chain = MockChain::default();
+    let lock = always_success_lock(&chain, 1);
+    assert!(chain.find_cell_dep_for_script(&lock).is_some());
+
+    let missing = lock.as_builder().code_hash([1u8; 32].pack()).build();
+    assert!(chain.find_cell_dep_for_script(&missing).is_none());
+    let missing = missing
+        .as_builder()
+        .hash_type(ScriptHashType::Type.into())
+        .build();
+    assert!(chain.find_cell_dep_for_script(&missing).is_none());
+}
+
+#[test]
+fn test_double_spend_is_rejected() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+
+    let tx = transfer_tx(&chain, input.clone(), receiver.clone(), 1000);
+    chain.receive_tx(&tx).unwrap();
+
+    let double_spend = transfer_tx(&chain, input.clone(), receiver, 999);
+    match chain.receive_tx(&double_spend) {
+        Err(MockChainError::DeadInput(outp)) => assert_eq!(outp, input),
+        res => panic!("Expected dead input error, got {:?}", res),
+    }
+    assert!(chain.verify_tx(&double_spend, 500_0000).is_err());
+}
+
+#[test]
+fn test_unknown_input_is_rejected() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let unknown = OutPoint::new(Default::default(), 7);
+
+    let tx = transfer_tx(&chain, unknown.clone(), receiver, 1000);
+    match chain.receive_tx(&tx) {
+        Err(MockChainError::UnknownInput(outp)) => assert_eq!(outp, unknown),
+        res => panic!("Expected unknown input error, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_committed_cells_are_linked_to_their_block() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let genesis = chain.tip_header().clone();
+    assert_eq!(genesis.number(), 0);
+
+    let tx = transfer_tx(&chain, input, receiver, 1000);
+    let tx_hash = chain.submit_tx(&tx).unwrap();
+    assert_eq!(chain.pending_txs.len(), 1);
+    assert!(!chain.is_live(&OutPoint::new(tx_hash.clone(), 0)));
+
+    let block = chain.commit_block();
+    assert_eq!(block.number(), 1);
+    assert_eq!(block.parent_hash(), genesis.hash());
+    assert_eq!(chain.tip_header().hash(), block.hash());
+    assert_eq!(chain.get_block_hash(1), Some(block.hash()));
+    let block_txs = chain.get_block_txs(&block.hash()).unwrap();
+    assert_eq!(block_txs.len(), 1);
+    assert_eq!(block_txs[0].hash(), tx.hash());
+    assert!(chain.pending_txs.is_empty());
+
+    let info = chain
+        .outpoint_txs
+        .get(&OutPoint::new(tx_hash, 0))
+        .unwrap()
+        .clone();
+    assert_eq!(info.block_number, 1);
+    assert_eq!(info.block_hash, block.hash());
+    assert_eq!(info.index, 1);
+}
+
+#[test]
+fn test_advance_blocks_epochs_and_time() {
+    let mut chain = MockChain::default();
+
+    let tip = chain.advance_blocks(10);
+    assert_eq!(tip.number(), 10);
+    assert_eq!(tip.timestamp(), 10 * BLOCK_INTERVAL);
+
+    let tip = chain.advance_epochs(2);
+    assert_eq!(tip.number(), 2 * EPOCH_LENGTH);
+    assert_eq!(tip.epoch().number(), 2);
+    assert_eq!(tip.epoch().index(), 0);
+
+    let before = chain.tip_header().timestamp();
+    let tip = chain.advance_time(60_000);
+    assert_eq!(tip.timestamp(), before + 60_000);
+    assert_eq!(tip.number(), 2 * EPOCH_LENGTH + 60_000 / BLOCK_INTERVAL);
+    assert!(chain.get_header(&tip.hash()).is_some());
+}
+
+#[test]
+fn test_advance_nothing_keeps_tip() {
+    let mut chain = MockChain::default();
+    let tip = chain.advance_blocks(3);
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let tx = transfer_tx(&chain, input, receiver, 1000);
+    chain.submit_tx(&tx).unwrap();
+
+    assert_eq!(chain.advance_blocks(0).hash(), tip.hash());
+    assert_eq!(chain.advance_epochs(0).hash(), tip.hash());
+    assert_eq!(chain.advance_time(BLOCK_INTERVAL - 1).hash(), tip.hash());
+    assert_eq!(chain.tip_header().hash(), tip.hash());
+    // Still pending, as no block was produced
+    assert!(matches!(chain.get_transaction(&tx.hash()), Some((_, None))));
+}
+
+#[test]
+fn test_advance_blocks_produces_every_block() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let tx = transfer_tx(&chain, input, receiver, 1000);
+    chain.submit_tx(&tx).unwrap();
+
+    let tip = chain.advance_blocks(5);
+    assert_eq!(tip.number(), 5);
+    for number in 1..=5 {
+        let hash = chain.get_block_hash(number).unwrap();
+        let header = chain.get_header(&hash).unwrap();
+        assert_eq!(header.number(), number);
+        assert_eq!(
+            header.parent_hash(),
+            chain.get_block_hash(number - 1).unwrap()
+        );
+        assert_eq!(header.timestamp(), number * BLOCK_INTERVAL);
+        // Pending transactions land in the first produced block
+        let tx_count = if number == 1 { 1 } else { 0 };
+        assert_eq!(chain.get_block_txs(&hash).unwrap().len(), tx_count);
+    }
+}
+
+#[test]
+fn test_relative_since_waits_for_maturity() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+
+    // Relative lock of 5 blocks on the input
+    let since: u64 = (1 << 63) | 5;
+    let tx = transfer_tx(&chain, input.clone(), receiver, 1000);
+    let tx = tx
+        .as_advanced_builder()
+        .set_inputs(vec![CellInput::new(input, since)])
+        .build();
+
+    match chain.receive_tx(&tx) {
+        Err(MockChainError::ImmatureInput { index, .. }) => assert_eq!(index, 0),
+        res => panic!("Expected immature input error, got {:?}", res),
+    }
+
+    chain.advance_blocks(4);
+    chain.receive_tx(&tx).unwrap();
+}
+
+#[test]
+fn test_snapshot_restore_and_fork() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let fixture = chain.snapshot();
+
+    // Each scenario branches off the same fixture and spends the same input
+    for capacity in [1000, 999] {
+        let tx = transfer_tx(&chain, input.clone(), receiver.clone(), capacity);
+        chain.receive_tx(&tx).unwrap();
+        assert!(!chain.is_live(&input));
+        assert_eq!(chain.tip_header().number(), 1);
+        chain.restore(fixture).unwrap();
+        assert!(chain.is_live(&input));
+        assert_eq!(chain.tip_header().number(), 0);
+    }
+
+    let mut fork = chain.clone();
+    let tx = transfer_tx(&fork, input.clone(), receiver, 1000);
+    fork.receive_tx(&tx).unwrap();
+    assert!(!fork.is_live(&input));
+    assert!(chain.is_live(&input));
+}
+
+#[test]
+fn test_save_and_load_fixture() {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let tx = transfer_tx(&chain, input.clone(), receiver.clone(), 1000);
+    let tx_hash = chain.receive_tx(&tx).unwrap();
+    chain.advance_epochs(1);
+
+    let path = std::env::temp_dir().join(format!("mock_chain_fixture_{}.json", tx_hash));
+    chain.save_to(&path).unwrap();
+    let loaded = MockChain::load_from(&path).unwrap();
+
+    assert_eq!(loaded.tip_header().hash(), chain.tip_header().hash());
+    assert!(!loaded.is_live(&input));
+    let output = OutPoint::new(tx_hash, 0);
+    assert_eq!(loaded.get_live_cell(&output), chain.get_live_cell(&output));
+    assert_eq!(
+        loaded.outpoint_txs.get(&output),
+        chain.outpoint_txs.get(&output)
+    );
+    assert_eq!(
+        loaded.get_cells_by_lock_hash(receiver.calc_script_hash()),
+        Some(vec![output])
+    );
+    assert_eq!(
+        loaded.get_default_script_outpoint(),
+        chain.get_default_script_outpoint()
+    );
+
+    // Saving is deterministic, so fixtures can be checked in
+    let saved = std::fs::read_to_string(&path).unwrap();
+    loaded.save_to(&path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), saved);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_compound_and_capacity_queries() {
+    let mut chain = MockChain::default();
+    let owner = always_success_lock(&chain, 1);
+    let other = always_success_lock(&chain, 2);
+    let token = always_success_lock(&chain, 3);
+    let owner_hash: ckb_jsonrpc_types::Byte32 = owner.calc_script_hash().into();
+
+    let small = chain.deploy_random_cell_with_default_lock(100, Some(vec![1_u8].into()));
+    let large = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let other_cell = chain.deploy_random_cell_with_default_lock(1000, Some(vec![2_u8].into()));
+    let token_cell = chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(500).unwrap().pack())
+            .lock(owner.clone())
+            .type_(Some(token.clone()).pack())
+            .build(),
+        Bytes::from(vec![7u8; 16]),
+    );
+    let token_copy = chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(500).unwrap().pack())
+            .lock(other.clone())
+            .build(),
+        Bytes::from(vec![7u8; 16]),
+    );
+    let provider = MockChainTxProvider::new(chain);
+    let query = |statement: QueryStatement, limit: u64| -> HashSet<OutPoint> {
+        provider
+            .query(CellQuery {
+                _query: statement,
+                _limit: limit,
+            })
+            .unwrap()
+            .into_iter()
+            .map(OutPoint::from)
+            .collect()
+    };
+    let at_least =
+        |ckb: usize| CellQueryAttribute::MinCapacity(Capacity::bytes(ckb).unwrap().as_u64().into());
+    let set = |cells: &[&OutPoint]| cells.iter().cloned().cloned().collect::<HashSet<_>>();
+
+    assert_eq!(
+        query(
+            QueryStatement::All(vec![
+                CellQueryAttribute::LockHash(owner_hash.clone()),
+                at_least(500),
+            ]),
+            u64::MAX
+        ),
+        set(&[&large, &token_cell])
+    );
+    assert_eq!(
+        query(
+            QueryStatement::All(vec![
+                CellQueryAttribute::LockScript(owner.clone().into()),
+                CellQueryAttribute::TypeScript(token.clone().into()),
+                at_least(500),
+            ]),
+            u64::MAX
+        ),
+        set(&[&token_cell])
+    );
+    assert_eq!(
+        query(
+            QueryStatement::FilterFrom(
+                CellQueryAttribute::LockHash(owner_hash.clone()),
+                CellQueryAttribute::MaxCapacity(Capacity::bytes(100).unwrap().as_u64().into()),
+            ),
+            u64::MAX
+        ),
+        set(&[&small])
+    );
+    assert_eq!(
+        query(
+            QueryStatement::Any(vec![
+                CellQueryAttribute::LockScript(other.into()),
+                CellQueryAttribute::TypeScript(token.into()),
+            ]),
+            u64::MAX
+        ),
+        set(&[&other_cell, &token_copy, &token_cell])
+    );
+    assert_eq!(
+        query(
+            QueryStatement::Single(CellQueryAttribute::DataHash(
+                CellOutput::calc_data_hash(&[7u8; 16]).into()
+            )),
+            u64::MAX
+        ),
+        set(&[&token_cell, &token_copy])
+    );
+    assert_eq!(
+        query(
+            QueryStatement::Single(CellQueryAttribute::LockHash(owner_hash)),
+            2
+        )
+        .len(),
+        2
+    );
+}
@@ -0,0 +1,192 @@
+use ckb_jsonrpc_types::{BlockView, CellWithStatus, HeaderView, JsonBytes, Uint32};
+use serde_json::json;
+use trampoline_sdk::chain::{MockChain, MockChainRpcServer};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder, TransactionView},
+    packed::{Byte32, CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use trampoline_sdk::contract::generator::{ProviderError, TransactionProvider};
+use trampoline_sdk::rpc::{
+    CkbRpcError, IndexerCell, Order, Pagination, ResolveFailure, RpcClient, RpcError, ScriptType,
+    SearchKey,
+};
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+fn transfer_tx(chain: &MockChain, input: OutPoint, lock: Script) -> TransactionView {
+    TransactionBuilder::default()
+        .input(CellInput::new(input, 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(1000).unwrap().pack())
+                .lock(lock.clone())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_dep(chain.find_cell_dep_for_script(&lock).unwrap())
+        .build()
+}
+
+fn start_server() -> (MockChainRpcServer, TransactionView, Script) {
+    let mut chain = MockChain::default();
+    let receiver = always_success_lock(&chain, 2);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let tx = transfer_tx(&chain, input, receiver.clone());
+    let server = MockChainRpcServer::start(chain, &"127.0.0.1:0".parse().unwrap()).unwrap();
+    (server, tx, receiver)
+}
+
+#[test]
+fn test_send_and_query_transaction() {
+    let (server, tx, receiver) = start_server();
+    let url = server.url();
+    let client = RpcClient::new();
+
+    let hash = client
+        .send_transaction(tx.data().into(), url.as_str())
+        .unwrap();
+    assert_eq!(hash, tx.hash().unpack());
+
+    let committed = client
+        .get_transaction(hash.clone(), url.as_str())
+        .unwrap()
+        .unwrap();
+    let tip: HeaderView = client
+        .req(url.as_str(), "get_tip_header", Vec::<()>::new())
+        .unwrap();
+    assert_eq!(committed.tx_status.block_hash, Some(tip.hash.clone()));
+    assert_eq!(u64::from(tip.inner.number), 1);
+
+    let block: BlockView = client
+        .get_block_by_number(1u64.into(), url.as_str())
+        .unwrap()
+        .unwrap();
+    assert_eq!(block.header.hash, tip.hash);
+    assert_eq!(block.transactions.len(), 2);
+    assert_eq!(block.transactions[1].hash, hash);
+
+    let out_point = OutPoint::new(tx.hash(), 0);
+    let cell: CellWithStatus = client
+        .req(
+            url.as_str(),
+            "get_live_cell",
+            vec![
+                json!(ckb_jsonrpc_types::OutPoint::from(out_point.clone())),
+                json!(true),
+            ],
+        )
+        .unwrap();
+    assert_eq!(cell.status, "live");
+    assert!(cell.cell.unwrap().data.is_some());
+
+    let search_key = SearchKey {
+        script: receiver.into(),
+        script_type: ScriptType::Lock,
+        filter: None,
+    };
+    let cells: Pagination<IndexerCell> = client
+        .req(
+            url.as_str(),
+            "get_cells",
+            vec![
+                json!(search_key),
+                json!(Order::Asc),
+                json!(Uint32::from(10u32)),
+                json!(Option::<JsonBytes>::None),
+            ],
+        )
+        .unwrap();
+    assert_eq!(cells.objects.len(), 1);
+    assert_eq!(
+        OutPoint::from(cells.objects[0].out_point.clone()),
+        out_point
+    );
+    assert_eq!(u64::from(cells.objects[0].block_number), 1);
+    server.close();
+}
+
+#[test]
+fn test_rejected_transaction_returns_ckb_error_code() {
+    let (server, tx, _) = start_server();
+    let url = server.url();
+    let client = RpcClient::new();
+
+    client
+        .send_transaction(tx.data().into(), url.as_str())
+        .unwrap();
+    match client.send_transaction(tx.data().into(), url.as_str()) {
+        Err(RpcError::Node(e)) => {
+            assert_eq!(e.code(), -301);
+            assert!(matches!(
+                e,
+                CkbRpcError::TransactionFailedToResolve {
+                    failure: Some(ResolveFailure::Dead),
+                    ..
+                }
+            ));
+        }
+        res => panic!("Expected TransactionFailedToResolve, got {:?}", res),
+    }
+
+    let unknown: Option<H256> = client
+        .req(url.as_str(), "get_block_hash", vec![json!("0x64")])
+        .unwrap();
+    assert!(unknown.is_none());
+    server.close();
+}
+
+#[test]
+fn test_rpc_client_as_transaction_provider() {
+    let (server, tx, _) = start_server();
+    let json_tx: ckb_jsonrpc_types::TransactionView = tx.clone().into();
+
+    let unconfigured = RpcClient::new();
+    assert!(matches!(
+        unconfigured.verify_tx(json_tx.clone()),
+        Err(ProviderError::NoNodeUrl)
+    ));
+
+    let provider = RpcClient::with_node_url(server.url());
+    assert!(provider.verify_tx(json_tx.clone()).unwrap() > 0);
+    let hash = provider.send_tx(json_tx.clone()).unwrap();
+    assert_eq!(Byte32::from(hash), tx.hash());
+
+    match provider.send_tx(json_tx) {
+        Err(ProviderError::Rejected(e)) => {
+            assert_eq!(e.code(), -301);
+            assert!(e.message().contains("TransactionFailedToResolve"));
+        }
+        res => panic!("Expected a rejected transaction, got {:?}", res),
+    }
+    server.close();
+}
+
+#[test]
+fn test_mine_exact_number_of_blocks() {
+    let (server, _, _) = start_server();
+    let url = server.url();
+    let client = RpcClient::new();
+
+    let start: u64 = client.get_tip_block_number(url.as_str()).unwrap().into();
+    let hashes = client.mine_blocks(3, url.as_str()).unwrap();
+    assert_eq!(hashes.len(), 3);
+
+    let tip = client.get_tip_header(url.as_str()).unwrap();
+    assert_eq!(u64::from(tip.inner.number), start + 3);
+    assert_eq!(&tip.hash, hashes.last().unwrap());
+    for (offset, hash) in hashes.iter().enumerate() {
+        let number = start + 1 + offset as u64;
+        assert_eq!(
+            client.get_block_hash(number.into(), url.as_str()).unwrap(),
+            Some(hash.clone())
+        );
+    }
+    server.close();
+}
@@ -0,0 +1,128 @@
+use ckb_script::ScriptGroupType;
+use jsonrpc_core::{Error, ErrorCode};
+use trampoline_sdk::chain::ScriptCell;
+use trampoline_sdk::ckb_types::H256;
+use trampoline_sdk::rpc::{CkbErrorCode, CkbRpcError, ResolveFailure, RpcError};
+
+const TX_HASH: &str = "0x8277d74d33850581f8d843613ded0c2a1722dec0e87e748f45c115dfb14210f1";
+
+fn node_error(code: i64, message: &str) -> CkbRpcError {
+    Error {
+        code: ErrorCode::ServerError(code),
+        message: message.to_owned(),
+        data: None,
+    }
+    .into()
+}
+
+#[test]
+fn test_parses_verification_failures() {
+    let e = node_error(
+        -302,
+        "TransactionFailedToVerify: Verification failed Script(TransactionScriptError { \
+         source: Inputs[1].Lock, cause: ValidationFailure: see the error code -31 in the page \
+         https://nervosnetwork.github.io/ckb-script-error-codes/by-type-hash/9bd7e06f.html#-31 })",
+    );
+    match &e {
+        CkbRpcError::TransactionFailedToVerify {
+            group_type,
+            cell,
+            exit_code,
+            ..
+        } => {
+            assert_eq!(*group_type, Some(ScriptGroupType::Lock));
+            assert_eq!(*cell, Some(ScriptCell::Input(1)));
+            assert_eq!(*exit_code, Some(-31));
+        }
+        e => panic!("Expected a verification failure, got {:?}", e),
+    }
+    assert!(e.is_rejection());
+    assert!(!e.is_transient());
+
+    let e = node_error(
+        -302,
+        "TransactionFailedToVerify: Verification failed Script(TransactionScriptError { \
+         source: Outputs[0].Type, cause: ExceededMaximumCycles: expect cycles <= 70000000 })",
+    );
+    assert!(matches!(
+        e,
+        CkbRpcError::TransactionFailedToVerify {
+            cell: Some(ScriptCell::Output(0)),
+            exit_code: None,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_parses_pool_rejections() {
+    let e = node_error(
+        -1104,
+        "PoolRejectedTransactionByMinFeeRate: The min fee rate is 1000 shannons/KB, \
+         so the transaction fee should be 242 shannons at least, but only got 100",
+    );
+    assert_eq!(
+        e,
+        CkbRpcError::PoolRejectedLowFeeRate {
+            message: e.message().to_owned(),
+            min_fee_rate: Some(1000),
+            min_fee: Some(242),
+            fee: Some(100),
+        }
+    );
+
+    let e = node_error(
+        -1107,
+        &format!(
+            "PoolRejectedDuplicatedTransaction: Transaction(Byte32({})) already exist in transaction_pool",
+            TX_HASH
+        ),
+    );
+    let tx_hash: H256 = serde_json::from_value(serde_json::json!(TX_HASH)).unwrap();
+    assert!(matches!(
+        &e,
+        CkbRpcError::PoolRejectedDuplicatedTransaction { tx_hash: Some(hash), .. } if *hash == tx_hash
+    ));
+    assert!(!e.is_transient());
+
+    let e = node_error(
+        -1106,
+        "PoolIsFull: Transaction pool exceeded maximum size or cycles limit",
+    );
+    assert!(e.is_rejection());
+    assert!(e.is_transient());
+
+    let e = node_error(
+        -301,
+        "TransactionFailedToResolve: Resolve failed Unknown(OutPoint(0x01))",
+    );
+    assert!(matches!(
+        e,
+        CkbRpcError::TransactionFailedToResolve {
+            failure: Some(ResolveFailure::Unknown),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_classifies_other_errors() {
+    let e = node_error(-101, "P2PFailedToBroadcast: no connected peers");
+    assert_eq!(
+        e,
+        CkbRpcError::Ckb {
+            code: CkbErrorCode::P2PFailedToBroadcast,
+            message: "P2PFailedToBroadcast: no connected peers".to_owned(),
+        }
+    );
+    assert!(e.is_transient());
+    assert!(!e.is_rejection());
+
+    let e: RpcError = Error::method_not_found().into();
+    assert!(matches!(&e, RpcError::Node(CkbRpcError::Other(_))));
+    assert!(!e.is_transient());
+    match e {
+        RpcError::Node(e) => assert_eq!(e.code(), -32601),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,218 @@
+use jsonrpc_core::{Error, IoHandler, Params, Value};
+use jsonrpc_http_server::ServerBuilder;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use trampoline_sdk::ckb_types::H256;
+use trampoline_sdk::rpc::{Order, RpcClient, RpcResult, SearchKey};
+
+// Each fixture under tests/fixtures/rpc records the params a node expects for a method
+// and the result it answered with. The stand-in node only replays the result when the
+// client sent exactly the recorded params.
+#[derive(Deserialize)]
+struct Fixture {
+    params: Value,
+    result: Value,
+}
+
+fn load_fixture(method: &str) -> Fixture {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/rpc")
+        .join(format!("{}.json", method));
+    let json = std::fs::read_to_string(&path).expect("Read fixture");
+    serde_json::from_str(&json).expect("Parse fixture")
+}
+
+fn fixture_value<T: for<'de> Deserialize<'de>>(method: &str, pointer: &str) -> T {
+    let value = load_fixture(method)
+        .params
+        .pointer(pointer)
+        .cloned()
+        .expect("Fixture param");
+    serde_json::from_value(value).expect("Typed fixture param")
+}
+
+// Fields a node leaves out deserialize to `None` and come back as `null`
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(strip_nulls).collect()),
+        value => value,
+    }
+}
+
+fn replay<T, F>(method: &str, call: F)
+where
+    T: Serialize,
+    F: FnOnce(&RpcClient, &str) -> RpcResult<T>,
+{
+    let fixture = load_fixture(method);
+    let (expected_params, result) = (fixture.params, fixture.result.clone());
+    let mut io = IoHandler::new();
+    io.add_sync_method(method, move |params: Params| {
+        let params = match params {
+            Params::None => Value::Array(vec![]),
+            Params::Array(values) => Value::Array(values),
+            Params::Map(map) => Value::Object(map),
+        };
+        if params == expected_params {
+            Ok(result.clone())
+        } else {
+            Err(Error::invalid_params(format!(
+                "expected {}, got {}",
+                expected_params, params
+            )))
+        }
+    });
+    let server = ServerBuilder::new(io)
+        .start_http(&"127.0.0.1:0".parse().unwrap())
+        .expect("Start stand-in node");
+    let url = format!("http://{}", server.address());
+
+    let typed = call(&RpcClient::new(), url.as_str())
+        .unwrap_or_else(|e| panic!("{} failed: {}", method, e));
+    assert_eq!(
+        strip_nulls(serde_json::to_value(typed).unwrap()),
+        strip_nulls(fixture.result),
+        "{} did not round-trip",
+        method
+    );
+    server.close();
+}
+
+// Walks a fixture and collects every value stored under a hash-like key
+fn hash_values<'a>(key: &str, value: &'a Value, found: &mut Vec<(String, &'a Value)>) {
+    let is_hash_key =
+        (key.ends_with("hash") || key.ends_with("_root") || key == "lemmas") && key != "hash_type";
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .for_each(|(k, v)| hash_values(k.as_str(), v, found)),
+        Value::Array(values) => values.iter().for_each(|v| hash_values(key, v, found)),
+        Value::String(_) if is_hash_key => found.push((key.to_string(), value)),
+        _ => {}
+    }
+}
+
+fn block_hash() -> H256 {
+    fixture_value("get_header", "/0")
+}
+
+fn tx_hash() -> H256 {
+    fixture_value("get_transaction", "/0")
+}
+
+fn search_key() -> SearchKey {
+    fixture_value("get_cells", "/0")
+}
+
+#[test]
+fn test_chain_methods() {
+    replay("get_tip_header", |c, url| c.get_tip_header(url));
+    replay("get_tip_block_number", |c, url| c.get_tip_block_number(url));
+    replay("get_header", |c, url| c.get_header(block_hash(), url));
+    replay("get_header_by_number", |c, url| {
+        c.get_header_by_number(0x1a2bu64.into(), url)
+    });
+    replay("get_block_hash", |c, url| {
+        c.get_block_hash(0x1a2bu64.into(), url)
+    });
+    replay("get_block", |c, url| c.get_block(block_hash(), url));
+    replay("get_block_by_number", |c, url| {
+        c.get_block_by_number(0x1a2bu64.into(), url)
+    });
+    replay("get_current_epoch", |c, url| c.get_current_epoch(url));
+    replay("get_epoch_by_number", |c, url| {
+        c.get_epoch_by_number(0u64.into(), url)
+    });
+    replay("get_transaction", |c, url| {
+        c.get_transaction(tx_hash(), url)
+    });
+    replay("get_live_cell", |c, url| {
+        c.get_live_cell(fixture_value("get_live_cell", "/0"), true, url)
+    });
+    replay("get_transaction_proof", |c, url| {
+        c.get_transaction_proof(vec![tx_hash()], None, url)
+    });
+    replay("verify_transaction_proof", |c, url| {
+        c.verify_transaction_proof(fixture_value("verify_transaction_proof", "/0"), url)
+    });
+}
+
+#[test]
+fn test_experiment_methods() {
+    replay("dry_run_transaction", |c, url| {
+        c.dry_run_transaction(fixture_value("dry_run_transaction", "/0"), url)
+    });
+    replay("estimate_cycles", |c, url| {
+        c.estimate_cycles(fixture_value("estimate_cycles", "/0"), url)
+    });
+}
+
+#[test]
+fn test_pool_methods() {
+    replay("send_transaction", |c, url| {
+        c.send_transaction(fixture_value("send_transaction", "/0"), url)
+    });
+    replay("tx_pool_info", |c, url| c.tx_pool_info(url));
+    replay("get_raw_tx_pool", |c, url| {
+        c.get_raw_tx_pool(Some(false), url)
+    });
+    replay("clear_tx_pool", |c, url| c.clear_tx_pool(url));
+}
+
+#[test]
+fn test_stats_and_net_methods() {
+    replay("get_blockchain_info", |c, url| c.get_blockchain_info(url));
+    replay("local_node_info", |c, url| c.local_node_info(url));
+    replay("get_peers", |c, url| c.get_peers(url));
+    replay("get_banned_addresses", |c, url| c.get_banned_addresses(url));
+    replay("set_network_active", |c, url| {
+        c.set_network_active(false, url)
+    });
+}
+
+#[test]
+fn test_indexer_methods() {
+    replay("get_tip", |c, url| c.get_indexer_tip(url));
+    replay("get_cells", |c, url| {
+        c.get_cells(search_key(), Order::Asc, 100u32.into(), None, url)
+    });
+    replay("get_transactions", |c, url| {
+        c.get_transactions(search_key(), Order::Desc, 100u32.into(), None, url)
+    });
+    replay("get_cells_capacity", |c, url| {
+        c.get_cells_capacity(search_key(), url)
+    });
+}
+
+#[test]
+fn test_fixture_hashes_are_32_bytes() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/rpc");
+    for entry in std::fs::read_dir(&dir).expect("Read fixture dir") {
+        let path = entry.expect("Fixture entry").path();
+        let json = std::fs::read_to_string(&path).expect("Read fixture");
+        let fixture: Value = serde_json::from_str(&json).expect("Parse fixture");
+        let mut found = vec![];
+        hash_values("", &fixture, &mut found);
+        for (key, value) in found {
+            assert!(
+                serde_json::from_value::<H256>(value.clone()).is_ok(),
+                "{}: {} is not a 32-byte hash: {}",
+                path.display(),
+                key,
+                value
+            );
+        }
+    }
+    // Hashes that are not stored under a hash-like key
+    let _: H256 = serde_json::from_value(load_fixture("get_block_hash").result)
+        .expect("get_block_hash result");
+    let _: Vec<H256> =
+        serde_json::from_value(load_fixture("get_raw_tx_pool").result["pending"].clone())
+            .expect("get_raw_tx_pool result");
+}
@@ -0,0 +1,89 @@
+use ckb_jsonrpc_types::{CellWithStatus, TransactionWithStatus};
+use trampoline_sdk::chain::{MockChain, MockChainRpcServer};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionBuilder},
+    packed::{CellInput, CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+use trampoline_sdk::rpc::{RpcClient, RpcError, RpcRecording};
+
+struct Session {
+    before: CellWithStatus,
+    hash: H256,
+    resent: String,
+    committed: Option<TransactionWithStatus>,
+    after: CellWithStatus,
+}
+
+// The calls a dapp makes around a transfer, against whatever `client` talks to
+fn transfer_session(client: &RpcClient, url: &str, chain: &MockChain, input: &OutPoint) -> Session {
+    let lock = chain
+        .build_script(&chain.get_default_script_outpoint(), vec![2u8].into())
+        .unwrap();
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(input.clone(), 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(1000).unwrap().pack())
+                .lock(lock.clone())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_dep(chain.find_cell_dep_for_script(&lock).unwrap())
+        .build();
+
+    let before = client
+        .get_live_cell(input.clone().into(), false, url)
+        .unwrap();
+    let hash = client.send_transaction(tx.data().into(), url).unwrap();
+    let resent = match client.send_transaction(tx.data().into(), url) {
+        Err(RpcError::Node(e)) => e.message().to_owned(),
+        res => panic!("Expected the resent transaction to fail, got {:?}", res),
+    };
+    let committed = client.get_transaction(hash.clone(), url).unwrap();
+    let after = client
+        .get_live_cell(input.clone().into(), false, url)
+        .unwrap();
+    Session {
+        before,
+        hash,
+        resent,
+        committed,
+        after,
+    }
+}
+
+#[test]
+fn test_replays_recorded_session_without_node() {
+    let mut chain = MockChain::default();
+    let input = chain.deploy_random_cell_with_default_lock(2000, Some(vec![1u8].into()));
+    let path = std::env::temp_dir().join(format!("rpc_recording_{}.json", std::process::id()));
+
+    let server = MockChainRpcServer::start(chain.clone(), &"127.0.0.1:0".parse().unwrap()).unwrap();
+    let recorder = RpcClient::new().recording(&path);
+    let recorded = transfer_session(&recorder, server.url().as_str(), &chain, &input);
+    server.close();
+    assert_eq!(RpcRecording::load_from(&path).unwrap().calls.len(), 5);
+
+    // Nothing listens on the url anymore, every answer comes from the recording
+    let replayer = RpcClient::new().replaying(&path).unwrap();
+    let replayed = transfer_session(&replayer, "http://127.0.0.1:1", &chain, &input);
+    assert_eq!(replayed.hash, recorded.hash);
+    assert_eq!(replayed.resent, recorded.resent);
+    assert_eq!(replayed.before.status, "live");
+    assert_eq!(replayed.after.status, recorded.after.status);
+    assert_ne!(replayed.after.status, "live");
+    assert_eq!(
+        replayed.committed.unwrap().tx_status.block_hash,
+        recorded.committed.unwrap().tx_status.block_hash
+    );
+
+    // Each recorded answer is served once
+    match replayer.get_tip_block_number("http://127.0.0.1:1") {
+        Err(RpcError::NotRecorded { method, .. }) => assert_eq!(method, "get_tip_block_number"),
+        res => panic!("Expected an unrecorded call, got {:?}", res),
+    }
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,143 @@
+use futures_util::SinkExt;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+use trampoline_sdk::rpc::{RpcError, SubscriptionClient, SubscriptionEvent, Topic};
+
+fn recorded_header() -> Value {
+    let path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/rpc/get_tip_header.json");
+    let fixture: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    fixture["result"].clone()
+}
+
+// Answers the subscription request of a client and publishes one tip header
+async fn accept_subscriber(listener: &TcpListener, header: &Value) -> TcpStream {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    stream.read_line(&mut line).await.unwrap();
+    let request: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(request["method"], "subscribe");
+    assert_eq!(request["params"], json!(["new_tip_header"]));
+
+    let response = json!({"jsonrpc": "2.0", "result": "0xa", "id": request["id"]});
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "subscribe",
+        "params": {"result": header.to_string(), "subscription": "0xa"},
+    });
+    let mut stream = stream.into_inner();
+    for message in [response, notification] {
+        stream
+            .write_all(format!("{}\n", message).as_bytes())
+            .await
+            .unwrap();
+    }
+    stream
+}
+
+#[tokio::test]
+async fn test_delivers_events_and_reconnects() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let header = recorded_header();
+    let (done, finished) = oneshot::channel::<()>();
+    let node = tokio::spawn({
+        let header = header.clone();
+        async move {
+            // The first connection is dropped right after publishing
+            drop(accept_subscriber(&listener, &header).await);
+            let _kept = accept_subscriber(&listener, &header).await;
+            finished.await.ok();
+        }
+    });
+
+    let mut events = SubscriptionClient::new(addr.to_string())
+        .topic(Topic::NewTipHeader)
+        .reconnect_backoff(Duration::from_millis(10), Duration::from_millis(50))
+        .subscribe()
+        .await
+        .unwrap();
+
+    let expected_hash = header["hash"].clone();
+    match events.next().await.unwrap().unwrap() {
+        SubscriptionEvent::NewTipHeader(tip) => assert_eq!(json!(tip.hash), expected_hash),
+        event => panic!("Expected a tip header, got {:?}", event),
+    }
+    assert!(matches!(
+        events.next().await.unwrap().unwrap(),
+        SubscriptionEvent::Reconnected
+    ));
+    match events.next().await.unwrap().unwrap() {
+        SubscriptionEvent::NewTipHeader(tip) => assert_eq!(json!(tip.hash), expected_hash),
+        event => panic!("Expected a tip header, got {:?}", event),
+    }
+
+    done.send(()).unwrap();
+    node.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_subscribes_over_websocket() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let header = recorded_header();
+    let (done, finished) = oneshot::channel::<()>();
+    let node = tokio::spawn({
+        let header = header.clone();
+        async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let request = match ws.next().await.unwrap().unwrap() {
+                Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+                message => panic!("Expected a text message, got {:?}", message),
+            };
+            assert_eq!(request["method"], "subscribe");
+            assert_eq!(request["params"], json!(["new_tip_header"]));
+
+            let response = json!({"jsonrpc": "2.0", "result": "0xb", "id": request["id"]});
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "subscribe",
+                "params": {"result": header.to_string(), "subscription": "0xb"},
+            });
+            for message in [response, notification] {
+                ws.send(Message::Text(message.to_string())).await.unwrap();
+            }
+            finished.await.ok();
+        }
+    });
+
+    let mut events = SubscriptionClient::new(format!("ws://{}", addr))
+        .topic(Topic::NewTipHeader)
+        .subscribe()
+        .await
+        .unwrap();
+    match events.next().await.unwrap().unwrap() {
+        SubscriptionEvent::NewTipHeader(tip) => assert_eq!(json!(tip.hash), header["hash"]),
+        event => panic!("Expected a tip header, got {:?}", event),
+    }
+
+    done.send(()).unwrap();
+    node.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reports_unreachable_node() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let result = SubscriptionClient::new(addr.to_string())
+        .topic(Topic::NewTipBlock)
+        .subscribe()
+        .await;
+    assert!(matches!(result, Err(RpcError::Io(_))));
+}
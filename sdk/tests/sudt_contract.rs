@@ -2,14 +2,20 @@ extern crate ckb_always_success_script;
 extern crate trampoline_sdk;
 
 use ckb_types::packed::CellOutputBuilder;
-use trampoline_sdk::chain::{MockChain, MockChainTxProvider as ChainRpc};
+use trampoline_sdk::account::{sighash_lock_args, sighash_signer};
+use trampoline_sdk::chain::{
+    MockChain, MockChainError, MockChainTxProvider as ChainRpc, ScriptCell,
+};
 use trampoline_sdk::contract::*;
 use trampoline_sdk::contract::{builtins::sudt::*, generator::*, schema::*};
 
 use ckb_types::{
     bytes::Bytes,
-    core::{TransactionBuilder, TransactionView},
-    packed::{Byte32, CellOutput},
+    core::{
+        cell::{CellMeta, CellMetaBuilder},
+        Capacity, TransactionBuilder, TransactionView,
+    },
+    packed::{Byte32, CellInput, CellOutput, OutPoint, WitnessArgs},
     prelude::*,
     H256,
 };
@@ -24,6 +30,9 @@ use std::sync::{Arc, Mutex};
 const EXPECTED_SUDT_HASH: &str =
     "0xe1e354d6d643ad42724d40967e334984534e0367405c5ae42a9d7d63d77df419";
 
+// simple_udt exit code for outputs exceeding inputs without owner mode
+const ERROR_AMOUNT: i8 = -52;
+
 fn gen_sudt_contract(
     minter_lock: Option<ckb_types::packed::Script>,
     initial_supply: Option<u128>,
@@ -110,6 +119,16 @@ fn generate_mock_tx(
         .build()
 }
 
+// A resolved sudt cell holding 1000 tokens at a made up out point
+fn generate_resolved_udt_cell(sudt_contract: &SudtContract, tx_byte: u8) -> CellMeta {
+    CellMetaBuilder::from_cell_output(
+        generate_simple_udt_cell(sudt_contract),
+        1000_u128.to_le_bytes().to_vec().into(),
+    )
+    .out_point(OutPoint::new(Byte32::new([tx_byte; 32]), 0))
+    .build()
+}
+
 #[test]
 fn test_failed_issuance_tx_no_permissions() {
     let mut chain = MockChain::default();
@@ -172,9 +191,18 @@ fn test_failed_issuance_tx_no_permissions() {
         .pipeline(vec![&sudt_contract]);
 
     let new_fail_tx = generator.generate(); //generator.pipe(fail_tx, Arc::new(Mutex::new(vec![])));
-                                            // Test that failure transaction failed
-    let is_valid = chain_rpc.verify_tx(new_fail_tx.tx.into());
-    assert!(!is_valid);
+
+    // Test that failure transaction failed: the sUDT type script only appears in the
+    // output and rejects the unauthorized mint
+    match chain_rpc.verify_tx(new_fail_tx.tx.into()) {
+        Err(ProviderError::MockChain(MockChainError::Script {
+            cell, exit_code, ..
+        })) => {
+            assert_eq!(cell, ScriptCell::Output(0));
+            assert_eq!(exit_code, Some(ERROR_AMOUNT));
+        }
+        other => panic!("expected sUDT amount error, got {:?}", other),
+    }
 }
 
 #[test]
@@ -250,8 +278,128 @@ fn test_sudt_issuance_tx_with_contract_pipeline() {
     let new_tx_amt: u128 = sudt_contract.read_raw_data(new_tx_amt).to_mol().unpack();
     assert_eq!(new_tx_amt, 2000_u128);
 
-    let is_valid = chain_rpc.verify_tx(new_tx.tx.into());
-    assert!(is_valid);
+    chain_rpc.verify_tx(new_tx.tx.into()).unwrap();
+}
+
+#[test]
+fn test_sudt_issuance_tx_balanced_with_change() {
+    let mut chain = MockChain::default();
+
+    let minter_lock_cell = chain.get_default_script_outpoint();
+    let minter_lock_script = chain.build_script(&minter_lock_cell, vec![1_u8].into());
+    let minter_lock_hash = minter_lock_script.clone().unwrap().calc_script_hash();
+    let change_lock_script = chain
+        .build_script(&minter_lock_cell, vec![9_u8].into())
+        .unwrap();
+    chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(50).unwrap().pack())
+            .lock(minter_lock_script.clone().unwrap())
+            .build(),
+        Default::default(),
+    );
+    chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(100).unwrap().pack())
+            .lock(change_lock_script.clone())
+            .build(),
+        Default::default(),
+    );
+
+    let mut sudt_contract = gen_sudt_contract(minter_lock_script, Some(1500));
+    let sudt_code_cell = sudt_contract.as_code_cell();
+    let sudt_code_cell_outpoint = chain.create_cell(sudt_code_cell.0, sudt_code_cell.1);
+    sudt_contract.source = Some(ContractSource::Chain(sudt_code_cell_outpoint.into()));
+    sudt_contract.add_input_rule(move |_tx| -> CellQuery {
+        CellQuery {
+            _query: QueryStatement::Single(CellQueryAttribute::LockHash(
+                minter_lock_hash.clone().into(),
+            )),
+            _limit: 1,
+        }
+    });
+
+    let chain_rpc = ChainRpc::new(chain);
+    let generator = Generator::new()
+        .chain_service(&chain_rpc)
+        .query_service(&chain_rpc)
+        .fee_rate(1000)
+        .change_lock(change_lock_script.clone())
+        .pipeline(vec![&sudt_contract]);
+
+    let new_tx = generator.generate();
+
+    // The minter cell alone cannot pay for a change cell, so the change lock's cell is pulled in
+    assert_eq!(new_tx.tx.inputs().len(), 2);
+    assert_eq!(new_tx.tx.outputs().len(), 2);
+    let change = new_tx.tx.output(1).unwrap();
+    assert_eq!(change.lock(), change_lock_script);
+
+    let inputs_capacity: u64 = new_tx
+        .inputs
+        .iter()
+        .map(|cell| cell.capacity().as_u64())
+        .sum();
+    let outputs_capacity = new_tx.tx.outputs_capacity().unwrap().as_u64();
+    let fee = inputs_capacity - outputs_capacity;
+    let tx_size = new_tx.tx.data().serialized_size_in_block() as u64;
+    // At 1000 shannons/KB the fee is exactly one shannon per byte
+    assert_eq!(fee, tx_size);
+    assert_eq!(new_tx.tx.witnesses().len(), 2);
+
+    chain_rpc.verify_tx(new_tx.tx.into()).unwrap();
+}
+
+#[test]
+fn test_sudt_issuance_tx_signed_with_sighash_lock() {
+    let mut chain = MockChain::default();
+
+    let (sighash_code_cell, secp_data_cell) = chain.deploy_secp256k1_sighash_all();
+    let minter_key = secp256k1::SecretKey::from_slice(&[7u8; 32]).unwrap();
+    let minter_lock_script = chain.build_script(&sighash_code_cell, sighash_lock_args(&minter_key));
+    let minter_lock_hash = minter_lock_script.clone().unwrap().calc_script_hash();
+    chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(200).unwrap().pack())
+            .lock(minter_lock_script.clone().unwrap())
+            .build(),
+        Default::default(),
+    );
+
+    let mut sudt_contract = gen_sudt_contract(minter_lock_script, Some(1500));
+    let sudt_code_cell = sudt_contract.as_code_cell();
+    let sudt_code_cell_outpoint = chain.create_cell(sudt_code_cell.0, sudt_code_cell.1);
+    sudt_contract.source = Some(ContractSource::Chain(sudt_code_cell_outpoint.into()));
+    sudt_contract.add_input_rule(move |_tx| -> CellQuery {
+        CellQuery {
+            _query: QueryStatement::Single(CellQueryAttribute::LockHash(
+                minter_lock_hash.clone().into(),
+            )),
+            _limit: 1,
+        }
+    });
+
+    let signer = sighash_signer(vec![minter_key]);
+    let chain_rpc = ChainRpc::new(chain);
+    let generator = Generator::new()
+        .chain_service(&chain_rpc)
+        .query_service(&chain_rpc)
+        .cell_dep(
+            ckb_types::packed::CellDep::new_builder()
+                .out_point(secp_data_cell)
+                .build(),
+        )
+        .signer(&signer)
+        .pipeline(vec![&sudt_contract]);
+
+    let new_tx = generator.generate();
+    let witness =
+        WitnessArgs::from_slice(&new_tx.tx.witnesses().get(0).unwrap().raw_data()).unwrap();
+    let signature = witness.lock().to_opt().unwrap().raw_data();
+    assert_eq!(signature.len(), 65);
+    assert_ne!(signature.as_ref(), [0u8; 65].as_ref());
+
+    chain_rpc.verify_tx(new_tx.tx.into()).unwrap();
 }
 
 #[test]
@@ -302,6 +450,195 @@ fn test_update_sudt_with_rule_pipeline() {
     let new_tx_amt: u128 = sudt_contract.read_raw_data(new_tx_amt).into();
     assert_eq!(new_tx_amt, 2037_u128);
 }
+#[test]
+fn test_update_sudt_cell_fields_with_rule_pipeline() {
+    let mut sudt_contract = gen_sudt_contract(None, None);
+    let sudt_cell = generate_simple_udt_cell(&sudt_contract);
+    let transaction = generate_mock_tx(vec![sudt_cell], vec![2000_u128.to_le_bytes().pack()]);
+
+    let recipient_lock = generate_always_success_lock(Some(vec![7_u8].pack()));
+    let expected_lock = recipient_lock.clone();
+
+    // Re-lock the output to the recipient
+    sudt_contract.add_output_rule(
+        ContractField::LockScript,
+        move |_ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            ContractCellField::LockScript(recipient_lock.clone())
+        },
+    );
+
+    // Top up the capacity by 50 shannons
+    sudt_contract.add_output_rule(
+        ContractField::Capacity,
+        |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            let capacity: ContractCellField<OwnerLockHash, SudtAmount> =
+                ctx.load(ContractField::Capacity);
+            if let ContractCellField::Capacity(capacity) = capacity {
+                let capacity: u64 = capacity.unpack();
+                ContractCellField::Capacity((capacity + 50).pack())
+            } else {
+                capacity
+            }
+        },
+    );
+
+    // Change the owner lock hash stored in the sudt type script args
+    sudt_contract.add_output_rule(
+        ContractField::Args,
+        |_ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            ContractCellField::Args(OwnerLockHash::from_mol([1u8; 32].pack()))
+        },
+    );
+
+    // Args must be read back from the type script even though its hash has changed
+    sudt_contract.add_output_rule(
+        ContractField::Args,
+        |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            let args: ContractCellField<OwnerLockHash, SudtAmount> = ctx.load(ContractField::Args);
+            if let ContractCellField::Args(args) = &args {
+                assert_eq!(args.to_mol().as_slice(), [1u8; 32].as_ref());
+            }
+            args
+        },
+    );
+
+    let new_tx = sudt_contract.pipe(transaction.into(), Arc::new(Mutex::new(vec![])));
+
+    let (output, data) = new_tx.tx.output_with_data(0).unwrap();
+    let capacity: u64 = output.capacity().unpack();
+    assert_eq!(capacity, 150);
+    assert_eq!(output.lock(), expected_lock);
+    let type_args = output.type_().to_opt().unwrap().args().raw_data();
+    assert_eq!(type_args.as_ref(), [1u8; 32].as_ref());
+    let amount: u128 = sudt_contract.read_raw_data(data).into();
+    assert_eq!(amount, 2000_u128);
+}
+
+#[test]
+fn test_rules_after_args_rewrite_keep_to_matched_outputs() {
+    let mut sudt_contract = gen_sudt_contract(None, None);
+    let sudt_cell = generate_simple_udt_cell(&sudt_contract);
+    // An output of another token which already carries the owner the first rule sets
+    let other_type = sudt_cell
+        .type_()
+        .to_opt()
+        .unwrap()
+        .as_builder()
+        .args([1u8; 32].to_vec().pack())
+        .build();
+    let other_cell = sudt_cell
+        .clone()
+        .as_builder()
+        .type_(Some(other_type).pack())
+        .build();
+    let transaction = generate_mock_tx(
+        vec![sudt_cell, other_cell],
+        vec![
+            2000_u128.to_le_bytes().pack(),
+            500_u128.to_le_bytes().pack(),
+        ],
+    );
+    sudt_contract.output_count(0);
+
+    sudt_contract.add_output_rule(
+        ContractField::Args,
+        |_ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            ContractCellField::Args(OwnerLockHash::from_mol([1u8; 32].pack()))
+        },
+    );
+    sudt_contract.add_output_rule(
+        ContractField::Data,
+        |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            let amount: ContractCellField<OwnerLockHash, SudtAmount> =
+                ctx.load(ContractField::Data);
+            if let ContractCellField::Data(amount) = amount {
+                let amt: u128 = amount.into();
+                ContractCellField::Data(SudtAmount::from(amt + 1))
+            } else {
+                amount
+            }
+        },
+    );
+
+    let new_tx = sudt_contract.pipe(transaction.into(), Arc::new(Mutex::new(vec![])));
+
+    let amounts = new_tx
+        .tx
+        .outputs_with_data_iter()
+        .map(|(_, data)| sudt_contract.read_raw_data(data).into())
+        .collect::<Vec<u128>>();
+    assert_eq!(amounts, vec![2001, 500]);
+    let (output, _) = new_tx.tx.output_with_data(0).unwrap();
+    let type_args = output.type_().to_opt().unwrap().args().raw_data();
+    assert_eq!(type_args.as_ref(), [1u8; 32].as_ref());
+}
+
+#[test]
+fn test_split_sudt_cell_with_transaction_rule() {
+    let mut sudt_contract = gen_sudt_contract(None, None);
+    let sudt_cell = generate_simple_udt_cell(&sudt_contract);
+    let transaction = generate_mock_tx(vec![sudt_cell], vec![3000_u128.to_le_bytes().pack()]);
+    sudt_contract.output_count(0);
+
+    // Split every sudt output into three outputs carrying a third of the amount each
+    sudt_contract.add_output_rule(
+        TransactionField::Outputs,
+        |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            if let ContractCellField::Outputs(outputs) = ctx.load(TransactionField::Outputs) {
+                let split = outputs
+                    .into_iter()
+                    .flat_map(|(cell, data)| {
+                        let amount: u128 = SudtAmount::from_bytes(data).into();
+                        let part = SudtAmount::from(amount / 3).to_bytes();
+                        vec![(cell, part); 3]
+                    })
+                    .collect::<Vec<_>>();
+                ContractCellField::Outputs(split)
+            } else {
+                unreachable!()
+            }
+        },
+    );
+
+    // Add an extra cell dep
+    sudt_contract.add_output_rule(
+        TransactionField::Dependencies,
+        |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            if let ContractCellField::CellDeps(mut deps) = ctx.load(TransactionField::Dependencies)
+            {
+                deps.push(ckb_types::packed::CellDep::default());
+                ContractCellField::CellDeps(deps)
+            } else {
+                unreachable!()
+            }
+        },
+    );
+
+    // Cell-level rules run after the split see every new output
+    sudt_contract.add_output_rule(
+        ContractField::Data,
+        |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            let amount: ContractCellField<OwnerLockHash, SudtAmount> =
+                ctx.load(ContractField::Data);
+            if let ContractCellField::Data(amount) = amount {
+                let amt: u128 = amount.into();
+                ContractCellField::Data(SudtAmount::from(amt + 1))
+            } else {
+                amount
+            }
+        },
+    );
+
+    let new_tx = sudt_contract.pipe(transaction.into(), Arc::new(Mutex::new(vec![])));
+
+    assert_eq!(new_tx.tx.outputs().len(), 3);
+    assert_eq!(new_tx.tx.cell_deps().len(), 1);
+    new_tx.tx.outputs_with_data_iter().for_each(|(_, data)| {
+        let amount: u128 = sudt_contract.read_raw_data(data).into();
+        assert_eq!(amount, 1001_u128);
+    });
+}
+
 #[test]
 fn test_add_output_rule() {
     let mut sudt_contract = gen_sudt_contract(None, None);
@@ -367,3 +704,113 @@ fn test_data_hash_is_accurate() {
     let data_hash = sudt_contract.data_hash().unwrap().pack();
     assert_ne!(data_hash.as_slice(), cell_output.as_slice());
 }
+
+#[test]
+fn test_resolved_inputs_rule_keeps_since_of_existing_inputs() {
+    let mut sudt_contract = gen_sudt_contract(None, None);
+    let locked = generate_resolved_udt_cell(&sudt_contract, 1);
+    let added = generate_resolved_udt_cell(&sudt_contract, 2);
+    let transaction = TransactionBuilder::default()
+        .input(CellInput::new(locked.out_point.clone(), 42))
+        .build();
+    let transaction = CellMetaTransaction::from(transaction).with_inputs(vec![locked]);
+    sudt_contract.output_count(0);
+
+    sudt_contract.add_output_rule(
+        TransactionField::ResolvedInputs,
+        move |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            if let ContractCellField::ResolvedInputs(mut cells) =
+                ctx.load(TransactionField::ResolvedInputs)
+            {
+                cells.push(added.clone());
+                ContractCellField::ResolvedInputs(cells)
+            } else {
+                unreachable!()
+            }
+        },
+    );
+
+    let new_tx = sudt_contract.pipe(transaction, Arc::new(Mutex::new(vec![])));
+    let since = new_tx
+        .tx
+        .inputs()
+        .into_iter()
+        .map(|input| input.since().unpack())
+        .collect::<Vec<u64>>();
+    assert_eq!(since, vec![42, 0]);
+    assert_eq!(new_tx.inputs.len(), 2);
+}
+
+// A contract whose Inputs rule adds an input spending `added`
+fn contract_adding_input(added: OutPoint) -> (SudtContract, CellMetaTransaction) {
+    let mut sudt_contract = gen_sudt_contract(None, None);
+    let cell = generate_resolved_udt_cell(&sudt_contract, 1);
+    let transaction = TransactionBuilder::default()
+        .input(CellInput::new(cell.out_point.clone(), 0))
+        .build();
+    let transaction = CellMetaTransaction::from(transaction).with_inputs(vec![cell]);
+    sudt_contract.output_count(0);
+
+    sudt_contract.add_output_rule(
+        TransactionField::Inputs,
+        move |ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            if let ContractCellField::Inputs(mut inputs) = ctx.load(TransactionField::Inputs) {
+                inputs.push(CellInput::new(added.clone(), 0));
+                ContractCellField::Inputs(inputs)
+            } else {
+                unreachable!()
+            }
+        },
+    );
+    (sudt_contract, transaction)
+}
+
+#[test]
+fn test_inputs_rule_resolves_added_inputs() {
+    let mut chain = MockChain::default();
+    let added = chain.deploy_random_cell_with_default_lock(1000, None);
+    let chain_rpc = ChainRpc::new(chain);
+    let (sudt_contract, transaction) = contract_adding_input(added.clone());
+
+    let new_tx = sudt_contract
+        .try_pipe(transaction, Arc::new(Mutex::new(vec![])), Some(&chain_rpc))
+        .unwrap();
+    assert_eq!(new_tx.tx.inputs().len(), 2);
+    assert_eq!(new_tx.inputs.len(), 2);
+    assert_eq!(new_tx.inputs[1].out_point, added);
+    assert_eq!(new_tx.inputs[1].capacity(), Capacity::bytes(1000).unwrap());
+}
+
+#[test]
+fn test_inputs_rule_fails_on_unresolvable_inputs() {
+    let dead = OutPoint::new(Byte32::new([3; 32]), 0);
+    let (sudt_contract, transaction) = contract_adding_input(dead.clone());
+    assert!(matches!(
+        sudt_contract.try_pipe(transaction.clone(), Arc::new(Mutex::new(vec![])), None),
+        Err(GeneratorError::NoQueryService)
+    ));
+
+    let chain_rpc = ChainRpc::new(MockChain::default());
+    match sudt_contract.try_pipe(transaction, Arc::new(Mutex::new(vec![])), Some(&chain_rpc)) {
+        Err(GeneratorError::Provider(ProviderError::DeadCell(out_point))) => {
+            assert_eq!(out_point, dead)
+        }
+        res => panic!("Expected a dead cell error, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_rule_returning_field_outside_scope_fails() {
+    let mut sudt_contract = gen_sudt_contract(None, None);
+    sudt_contract.add_output_rule(
+        ContractField::Data,
+        |_ctx| -> ContractCellField<OwnerLockHash, SudtAmount> {
+            ContractCellField::Capacity(0u64.pack())
+        },
+    );
+    let transaction = CellMetaTransaction::from(TransactionBuilder::default().build());
+    assert!(matches!(
+        sudt_contract.try_pipe(transaction, Arc::new(Mutex::new(vec![])), None),
+        Err(GeneratorError::RuleScopeMismatch)
+    ));
+}
@@ -5,12 +5,13 @@ use ckb_hash::blake2b_256;
 use trampoline_sdk::ckb_types::packed::{CellInput, CellOutput};
 
 use ckb_jsonrpc_types::JsonBytes;
-use trampoline_sdk::chain::{MockChain, MockChainTxProvider as ChainRpc};
+use trampoline_sdk::chain::{
+    MockChain, MockChainError, MockChainTxProvider as ChainRpc, ScriptCell,
+};
 use trampoline_sdk::ckb_types::{
     self,
     bytes::Bytes,
     core::{TransactionBuilder, TransactionView},
-    error::Error,
     packed::*,
     prelude::*,
     H256,
@@ -25,14 +26,16 @@ use trampoline_sdk::contract::{schema::*, ContractSource};
 
 // ALSO: Make generator pipeline able to handle empty data so it doesn't have to be set
 
-fn _assert_script_error(err: Error, err_code: i8) {
-    let error_string = err.to_string();
-    assert!(
-        error_string.contains(format!("error code {} ", err_code).as_str()),
-        "error_string: {}, expected_error_code: {}",
-        error_string,
-        err_code
-    );
+fn _assert_script_error(err: ProviderError, err_code: i8) {
+    match err {
+        ProviderError::MockChain(MockChainError::Script { exit_code, .. }) => {
+            assert_eq!(exit_code, Some(err_code))
+        }
+        other => panic!(
+            "expected script error with code {}, got: {}",
+            err_code, other
+        ),
+    }
 }
 
 fn _generate_always_success_lock(
@@ -147,8 +150,7 @@ fn test_success_deploy() {
         .query_service(&chain_rpc)
         .pipeline(vec![&tnft_contract]);
     let new_mint_tx = generator.generate(); //generator.pipe(tx_skeleton, Arc::new(Mutex::new(vec![])));
-    let is_valid = chain_rpc.verify_tx(new_mint_tx.tx.into());
-    assert!(is_valid);
+    chain_rpc.verify_tx(new_mint_tx.tx.into()).unwrap();
 }
 
 #[test]
@@ -199,8 +201,17 @@ fn test_invalid_mismatched_genesis_id() {
         .query_service(&chain_rpc)
         .pipeline(vec![&tnft_contract]);
     let new_mint_tx = generator.generate(); //generator.pipe(tx_skeleton, Arc::new(Mutex::new(vec![])));
-    let is_valid = chain_rpc.verify_tx(new_mint_tx.tx.into());
-    assert!(!is_valid);
+
+    // The minted NFT is the only cell carrying the type script
+    match chain_rpc.verify_tx(new_mint_tx.tx.into()) {
+        Err(ProviderError::MockChain(MockChainError::Script {
+            cell, exit_code, ..
+        })) => {
+            assert_eq!(cell, ScriptCell::Output(0));
+            assert!(exit_code.is_some());
+        }
+        other => panic!("expected genesis id mismatch error, got {:?}", other),
+    }
 }
 
 // TO DO: Finish Test; currently builds a tx identical to mint
@@ -269,6 +280,5 @@ fn test_invalid_mint_of_pre_existing_tnft() {
         .query_service(&chain_rpc)
         .pipeline(vec![&tnft_contract]);
     let new_mint_tx = generator.generate(); //generator.pipe(tx_skeleton, Arc::new(Mutex::new(vec![])));
-    let is_valid = chain_rpc.verify_tx(new_mint_tx.tx.into());
-    assert!(is_valid);
+    chain_rpc.verify_tx(new_mint_tx.tx.into()).unwrap();
 }
@@ -0,0 +1,196 @@
+use ckb_always_success_script::ALWAYS_SUCCESS;
+use ckb_jsonrpc_types::ScriptHashType;
+use trampoline_sdk::chain::{MockChain, MockChainError, MockChainTxProvider};
+use trampoline_sdk::ckb_types::{
+    bytes::Bytes,
+    core::{cell::CellMetaBuilder, Capacity, TransactionBuilder, TransactionView},
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use trampoline_sdk::contract::builtins::sudt::SudtContract;
+use trampoline_sdk::contract::deploy::{DeployError, Deployment};
+use trampoline_sdk::contract::generator::{CellMetaTransaction, Generator, GeneratorMiddleware};
+use trampoline_sdk::contract::type_id::{type_id_args, type_id_script, TypeIdMiddleware};
+
+fn always_success_lock(chain: &MockChain, args: u8) -> Script {
+    chain
+        .build_script(&chain.get_default_script_outpoint(), vec![args].into())
+        .unwrap()
+}
+
+// Deploys the always success script with a Type ID, leaving the deployer enough capacity
+// for upgrades
+fn deploy_with_type_id() -> (MockChainTxProvider, Script, Deployment) {
+    let mut chain = MockChain::default();
+    let deployer = always_success_lock(&chain, 1);
+    chain.deploy_random_cell_with_default_lock(10_000, Some(vec![1_u8].into()));
+    let provider = MockChainTxProvider::new(chain);
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(deployer.clone());
+    let deployment = Deployment::deploy(
+        Bytes::from_static(ALWAYS_SUCCESS),
+        deployer.clone(),
+        true,
+        &generator,
+        &provider,
+    )
+    .unwrap();
+    (provider, deployer, deployment)
+}
+
+// Spends a cell locked by `lock`, whose code is found through the chain's cell dep lookup
+fn spend_cell_locked_by(chain: &mut MockChain, lock: Script) -> TransactionView {
+    let input = chain.create_cell(
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(500).unwrap().pack())
+            .lock(lock.clone())
+            .build(),
+        Bytes::new(),
+    );
+    TransactionBuilder::default()
+        .input(CellInput::new(input, 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(400).unwrap().pack())
+                .lock(always_success_lock(chain, 2))
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_dep(chain.find_cell_dep_for_script(&lock).unwrap())
+        .build()
+}
+
+#[test]
+fn test_upgrade_keeps_code_hash() {
+    let (provider, deployer, deployment) = deploy_with_type_id();
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(deployer.clone());
+    let new_code = Bytes::from(vec![0u8; 128]);
+    let upgraded = deployment
+        .upgrade(new_code.clone(), &generator, &provider)
+        .unwrap();
+
+    assert_eq!(upgraded.code_hash, deployment.code_hash);
+    assert_eq!(upgraded.type_script, deployment.type_script);
+    assert_ne!(upgraded.out_point, deployment.out_point);
+    let chain = provider.chain.borrow();
+    assert!(!chain.is_live(&deployment.out_point.clone().into()));
+    let (output, data) = chain
+        .get_live_cell(&upgraded.out_point.clone().into())
+        .unwrap();
+    assert_eq!(data, new_code);
+    assert_eq!(output.lock(), deployer);
+
+    // Scripts referencing the code by type hash now load the upgraded cell
+    let script: Script = upgraded.script(Default::default()).into();
+    let dep = chain.find_cell_dep_for_script(&script).unwrap();
+    assert_eq!(dep.out_point(), OutPoint::from(upgraded.out_point));
+}
+
+#[test]
+fn test_upgrade_requires_type_id() {
+    let mut chain = MockChain::default();
+    let deployer = always_success_lock(&chain, 1);
+    chain.deploy_random_cell_with_default_lock(10_000, Some(vec![1_u8].into()));
+    let provider = MockChainTxProvider::new(chain);
+    let generator = Generator::new()
+        .query_service(&provider)
+        .change_lock(deployer.clone());
+    let deployment = Deployment::deploy(
+        Bytes::from_static(ALWAYS_SUCCESS),
+        deployer,
+        false,
+        &generator,
+        &provider,
+    )
+    .unwrap();
+    assert!(deployment.type_script.is_none());
+    assert!(matches!(
+        deployment.upgrade(Bytes::new(), &generator, &provider),
+        Err(DeployError::NotUpgradeable)
+    ));
+}
+
+#[test]
+fn test_contract_references_code_by_type_hash() {
+    let (provider, _, deployment) = deploy_with_type_id();
+    let contract = SudtContract::default().deployment(deployment.clone());
+    let script = contract.as_script().unwrap();
+    assert_eq!(script.code_hash, deployment.code_hash);
+    assert_eq!(script.hash_type, ScriptHashType::Type);
+
+    let mut chain = provider.chain.borrow_mut();
+    let tx = spend_cell_locked_by(&mut chain, script.into());
+    chain.receive_tx(&tx).unwrap();
+}
+
+#[test]
+fn test_mock_chain_verifies_type_id() {
+    let mut chain = MockChain::default();
+    let lock = always_success_lock(&chain, 1);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let type_id_tx = |args: Bytes| {
+        TransactionBuilder::default()
+            .input(CellInput::new(input.clone(), 0))
+            .output(
+                CellOutput::new_builder()
+                    .capacity(Capacity::bytes(1000).unwrap().pack())
+                    .lock(lock.clone())
+                    .type_(Some(type_id_script(args)).pack())
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .cell_dep(chain.find_cell_dep_for_script(&lock).unwrap())
+            .build()
+    };
+
+    let forged = type_id_tx(Bytes::from(vec![1u8; 32]));
+    match chain.verify_tx(&forged, 10_000_000) {
+        Err(MockChainError::Script { .. }) => {}
+        res => panic!("Expected the forged Type ID to fail, got {:?}", res),
+    }
+
+    let tx = type_id_tx(type_id_args(&CellInput::new(input.clone(), 0), 0));
+    chain.receive_tx(&tx).unwrap();
+}
+
+#[test]
+fn test_type_id_middleware_fills_created_type_ids() {
+    let mut chain = MockChain::default();
+    let lock = always_success_lock(&chain, 1);
+    let input = chain.deploy_random_cell_with_default_lock(1000, Some(vec![1_u8].into()));
+    let output = |type_: Script| {
+        CellOutput::new_builder()
+            .capacity(Capacity::bytes(100).unwrap().pack())
+            .lock(lock.clone())
+            .type_(Some(type_).pack())
+            .build()
+    };
+    let existing = type_id_script(Bytes::from(vec![7u8; 32]));
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(input.clone(), 0))
+        .output(output(existing.clone()))
+        .output(output(type_id_script(Bytes::new())))
+        .outputs_data(vec![Bytes::new().pack(); 2])
+        .build();
+    // Resolved as if the input carried the existing Type ID
+    let cell = CellMetaBuilder::from_cell_output(output(existing.clone()), Bytes::new())
+        .out_point(input.clone())
+        .build();
+    let tx = CellMetaTransaction::from(tx).with_inputs(vec![cell]);
+
+    let tx = TypeIdMiddleware.pipe(tx, Default::default());
+    let args = |idx| -> Bytes {
+        tx.tx
+            .output(idx)
+            .and_then(|output| output.type_().to_opt())
+            .unwrap()
+            .args()
+            .raw_data()
+    };
+    // The first output upgrades the Type ID carried by the input
+    assert_eq!(args(0), existing.args().raw_data());
+    assert_eq!(args(1), type_id_args(&CellInput::new(input, 0), 1));
+}
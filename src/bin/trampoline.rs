@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::str::FromStr;
 
 use anyhow::anyhow;
 use anyhow::Result;
@@ -11,15 +10,28 @@ use ckb_types::{h256, H256};
 use structopt::StructOpt;
 
 use trampoline::docker::*;
-use trampoline::opts::{NetworkCommands, SchemaCommand, TrampolineCommand};
+use trampoline::opts::{
+    ContractCommand, NetworkCommands, NetworkService, SchemaCommand, TrampolineCommand,
+};
 use trampoline::parse_hex;
 use trampoline::project::*;
-use trampoline::schema::{Schema, SchemaInitArgs};
+use trampoline::rpc_call;
+use trampoline::schema::{MolSchema, Schema, SchemaInitArgs};
 use trampoline::TrampolineResource;
 use trampoline::TrampolineResourceType;
+use trampoline_sdk::account::{sighash_lock_args, sighash_signer};
+use trampoline_sdk::contract::deploy::Deployment;
+use trampoline_sdk::contract::generator::Generator;
 use trampoline_sdk::rpc;
+use trampoline_sdk::rpc::IndexerQueryProvider;
+// JSON-RPC code of calls to methods the node does not serve
+const METHOD_NOT_FOUND: i64 = -32601;
 const SECP_TYPE_HASH: H256 =
     h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8");
+// Well-known key owning the genesis cells of dev chains, only usable on the local devnet
+const DEV_GENESIS_PRIVKEY: &str =
+    "d00c06bfd800d27397002dca6fb0993d5ba6399b4238b2f29ee9deb97593d2bc";
+const PRIVKEY_ENV_VAR: &str = "TRAMPOLINE_PRIVKEY";
 fn create_block_assembler_from_pkhash(hash: &[u8]) -> BlockAssemblerConfig {
     use ckb_jsonrpc_types::{JsonBytes, ScriptHashType};
     BlockAssemblerConfig {
@@ -32,6 +44,132 @@ fn create_block_assembler_from_pkhash(hash: &[u8]) -> BlockAssemblerConfig {
     }
 }
 
+fn print_service_status(
+    service: NetworkService,
+    state: &ContainerState,
+    url: Option<&str>,
+    tip: Option<u64>,
+) {
+    let mut line = format!("{:<8} {:<12}", service, state.to_string());
+    if let Some(url) = url {
+        line.push_str(&format!(" {:<24}", url));
+    }
+    if let Some(tip) = tip {
+        line.push_str(&format!(" tip #{}", tip));
+    }
+    println!("{}", line.trim_end());
+}
+
+fn mine_blocks(project: &TrampolineProject, blocks: u64) -> Result<()> {
+    let env = project.config.env.as_ref().unwrap();
+    let url = format!("{}:{}", env.chain.host, env.chain.host_port);
+    let hashes = rpc::RpcClient::new()
+        .mine_blocks(blocks, url)
+        .map_err(|e| match e {
+            rpc::RpcError::Node(e) if e.code() == METHOD_NOT_FOUND => anyhow!(
+                "The node does not serve `generate_block`. Add \"IntegrationTest\" to the rpc modules in {} and restart the network.",
+                project
+                    .path_to_ckb_config()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|_| "ckb.toml".to_string())
+            ),
+            e => e.into(),
+        })?;
+    for hash in hashes {
+        println!("Committed block {:#x}", hash);
+    }
+    Ok(())
+}
+
+// The deployer key, read from `privkey_file` or else from the TRAMPOLINE_PRIVKEY variable.
+// The dev genesis key is refused unless the chain is the local devnet.
+fn deployer_key(
+    env: &TrampolineEnv,
+    privkey_file: Option<&std::path::Path>,
+) -> Result<secp256k1::SecretKey> {
+    let privkey = match privkey_file {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => std::env::var(PRIVKEY_ENV_VAR).map_err(|_| {
+            anyhow!(
+                "No deployer key, pass --privkey-file or set {}",
+                PRIVKEY_ENV_VAR
+            )
+        })?,
+    };
+    let privkey = parse_hex(privkey.trim())?;
+    if privkey == parse_hex(DEV_GENESIS_PRIVKEY)? && !env.chain.is_local() {
+        return Err(anyhow!(
+            "Refusing to deploy to {} with the dev genesis key, which is only for the local devnet",
+            env.chain.host
+        ));
+    }
+    Ok(secp256k1::SecretKey::from_slice(&privkey)?)
+}
+
+// Deploys `binary` with a code cell locked by the secp256k1 sighash lock of `key`,
+// which also pays for it. With `upgrade` the code cell of the deployment recorded as `name`
+// is replaced instead.
+fn deploy_contract(
+    project: &TrampolineProject,
+    binary: &std::path::Path,
+    name: &str,
+    type_id: bool,
+    upgrade: bool,
+    key: secp256k1::SecretKey,
+) -> Result<Deployment> {
+    use ckb_types::{packed, prelude::*};
+    let env = project.config.env.as_ref().unwrap();
+    let node_url = format!("{}:{}", env.chain.host, env.chain.host_port);
+    let indexer_url = format!("{}:{}", env.indexer.host, env.indexer.host_port);
+
+    let lock = packed::Script::new_builder()
+        .code_hash(SECP_TYPE_HASH.pack())
+        .hash_type(ckb_types::core::ScriptHashType::Type.into())
+        .args(sighash_lock_args(&key).pack())
+        .build();
+
+    // The second genesis transaction holds the dep group of the sighash lock, which also
+    // carries the secp256k1 data the lock loads
+    let client = rpc::RpcClient::with_node_url(node_url.clone());
+    let genesis = client
+        .get_block_by_number(0u64.into(), node_url.as_str())?
+        .ok_or_else(|| anyhow!("Genesis block not found, is the network launched?"))?;
+    let (cellbase, dep_group_tx) = match genesis.transactions.as_slice() {
+        [cellbase, dep_group_tx, ..] => (cellbase, dep_group_tx),
+        _ => return Err(anyhow!("Genesis block has no dep group transaction")),
+    };
+    let secp_dep = packed::CellDep::new_builder()
+        .out_point(packed::OutPoint::new(dep_group_tx.hash.pack(), 0))
+        .dep_type(ckb_types::core::DepType::DepGroup.into())
+        .build();
+
+    // The sighash code cell is the second genesis output; registering it lets the generator
+    // look up the lock's code by type hash
+    let mut query_provider = IndexerQueryProvider::new(indexer_url);
+    query_provider.register_code_cell(packed::OutPoint::new(cellbase.hash.pack(), 1))?;
+    let previous = if upgrade {
+        let previous = project.deployment(name)?;
+        query_provider.register_deployment(&previous)?;
+        Some(previous)
+    } else {
+        None
+    };
+
+    let signer = sighash_signer(vec![key]);
+    let generator = Generator::new()
+        .query_service(&query_provider)
+        .change_lock(lock.clone())
+        .cell_dep(secp_dep)
+        .signer(&signer);
+    let code = std::fs::read(binary)?;
+    let deployment = match previous {
+        Some(previous) => previous.upgrade(code.into(), &generator, &client)?,
+        None => Deployment::deploy(code.into(), lock, type_id, &generator, &client)?,
+    };
+    project.record_deployment(name, &deployment)?;
+    Ok(deployment)
+}
+
 fn main() -> Result<()> {
     let opts = TrampolineCommand::from_args();
 
@@ -73,8 +211,76 @@ fn main() -> Result<()> {
                 }
             }
         }
-        TrampolineCommand::Network { command } => {
+        TrampolineCommand::Rpc {
+            method,
+            params,
+            indexer,
+            schema,
+            mol_type,
+        } => {
             let project = TrampolineProject::from(project?);
+            let env = project.config.env.as_ref().unwrap();
+            let service = if indexer || rpc_call::is_indexer_method(&method) {
+                &env.indexer
+            } else {
+                &env.chain
+            };
+            let url = format!("{}:{}", service.host, service.host_port);
+            let params: Vec<serde_json::Value> = params
+                .iter()
+                .map(|param| rpc_call::parse_param(param))
+                .collect();
+            let mut result: serde_json::Value = rpc::RpcClient::new().req(url, method, params)?;
+            if let Some(schema) = schema {
+                let path = project
+                    .root_dir
+                    .join("schemas")
+                    .join("mol")
+                    .join(format!("{}.mol", schema));
+                let mol_schema = MolSchema::load(path)?;
+                let type_name = match mol_type.as_deref().or_else(|| mol_schema.root_type()) {
+                    Some(type_name) => type_name.to_string(),
+                    None => return Err(anyhow!("Schema {} declares no types", schema)),
+                };
+                rpc_call::decode_cell_data(&mut result, &mol_schema, &type_name);
+            }
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        }
+        TrampolineCommand::Contract { command } => match command {
+            ContractCommand::Deploy {
+                binary,
+                name,
+                type_id,
+                upgrade,
+                privkey_file,
+            } => {
+                let project = TrampolineProject::from(project?);
+                let name = name
+                    .or_else(|| {
+                        binary
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                    })
+                    .ok_or_else(|| anyhow!("Cannot name the contract {}", binary.display()))?;
+                let key = deployer_key(
+                    project.config.env.as_ref().unwrap(),
+                    privkey_file.as_deref(),
+                )?;
+                let deployment = deploy_contract(&project, &binary, &name, type_id, upgrade, key)?;
+                println!(
+                    "Deployed {} at {:#x}:{}",
+                    name,
+                    deployment.out_point.tx_hash,
+                    u32::from(deployment.out_point.index)
+                );
+                println!(
+                    "code_hash {:#x} hash_type {:?}",
+                    deployment.code_hash, deployment.hash_type
+                );
+            }
+        },
+        TrampolineCommand::Network { command } => {
+            let mut project = TrampolineProject::from(project?);
             match command {
                 NetworkCommands::Launch {} => {
                     let image = DockerImage {
@@ -153,12 +359,16 @@ fn main() -> Result<()> {
                         .name(format!("{}-node", project.config.name.as_str()).as_str())
                         .restart()?;
                 }
-                NetworkCommands::Miner { one_block: _ } => {
+                NetworkCommands::Miner { one_block } => {
                     let config = project.load_ckb_config()?;
                     let block_assembler_args = config.block_assembler.as_ref();
                     if block_assembler_args.is_none() {
                         return Err(anyhow!("No miner address set. Refer to `trampoline net set-miner --help` for more information."));
                     }
+                    if one_block == Some(true) {
+                        mine_blocks(&project, 1)?;
+                        return Ok(());
+                    }
                     let container_name = project.config.name.as_str();
                     let miner_mount_path = &project.config.env.unwrap().miner.container_mount;
                     Docker::exec(
@@ -235,20 +445,145 @@ fn main() -> Result<()> {
                         "http://172.17.0.2:8114".into(),
                     ]))?;
                 }
-                NetworkCommands::Rpc { hash } => {
-                    let hash = H256::from_str(hash.as_str())?;
-                    let mut rpc_client = rpc::RpcClient::new();
-                    let url = format!(
-                        "{}:{}",
-                        project.config.env.as_ref().unwrap().chain.host,
-                        project.config.env.as_ref().unwrap().chain.host_port
+                NetworkCommands::Mine { blocks } => {
+                    let config = project.load_ckb_config()?;
+                    if config.block_assembler.is_none() {
+                        return Err(anyhow!("No miner address set. Refer to `trampoline net set-miner --help` for more information."));
+                    }
+                    mine_blocks(&project, blocks)?;
+                }
+                NetworkCommands::Config {
+                    service,
+                    port_host,
+                    host,
+                    local_binding,
+                } => {
+                    let mut env = project.config.env.clone().unwrap_or_default();
+                    let services = match service {
+                        // The miner runs in the node's container and shares its data
+                        NetworkService::Node => vec![&mut env.chain, &mut env.miner],
+                        NetworkService::Miner => vec![&mut env.miner],
+                        NetworkService::Indexer => vec![&mut env.indexer],
+                    };
+                    for (idx, virtual_env) in services.into_iter().enumerate() {
+                        if let Some(host) = host.as_ref() {
+                            virtual_env.host = host.clone();
+                        }
+                        if let Some(local_binding) = local_binding.as_ref() {
+                            virtual_env.local_binding = local_binding.clone();
+                        }
+                        // The miner's port is not bound, only the service's own port changes
+                        if idx == 0 {
+                            if let Some(port_host) = port_host {
+                                virtual_env.host_port = port_host;
+                            }
+                        }
+                    }
+                    project.save_env(env)?;
+                    let env = project.config.env.as_ref().unwrap();
+                    let virtual_env = match service {
+                        NetworkService::Node => &env.chain,
+                        NetworkService::Miner => &env.miner,
+                        NetworkService::Indexer => &env.indexer,
+                    };
+                    println!("Updated {} configuration\n{}", service, virtual_env);
+                    if Docker::default()
+                        .name(&project.node_container_name())
+                        .state()?
+                        != ContainerState::NotCreated
+                    {
+                        println!("Run `trampoline network stop` and `trampoline network launch` to apply it to the running network");
+                    }
+                }
+                NetworkCommands::Init { force } => {
+                    project.init_network(force)?;
+                    println!(
+                        "Network configuration created in {}",
+                        project.path_to_ckb_config()?.parent().unwrap().display()
                     );
-                    let result = rpc_client.get_transaction(hash, url)?;
-                    println!("Transaction with status: {}", serde_json::json!(result));
                 }
-                _ => {
-                    println!("Command not yet implemented!");
-                    std::process::exit(0);
+                NetworkCommands::Stop { service } => {
+                    let node = Docker::default().name(&project.node_container_name());
+                    let indexer = Docker::default().name(&project.indexer_container_name());
+                    match service {
+                        Some(NetworkService::Miner) => {
+                            if !node.exec_status(&["pkill", "-f", "ckb miner"])? {
+                                println!("Miner is not running");
+                            }
+                        }
+                        Some(NetworkService::Node) => node.stop()?,
+                        Some(NetworkService::Indexer) => indexer.stop()?,
+                        None => {
+                            for container in [indexer, node] {
+                                if container.state()? != ContainerState::NotCreated {
+                                    container.stop()?;
+                                }
+                            }
+                        }
+                    }
+                }
+                NetworkCommands::Status {} => {
+                    let env = project.config.env.as_ref().unwrap();
+                    let rpc_client = rpc::RpcClient::new();
+
+                    let node = Docker::default().name(&project.node_container_name());
+                    let node_state = node.state()?;
+                    let node_url = format!("{}:{}", env.chain.host, env.chain.host_port);
+                    let tip = rpc_client
+                        .get_tip_block_number(node_url.as_str())
+                        .ok()
+                        .map(|number| number.value());
+                    print_service_status(NetworkService::Node, &node_state, Some(&node_url), tip);
+
+                    let miner_state = if node_state == ContainerState::Running
+                        && node.exec_status(&["pgrep", "-f", "ckb miner"])?
+                    {
+                        ContainerState::Running
+                    } else {
+                        ContainerState::Stopped("stopped".to_string())
+                    };
+                    print_service_status(NetworkService::Miner, &miner_state, None, None);
+
+                    let indexer = Docker::default().name(&project.indexer_container_name());
+                    let indexer_url = format!("{}:{}", env.indexer.host, env.indexer.host_port);
+                    let indexer_tip = rpc_client
+                        .get_indexer_tip(indexer_url.as_str())
+                        .ok()
+                        .flatten()
+                        .map(|tip| tip.block_number.value());
+                    print_service_status(
+                        NetworkService::Indexer,
+                        &indexer.state()?,
+                        Some(&indexer_url),
+                        indexer_tip,
+                    );
+                }
+                NetworkCommands::Reset {} => {
+                    for name in [
+                        project.indexer_container_name(),
+                        project.node_container_name(),
+                    ] {
+                        let container = Docker::default().name(&name);
+                        if container.state()? != ContainerState::NotCreated {
+                            container.stop()?;
+                        }
+                    }
+                    project.reset_network_data()?;
+                    println!("Network data deleted, run `trampoline network launch` to start a new chain");
+                }
+                NetworkCommands::Logs {
+                    service,
+                    follow,
+                    tail,
+                } => {
+                    let name = match service {
+                        NetworkService::Node => project.node_container_name(),
+                        NetworkService::Indexer => project.indexer_container_name(),
+                        NetworkService::Miner => {
+                            return Err(anyhow!("The miner runs detached inside the node container and keeps no logs. Use `trampoline network logs node` to follow the blocks it produces."));
+                        }
+                    };
+                    Docker::default().name(&name).logs(follow, tail)?;
                 }
             }
         }
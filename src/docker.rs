@@ -486,6 +486,78 @@ impl Docker {
         Ok(())
     }
 
+    fn container_name(&self) -> &str {
+        self.name.as_deref().expect("Container name not set")
+    }
+
+    /// Stops and removes the container. Data in mounted volumes is kept.
+    pub fn stop(&self) -> DockerResult<()> {
+        for action in ["stop", "rm"] {
+            let status = Command::new(DOCKER_BIN)
+                .args(&["container", action, self.container_name()])
+                .stdout(Stdio::null())
+                .stderr(Stdio::inherit())
+                .status()?;
+            if !status.success() {
+                return Err(anyhow!(
+                    "docker container {} exited with status {:?}",
+                    action,
+                    status.code()
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> DockerResult<ContainerState> {
+        let output = Command::new(DOCKER_BIN)
+            .args(&[
+                "container",
+                "inspect",
+                "--format",
+                "{{.State.Status}}",
+                self.container_name(),
+            ])
+            .stdin(Stdio::null())
+            .output()?;
+        if output.status.success() {
+            Ok(ContainerState::from_inspect(&String::from_utf8_lossy(
+                &output.stdout,
+            )))
+        } else {
+            Ok(ContainerState::NotCreated)
+        }
+    }
+
+    /// Runs a command in the container, returning whether it succeeded.
+    pub fn exec_status(&self, exec_args: &[&str]) -> DockerResult<bool> {
+        let status = Command::new(DOCKER_BIN)
+            .arg("exec")
+            .arg(self.container_name())
+            .args(exec_args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        Ok(status.success())
+    }
+
+    /// Prints the container's logs, following new output if `follow` is set.
+    pub fn logs(&self, follow: bool, tail: Option<usize>) -> DockerResult<()> {
+        let status = Command::new(DOCKER_BIN)
+            .args(logs_args(self.container_name(), follow, tail))
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("docker logs exited with status {:?}", status.code()).into())
+        }
+    }
+
     pub fn restart(&self) -> DockerResult<()> {
         let mut cmd = Command::new(DOCKER_BIN);
         cmd.args(&["restart", self.name.as_ref().unwrap().as_str()]);
@@ -541,6 +613,46 @@ impl Docker {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerState {
+    Running,
+    /// Created but not running, with the status reported by docker (`exited`, `paused`, ...)
+    Stopped(String),
+    NotCreated,
+}
+
+impl ContainerState {
+    fn from_inspect(status: &str) -> Self {
+        match status.trim() {
+            "running" => ContainerState::Running,
+            status => ContainerState::Stopped(status.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerState::Running => write!(f, "running"),
+            ContainerState::Stopped(status) => write!(f, "{}", status),
+            ContainerState::NotCreated => write!(f, "not created"),
+        }
+    }
+}
+
+fn logs_args(container_name: &str, follow: bool, tail: Option<usize>) -> Vec<String> {
+    let mut args = vec!["container".to_string(), "logs".to_string()];
+    if follow {
+        args.push("--follow".to_string());
+    }
+    if let Some(tail) = tail {
+        args.push("--tail".to_string());
+        args.push(tail.to_string());
+    }
+    args.push(container_name.to_string());
+    args
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -711,4 +823,35 @@ mod tests {
             expected_docker_command.as_str()
         )
     }
+
+    #[test]
+    fn test_logs_args() {
+        assert_eq!(
+            logs_args("test-container", false, None),
+            vec!["container", "logs", "test-container"]
+        );
+        assert_eq!(
+            logs_args("test-container", true, Some(100)),
+            vec![
+                "container",
+                "logs",
+                "--follow",
+                "--tail",
+                "100",
+                "test-container"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_container_state_from_inspect() {
+        assert_eq!(
+            ContainerState::from_inspect("running\n"),
+            ContainerState::Running
+        );
+        assert_eq!(
+            ContainerState::from_inspect("exited\n"),
+            ContainerState::Stopped("exited".to_string())
+        );
+    }
 }
@@ -1,6 +1,7 @@
 pub mod docker;
 pub mod opts;
 pub mod project;
+pub mod rpc_call;
 pub mod schema;
 mod utils;
 
@@ -29,6 +29,36 @@ pub enum TrampolineCommand {
         #[structopt(flatten)]
         command: SchemaCommand,
     },
+    #[structopt(name = "contract", about = "Deploy contracts to the local network")]
+    Contract {
+        #[structopt(flatten)]
+        command: ContractCommand,
+    },
+    #[structopt(
+        name = "rpc",
+        about = "Call a JSON-RPC method of the local node or indexer and print the result"
+    )]
+    Rpc {
+        method: String,
+        #[structopt(
+            help = "Params as JSON. Integers are sent as hex quantities and `<tx hash>:<index>` as an out point"
+        )]
+        params: Vec<String>,
+        #[structopt(
+            long,
+            help = "Send to the indexer. Indexer methods such as get_cells are sent there by default"
+        )]
+        indexer: bool,
+        #[structopt(long, help = "Decode cell data with a schema from schemas/mol")]
+        schema: Option<String>,
+        #[structopt(
+            name = "type",
+            long,
+            requires = "schema",
+            help = "Schema type of the cell data, the last type declared by default"
+        )]
+        mol_type: Option<String>,
+    },
     // #[structopt(name = "account", about = "Manage addresses and keys")]
     // Account {
     //     #[structopt(flatten)]
@@ -60,6 +90,8 @@ pub enum NetworkCommands {
         about = "Configure your local developer network. You can also manually edit `trampoline-env.toml`"
     )]
     Config {
+        #[structopt(long, default_value = "node", help = "Service to configure")]
+        service: NetworkService,
         #[structopt(name = "host-port", long, short)]
         port_host: Option<usize>,
         #[structopt(name = "host", long, short)]
@@ -74,13 +106,79 @@ pub enum NetworkCommands {
         about = "Start continuously mining blocks or mine a single block"
     )]
     Miner { one_block: Option<bool> },
+    #[structopt(
+        name = "mine",
+        about = "Mine an exact number of blocks and wait until they are committed"
+    )]
+    Mine {
+        #[structopt(long, short, default_value = "1")]
+        blocks: u64,
+    },
     #[structopt(
         name = "init",
         about = "Initialize new network configuration without starting"
     )]
-    Init {},
-    #[structopt(name = "rpc", about = "Make Rpc calls")]
-    Rpc { hash: String },
+    Init {
+        #[structopt(long, short, help = "Overwrite an existing node configuration")]
+        force: bool,
+    },
+    #[structopt(
+        name = "stop",
+        about = "Stop the local network, or only one of its services"
+    )]
+    Stop { service: Option<NetworkService> },
+    #[structopt(
+        name = "status",
+        about = "Show the state of the local network's services"
+    )]
+    Status {},
+    #[structopt(
+        name = "reset",
+        about = "Stop the local network and delete its chain and indexer data"
+    )]
+    Reset {},
+    #[structopt(name = "logs", about = "Print the logs of the node or the indexer")]
+    Logs {
+        #[structopt(default_value = "node")]
+        service: NetworkService,
+        #[structopt(long, short)]
+        follow: bool,
+        #[structopt(long, short)]
+        tail: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkService {
+    Node,
+    Miner,
+    Indexer,
+}
+
+impl std::str::FromStr for NetworkService {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "node" => Ok(NetworkService::Node),
+            "miner" => Ok(NetworkService::Miner),
+            "indexer" => Ok(NetworkService::Indexer),
+            _ => Err(format!(
+                "Unknown service {}, expected one of node, miner or indexer",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for NetworkService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkService::Node => write!(f, "node"),
+            NetworkService::Miner => write!(f, "miner"),
+            NetworkService::Indexer => write!(f, "indexer"),
+        }
+    }
 }
 #[derive(Debug, StructOpt)]
 pub enum SchemaCommand {
@@ -89,3 +187,36 @@ pub enum SchemaCommand {
     #[structopt(name = "build", about = "Generate rust bindings for schema")]
     Build { name: String },
 }
+
+#[derive(Debug, StructOpt)]
+pub enum ContractCommand {
+    #[structopt(
+        name = "deploy",
+        about = "Deploy a contract binary and record it under [deployed]"
+    )]
+    Deploy {
+        binary: PathBuf,
+        #[structopt(
+            long,
+            help = "Name to record the deployment under, the file name by default"
+        )]
+        name: Option<String>,
+        #[structopt(
+            long,
+            help = "Give the code cell a Type ID so the contract can be upgraded"
+        )]
+        type_id: bool,
+        #[structopt(
+            long,
+            conflicts_with = "type_id",
+            help = "Replace the code of a contract deployed with --type-id, keeping its code hash"
+        )]
+        upgrade: bool,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "File holding the hex private key funding the code cell, which is locked by it. Defaults to the key in the TRAMPOLINE_PRIVKEY environment variable"
+        )]
+        privkey_file: Option<PathBuf>,
+    },
+}
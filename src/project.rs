@@ -1,6 +1,7 @@
 use crate::{TrampolineResource, TrampolineResourceType, TEMPLATES};
 use anyhow::Result;
 use ckb_app_config::CKBAppConfig;
+use ckb_resource::{Resource, TemplateContext, CKB_CONFIG_FILE_NAME, SPEC_DEV_FILE_NAME};
 use serde::{Deserialize, Serialize};
 
 use std::convert::From;
@@ -8,16 +9,19 @@ use std::convert::From;
 use std::fmt::Formatter;
 use std::fs;
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use tera::Context as TeraContext;
 use thiserror::Error;
 use toml;
+use trampoline_sdk::contract::deploy::Deployment;
 
 pub const TRAMPOLINE_ROOT_CONFIG: &str = "trampoline.toml";
 pub const TRAMPOLINE_FOLDER: &str = ".trampoline";
 pub const TRAMPOLINE_SCHEMAS: &str = "schemas";
 pub const TRAMPOLINE_ROOT_DB_DIR: &str = "cache";
+pub const TRAMPOLINE_ENV_CONFIG: &str = "trampoline-env.toml";
 
 #[derive(Debug, Error)]
 pub enum TrampolineProjectError {
@@ -39,6 +43,15 @@ pub enum TrampolineProjectError {
 
     #[error("Invalid initialization: Project {} already exists at {}", .name, .path)]
     ProjectAlreadyExists { path: String, name: String },
+
+    #[error("Invalid network configuration: {0}")]
+    InvalidEnv(String),
+
+    #[error("Network already initialized at {0}, use --force to overwrite its configuration")]
+    NetworkAlreadyInitialized(String),
+
+    #[error("Contract {0} has not been deployed, run `trampoline contract deploy` first")]
+    ContractNotDeployed(String),
 }
 
 pub type ProjectResult<T> = std::result::Result<T, TrampolineProjectError>;
@@ -70,6 +83,64 @@ pub struct TrampolineEnv {
     pub indexer: VirtualEnv,
 }
 
+impl VirtualEnv {
+    // Whether the service runs on this machine, e.g. the devnet launched by the project
+    pub fn is_local(&self) -> bool {
+        let host = self
+            .host
+            .trim_start_matches("http://")
+            .trim_start_matches("https://");
+        let host = host
+            .split(|c| c == ':' || c == '/')
+            .next()
+            .unwrap_or_default();
+        matches!(host, "localhost" | "127.0.0.1" | "0.0.0.0")
+    }
+
+    fn validate(&self, service: &str) -> ProjectResult<()> {
+        let invalid = |reason: String| Err(TrampolineProjectError::InvalidEnv(reason));
+        if !self.host.starts_with("http://") && !self.host.starts_with("https://") {
+            return invalid(format!(
+                "{} host {} must start with http:// or https://",
+                service, self.host
+            ));
+        }
+        for port in [self.host_port, self.container_port] {
+            if port == 0 || port > u16::MAX as usize {
+                return invalid(format!("{} port {} is not a valid port", service, port));
+            }
+        }
+        // Bindings are relative to the project root so the project can be moved
+        if self
+            .local_binding
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return invalid(format!(
+                "{} local path binding {} must be a path inside the project",
+                service,
+                self.local_binding.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl TrampolineEnv {
+    pub fn validate(&self) -> ProjectResult<()> {
+        self.chain.validate("chain")?;
+        self.miner.validate("miner")?;
+        self.indexer.validate("indexer")?;
+        if self.chain.host_port == self.indexer.host_port {
+            return Err(TrampolineProjectError::InvalidEnv(format!(
+                "chain and indexer both use host port {}",
+                self.chain.host_port
+            )));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct TrampolineConfig {
     pub name: String,
@@ -248,6 +319,161 @@ impl TrampolineProject {
         let ckb_toml_path = env.chain.local_binding.join("ckb.toml").canonicalize()?;
         Ok(ckb_toml_path)
     }
+
+    /// Validates `env` and writes it to `trampoline-env.toml`. Other tables of the file,
+    /// such as `[deployed]`, are kept.
+    pub fn save_env(&mut self, env: TrampolineEnv) -> ProjectResult<()> {
+        env.validate()?;
+        for binding in [&env.chain.local_binding, &env.indexer.local_binding] {
+            let path = self.root_dir.join(binding);
+            if path.exists() && !path.is_dir() {
+                return Err(TrampolineProjectError::InvalidEnv(format!(
+                    "local path binding {} is not a directory",
+                    binding.display()
+                )));
+            }
+        }
+
+        let mut document = self.load_env_document()?;
+        if let (Some(document), toml::Value::Table(services)) =
+            (document.as_table_mut(), toml::Value::try_from(&env)?)
+        {
+            document.extend(services);
+        }
+        self.write_env_document(&document)?;
+        self.config.env = Some(env);
+        Ok(())
+    }
+
+    /// Records where contract `name` was deployed in the `[deployed]` table of
+    /// `trampoline-env.toml`, replacing any previous deployment with the same name.
+    pub fn record_deployment(&self, name: &str, deployment: &Deployment) -> ProjectResult<()> {
+        let mut document = self.load_env_document()?;
+        if let Some(document) = document.as_table_mut() {
+            let deployed = document
+                .entry("deployed")
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            if let Some(deployed) = deployed.as_table_mut() {
+                deployed.insert(name.to_string(), toml::Value::try_from(deployment)?);
+            }
+        }
+        self.write_env_document(&document)
+    }
+
+    pub fn deployment(&self, name: &str) -> ProjectResult<Deployment> {
+        let document = self.load_env_document()?;
+        let deployment = document
+            .get("deployed")
+            .and_then(|deployed| deployed.get(name))
+            .ok_or_else(|| TrampolineProjectError::ContractNotDeployed(name.to_string()))?;
+        Ok(deployment.clone().try_into()?)
+    }
+
+    fn load_env_document(&self) -> ProjectResult<toml::Value> {
+        let env_path = self.root_dir.join(TRAMPOLINE_ENV_CONFIG);
+        if env_path.exists() {
+            Ok(toml::from_str(&fs::read_to_string(&env_path)?)?)
+        } else {
+            Ok(toml::Value::Table(Default::default()))
+        }
+    }
+
+    fn write_env_document(&self, document: &toml::Value) -> ProjectResult<()> {
+        // Written next to the original and renamed, so an interrupted write cannot leave a
+        // truncated configuration behind
+        let env_path = self.root_dir.join(TRAMPOLINE_ENV_CONFIG);
+        let tmp_path = env_path.with_extension("toml.tmp");
+        fs::write(&tmp_path, toml::to_string(document)?)?;
+        fs::rename(&tmp_path, &env_path)?;
+        Ok(())
+    }
+
+    /// Generates the node's `ckb.toml`, `ckb-miner.toml` and dev chain spec in the chain's
+    /// local binding, like `ckb init --chain dev` does when the network is first launched.
+    pub fn init_network(&self, force: bool) -> ProjectResult<()> {
+        let env = self.config.env.as_ref().ok_or_else(|| {
+            TrampolineProjectError::InvalidEnv(format!("{} not found", TRAMPOLINE_ENV_CONFIG))
+        })?;
+        let root = self.root_dir.join(&env.chain.local_binding);
+        let ckb_config_path = root.join(CKB_CONFIG_FILE_NAME);
+        if ckb_config_path.exists() && !force {
+            return Err(TrampolineProjectError::NetworkAlreadyInitialized(
+                root.display().to_string(),
+            ));
+        }
+        fs::create_dir_all(&root)?;
+        fs::create_dir_all(self.root_dir.join(&env.indexer.local_binding))?;
+
+        let genesis_message = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default()
+            .to_string();
+        let spec_context = TemplateContext::new(
+            "customize",
+            vec![("genesis_message", genesis_message.as_str())],
+        );
+        Resource::bundled(SPEC_DEV_FILE_NAME.to_string()).export(&spec_context, &root)?;
+
+        let rpc_port = env.chain.container_port.to_string();
+        let context = TemplateContext::new(
+            "dev",
+            vec![
+                ("rpc_port", rpc_port.as_str()),
+                ("p2p_port", "8115"),
+                ("log_to_file", "true"),
+                ("log_to_stdout", "true"),
+                ("block_assembler", ""),
+                ("spec_source", "file"),
+            ],
+        );
+        Resource::bundled_ckb_config().export(&context, &root)?;
+        Resource::bundled_miner_config().export(&context, &root)?;
+        Resource::bundled_db_options().export(&context, &root)?;
+
+        // The node runs in a container, its RPC has to be reachable through the port binding.
+        // `IntegrationTest` serves `generate_block`, used by `trampoline network mine`.
+        let local_rpc = format!("listen_address = \"127.0.0.1:{}\"", rpc_port);
+        let public_rpc = format!("listen_address = \"0.0.0.0:{}\"", rpc_port);
+        let ckb_config = fs::read_to_string(&ckb_config_path)?
+            .replace(&local_rpc, &public_rpc)
+            .replacen("\nmodules = [", "\nmodules = [\"IntegrationTest\", ", 1);
+        fs::write(&ckb_config_path, ckb_config)?;
+        Ok(())
+    }
+
+    pub fn node_container_name(&self) -> String {
+        format!("{}-node", self.config.name)
+    }
+
+    pub fn indexer_container_name(&self) -> String {
+        format!("{}-indexer", self.config.name)
+    }
+
+    /// Deletes the chain and indexer data of the local network. The node's configuration
+    /// files next to the chain data are kept.
+    pub fn reset_network_data(&self) -> ProjectResult<()> {
+        let env = match self.config.env.as_ref() {
+            Some(env) => env,
+            None => return Ok(()),
+        };
+        let chain_data = self.root_dir.join(&env.chain.local_binding).join("data");
+        if chain_data.exists() {
+            fs::remove_dir_all(chain_data)?;
+        }
+        let indexer_data = self.root_dir.join(&env.indexer.local_binding);
+        if indexer_data.exists() {
+            for entry in fs::read_dir(indexer_data)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 fn find_ancestor(curr_path: &mut PathBuf, target: &str) -> Option<PathBuf> {
@@ -260,3 +486,127 @@ fn find_ancestor(curr_path: &mut PathBuf, target: &str) -> Option<PathBuf> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_env() -> TrampolineEnv {
+        toml::from_str(
+            &TEMPLATES
+                .render("trampoline-env.toml", &TeraContext::new())
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_env() {
+        let env = default_env();
+        assert!(env.validate().is_ok());
+
+        let mut env = default_env();
+        env.indexer.host_port = env.chain.host_port;
+        assert!(env.validate().is_err());
+
+        let mut env = default_env();
+        env.chain.host_port = 70000;
+        assert!(env.validate().is_err());
+
+        let mut env = default_env();
+        env.chain.local_binding = PathBuf::from("../network");
+        assert!(env.validate().is_err());
+
+        let mut env = default_env();
+        env.chain.host = "localhost".to_string();
+        assert!(env.validate().is_err());
+    }
+
+    #[test]
+    fn test_local_env() {
+        let mut env = default_env();
+        assert!(env.chain.is_local());
+        env.chain.host = "http://127.0.0.1".to_string();
+        assert!(env.chain.is_local());
+        env.chain.host = "https://testnet.ckb.dev".to_string();
+        assert!(!env.chain.is_local());
+        env.chain.host = "http://localhost.example.com".to_string();
+        assert!(!env.chain.is_local());
+    }
+
+    #[test]
+    fn test_save_env_keeps_other_tables() {
+        let root_dir = std::env::temp_dir().join(format!("trampoline_env_{}", std::process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+        let env_path = root_dir.join(TRAMPOLINE_ENV_CONFIG);
+        let deployed = "[deployed]\nmy_contract = \"0x01\"\n";
+        let raw_env = format!("{}\n{}", toml::to_string(&default_env()).unwrap(), deployed);
+        fs::write(&env_path, raw_env).unwrap();
+
+        let mut project = TrampolineProject {
+            config: TrampolineConfig {
+                name: "test".to_string(),
+                env: Some(default_env()),
+            },
+            root_dir: root_dir.clone(),
+        };
+        let mut env = default_env();
+        env.chain.host_port = 9114;
+        project.save_env(env).unwrap();
+
+        let saved: toml::Value = toml::from_str(&fs::read_to_string(&env_path).unwrap()).unwrap();
+        assert_eq!(saved["chain"]["host_port"].as_integer(), Some(9114));
+        assert_eq!(saved["deployed"]["my_contract"].as_str(), Some("0x01"));
+
+        let mut env = default_env();
+        env.indexer.host_port = 9114;
+        env.chain.host_port = 9114;
+        assert!(project.save_env(env).is_err());
+        assert_eq!(project.config.env.as_ref().unwrap().chain.host_port, 9114);
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_deployment() {
+        let root_dir =
+            std::env::temp_dir().join(format!("trampoline_deployed_{}", std::process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+        fs::write(
+            root_dir.join(TRAMPOLINE_ENV_CONFIG),
+            toml::to_string(&default_env()).unwrap(),
+        )
+        .unwrap();
+        let project = TrampolineProject {
+            config: TrampolineConfig {
+                name: "test".to_string(),
+                env: Some(default_env()),
+            },
+            root_dir: root_dir.clone(),
+        };
+        assert!(matches!(
+            project.deployment("my_contract"),
+            Err(TrampolineProjectError::ContractNotDeployed(_))
+        ));
+
+        let deployment = Deployment {
+            out_point: ckb_jsonrpc_types::OutPoint {
+                tx_hash: Default::default(),
+                index: 1.into(),
+            },
+            code_hash: Default::default(),
+            hash_type: ckb_jsonrpc_types::ScriptHashType::Type,
+            dep_type: ckb_jsonrpc_types::DepType::Code,
+            type_script: None,
+        };
+        project
+            .record_deployment("my_contract", &deployment)
+            .unwrap();
+        assert_eq!(project.deployment("my_contract").unwrap(), deployment);
+
+        let saved: toml::Value =
+            toml::from_str(&fs::read_to_string(root_dir.join(TRAMPOLINE_ENV_CONFIG)).unwrap())
+                .unwrap();
+        assert_eq!(saved["chain"]["host_port"].as_integer(), Some(8114));
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+}
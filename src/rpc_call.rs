@@ -0,0 +1,113 @@
+use crate::parse_hex;
+use crate::schema::MolSchema;
+use serde_json::{json, Value};
+
+// Methods served by ckb-indexer rather than the node
+const INDEXER_METHODS: [&str; 5] = [
+    "get_tip",
+    "get_cells",
+    "get_transactions",
+    "get_cells_capacity",
+    "get_indexer_info",
+];
+
+pub fn is_indexer_method(method: &str) -> bool {
+    INDEXER_METHODS.contains(&method)
+}
+
+/// Parses a command line param as JSON, with shortcuts for the values the node expects:
+/// - integers become hex quantities: `16` => `"0x10"`
+/// - `<tx hash>:<index>` becomes an out point
+/// - anything else that is not JSON is passed as a string, e.g. hashes
+pub fn parse_param(raw: &str) -> Value {
+    if let Some((tx_hash, index)) = raw.split_once(':') {
+        if let (Ok(hash), Ok(index)) = (parse_hex(tx_hash), index.parse::<u32>()) {
+            if hash.len() == 32 {
+                return json!({
+                    "tx_hash": tx_hash,
+                    "index": format!("{:#x}", index),
+                });
+            }
+        }
+    }
+    match serde_json::from_str(raw) {
+        Ok(Value::Number(number)) => match number.as_u64() {
+            Some(number) => json!(format!("{:#x}", number)),
+            None => Value::Number(number),
+        },
+        Ok(value) => value,
+        Err(_) => json!(raw),
+    }
+}
+
+/// Adds the decoded form of every cell data found in an RPC result next to it, as
+/// `<field>_decoded`. Data that does not match the type is decoded as null.
+pub fn decode_cell_data(result: &mut Value, schema: &MolSchema, type_name: &str) {
+    let decode = |data: &Value| {
+        data.as_str()
+            .and_then(|data| parse_hex(data).ok())
+            .and_then(|data| schema.decode(type_name, &data).ok())
+            .unwrap_or(Value::Null)
+    };
+    match result {
+        Value::Object(object) => {
+            let mut decoded = vec![];
+            for (key, value) in object.iter_mut() {
+                let field = format!("{}_decoded", key);
+                match key.as_str() {
+                    // `get_cells` and `get_live_cell`
+                    "output_data" | "content" if value.is_string() => {
+                        decoded.push((field, decode(value)))
+                    }
+                    // Transactions
+                    "outputs_data" if value.is_array() => {
+                        let outputs_data = value.as_array().into_iter().flatten();
+                        decoded.push((field, outputs_data.map(decode).collect()))
+                    }
+                    _ => decode_cell_data(value, schema, type_name),
+                }
+            }
+            object.extend(decoded);
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| decode_cell_data(value, schema, type_name)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_param() {
+        assert_eq!(parse_param("16"), json!("0x10"));
+        assert_eq!(parse_param("true"), json!(true));
+        assert_eq!(parse_param("0xabcd"), json!("0xabcd"));
+        assert_eq!(parse_param(r#"{"a": 1}"#), json!({"a": 1}));
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            parse_param(&format!("{}:2", hash)),
+            json!({"tx_hash": hash, "index": "0x2"})
+        );
+    }
+
+    #[test]
+    fn test_decode_cell_data() {
+        let schema =
+            MolSchema::parse("array Uint32 [byte; 4]; struct Pair { a: Uint32, b: Uint32 }")
+                .unwrap();
+        let mut result = json!({
+            "objects": [{"output_data": "0x0100000002000000"}],
+            "transaction": {"outputs_data": ["0x0100000002000000", "0x"]},
+        });
+        decode_cell_data(&mut result, &schema, "Pair");
+        let pair = json!({"a": "0x01000000", "b": "0x02000000"});
+        assert_eq!(result["objects"][0]["output_data_decoded"], pair);
+        assert_eq!(
+            result["transaction"]["outputs_data_decoded"],
+            json!([pair, null])
+        );
+    }
+}
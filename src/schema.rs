@@ -8,6 +8,9 @@ use std::path::Path;
 use std::path::PathBuf;
 use thiserror::Error;
 
+mod decode;
+pub use decode::*;
+
 type SchemaResult<T> = std::result::Result<T, SchemaError>;
 
 #[derive(Debug, Error)]
@@ -16,6 +19,12 @@ pub enum SchemaError {
     Io(#[from] std::io::Error),
     #[error("Error compiling molecule schema file:\n {0}")]
     Molecule(String),
+    #[error("Error parsing molecule schema: {0}")]
+    Parse(String),
+    #[error("Unknown molecule type {0}")]
+    UnknownType(String),
+    #[error("Cannot decode data as {type_name}: {reason}")]
+    Decode { type_name: String, reason: String },
 }
 
 #[derive(Debug, Clone, Default)]
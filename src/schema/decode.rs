@@ -0,0 +1,487 @@
+use super::{SchemaError, SchemaResult};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+const HEADER_SIZE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MolType {
+    Array { item: String, len: usize },
+    Struct { fields: Vec<(String, String)> },
+    Vector { item: String },
+    Table { fields: Vec<(String, String)> },
+    Option { item: String },
+    Union { items: Vec<String> },
+}
+
+/// Types declared in a molecule schema, used to decode data at runtime without the
+/// generated bindings.
+#[derive(Debug, Clone, Default)]
+pub struct MolSchema {
+    types: HashMap<String, MolType>,
+    // Declaration order of the schema's own types, imports excluded
+    declared: Vec<String>,
+}
+
+impl MolSchema {
+    /// Loads a `.mol` file along with the files it imports.
+    pub fn load(path: impl AsRef<Path>) -> SchemaResult<Self> {
+        let mut schema = Self::default();
+        schema.load_file(path.as_ref(), true)?;
+        Ok(schema)
+    }
+
+    pub fn parse(source: &str) -> SchemaResult<Self> {
+        let mut schema = Self::default();
+        if let Some(import) = schema.parse_declarations(source, true)?.first() {
+            return Err(SchemaError::Parse(format!(
+                "cannot resolve import {} without a schema file",
+                import
+            )));
+        }
+        Ok(schema)
+    }
+
+    /// The last type declared by the schema, usually the one wrapping the others.
+    pub fn root_type(&self) -> Option<&str> {
+        self.declared.last().map(String::as_str)
+    }
+
+    pub fn decode(&self, type_name: &str, data: &[u8]) -> SchemaResult<Value> {
+        let decode_error = |reason: String| SchemaError::Decode {
+            type_name: type_name.to_string(),
+            reason,
+        };
+        if type_name == "byte" {
+            return match data {
+                [byte] => Ok(json!(byte)),
+                _ => Err(decode_error(format!("expected 1 byte, got {}", data.len()))),
+            };
+        }
+        let mol_type = self
+            .types
+            .get(type_name)
+            .ok_or_else(|| SchemaError::UnknownType(type_name.to_string()))?;
+        if let Some(size) = self.fixed_size(type_name) {
+            if data.len() != size {
+                return Err(decode_error(format!(
+                    "expected {} bytes, got {}",
+                    size,
+                    data.len()
+                )));
+            }
+        }
+        match mol_type {
+            MolType::Array { item, len } => self.decode_fixed_items(item, *len, data),
+            MolType::Struct { fields } => {
+                let mut offset = 0;
+                let mut decoded = serde_json::Map::new();
+                for (name, field_type) in fields {
+                    let size = self.fixed_size(field_type).unwrap_or_default();
+                    decoded.insert(
+                        name.clone(),
+                        self.decode(field_type, &data[offset..offset + size])?,
+                    );
+                    offset += size;
+                }
+                Ok(Value::Object(decoded))
+            }
+            MolType::Vector { item } => match self.fixed_size(item) {
+                Some(_) => {
+                    let len = read_u32(data, 0).map_err(decode_error)?;
+                    self.decode_fixed_items(item, len, &data[HEADER_SIZE..])
+                }
+                None => {
+                    let items = dynamic_items(data).map_err(decode_error)?;
+                    items
+                        .into_iter()
+                        .map(|item_data| self.decode(item, item_data))
+                        .collect::<SchemaResult<Vec<_>>>()
+                        .map(Value::Array)
+                }
+            },
+            MolType::Table { fields } => {
+                let items = dynamic_items(data).map_err(decode_error)?;
+                if items.len() < fields.len() {
+                    return Err(decode_error(format!(
+                        "expected {} fields, got {}",
+                        fields.len(),
+                        items.len()
+                    )));
+                }
+                let mut decoded = serde_json::Map::new();
+                // Fields appended by newer versions of the schema are ignored
+                for ((name, field_type), field_data) in fields.iter().zip(items) {
+                    decoded.insert(name.clone(), self.decode(field_type, field_data)?);
+                }
+                Ok(Value::Object(decoded))
+            }
+            MolType::Option { item } => {
+                if data.is_empty() {
+                    Ok(Value::Null)
+                } else {
+                    self.decode(item, data)
+                }
+            }
+            MolType::Union { items } => {
+                let id = read_u32(data, 0).map_err(decode_error)?;
+                let item = items
+                    .get(id)
+                    .ok_or_else(|| decode_error(format!("unknown union item id {}", id)))?;
+                Ok(json!({
+                    "type": item,
+                    "value": self.decode(item, &data[HEADER_SIZE..])?,
+                }))
+            }
+        }
+    }
+
+    fn decode_fixed_items(&self, item: &str, len: usize, data: &[u8]) -> SchemaResult<Value> {
+        let size = self.fixed_size(item).unwrap_or_default();
+        if data.len() != size * len {
+            return Err(SchemaError::Decode {
+                type_name: item.to_string(),
+                reason: format!(
+                    "expected {} items of {} bytes, got {}",
+                    len,
+                    size,
+                    data.len()
+                ),
+            });
+        }
+        // Byte sequences are shown as hex, like the node shows them
+        if item == "byte" {
+            return Ok(json!(format!("0x{}", hex::encode(data))));
+        }
+        data.chunks(size.max(1))
+            .take(len)
+            .map(|chunk| self.decode(item, chunk))
+            .collect::<SchemaResult<Vec<_>>>()
+            .map(Value::Array)
+    }
+
+    fn fixed_size(&self, type_name: &str) -> Option<usize> {
+        if type_name == "byte" {
+            return Some(1);
+        }
+        match self.types.get(type_name)? {
+            MolType::Array { item, len } => Some(self.fixed_size(item)? * len),
+            MolType::Struct { fields } => fields
+                .iter()
+                .map(|(_, field_type)| self.fixed_size(field_type))
+                .sum(),
+            _ => None,
+        }
+    }
+
+    fn load_file(&mut self, path: &Path, declared: bool) -> SchemaResult<()> {
+        let source = std::fs::read_to_string(path)?;
+        for import in self.parse_declarations(&source, declared)? {
+            let import_path = path.with_file_name(format!("{}.mol", import));
+            self.load_file(&import_path, false)?;
+        }
+        Ok(())
+    }
+
+    // Adds the declared types and returns the imports to resolve
+    fn parse_declarations(&mut self, source: &str, declared: bool) -> SchemaResult<Vec<String>> {
+        let mut tokens = Tokens::new(source);
+        let mut imports = vec![];
+        while let Some(keyword) = tokens.next_token() {
+            if keyword == "import" {
+                let mut import = tokens.expect_ident()?;
+                // Imports of nested paths use `import dir/name;`
+                while tokens.peek() == Some("/") {
+                    tokens.next_token();
+                    import = format!("{}/{}", import, tokens.expect_ident()?);
+                }
+                tokens.expect(";")?;
+                imports.push(import);
+                continue;
+            }
+            let name = tokens.expect_ident()?;
+            let mol_type = match keyword.as_str() {
+                "array" => {
+                    tokens.expect("[")?;
+                    let item = tokens.expect_ident()?;
+                    tokens.expect(";")?;
+                    let len = tokens.expect_ident()?;
+                    let len = len
+                        .parse()
+                        .map_err(|_| SchemaError::Parse(format!("invalid array length {}", len)))?;
+                    tokens.expect("]")?;
+                    MolType::Array { item, len }
+                }
+                "struct" => MolType::Struct {
+                    fields: tokens.fields()?,
+                },
+                "table" => MolType::Table {
+                    fields: tokens.fields()?,
+                },
+                "vector" => {
+                    tokens.expect("<")?;
+                    let item = tokens.expect_ident()?;
+                    tokens.expect(">")?;
+                    MolType::Vector { item }
+                }
+                "option" => {
+                    tokens.expect("(")?;
+                    let item = tokens.expect_ident()?;
+                    tokens.expect(")")?;
+                    MolType::Option { item }
+                }
+                "union" => {
+                    tokens.expect("{")?;
+                    let mut items = vec![];
+                    while tokens.peek() != Some("}") {
+                        items.push(tokens.expect_ident()?);
+                        if tokens.peek() == Some(",") {
+                            tokens.next_token();
+                        }
+                    }
+                    tokens.expect("}")?;
+                    MolType::Union { items }
+                }
+                keyword => {
+                    return Err(SchemaError::Parse(format!(
+                        "unexpected {} before {}",
+                        keyword, name
+                    )))
+                }
+            };
+            tokens.expect(";")?;
+            if declared {
+                self.declared.push(name.clone());
+            }
+            self.types.insert(name, mol_type);
+        }
+        Ok(imports)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<usize, String> {
+    data.get(offset..offset + HEADER_SIZE)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        .ok_or_else(|| format!("expected a header at offset {}", offset))
+}
+
+// Splits the items of a dynvec or table by their offsets
+fn dynamic_items(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let total_size = read_u32(data, 0)?;
+    if total_size != data.len() {
+        return Err(format!(
+            "header declares {} bytes, got {}",
+            total_size,
+            data.len()
+        ));
+    }
+    if total_size == HEADER_SIZE {
+        return Ok(vec![]);
+    }
+    let first_offset = read_u32(data, HEADER_SIZE)?;
+    if first_offset % HEADER_SIZE != 0 || first_offset < HEADER_SIZE * 2 {
+        return Err(format!("invalid first offset {}", first_offset));
+    }
+    let count = first_offset / HEADER_SIZE - 1;
+    let mut offsets = (0..count)
+        .map(|idx| read_u32(data, HEADER_SIZE * (idx + 1)))
+        .collect::<Result<Vec<_>, _>>()?;
+    offsets.push(total_size);
+    offsets
+        .windows(2)
+        .map(|bounds| {
+            data.get(bounds[0]..bounds[1])
+                .ok_or_else(|| format!("invalid item offsets {} and {}", bounds[0], bounds[1]))
+        })
+        .collect()
+}
+
+struct Tokens {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Tokens {
+    fn new(source: &str) -> Self {
+        let mut tokens = vec![];
+        let mut chars = strip_comments(source).chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c.is_alphanumeric() || c == '_' {
+                let mut ident = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    ident.push(next);
+                    chars.next();
+                }
+                tokens.push(ident);
+            } else {
+                tokens.push(c.to_string());
+            }
+        }
+        Self { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next_token(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> SchemaResult<()> {
+        match self.next_token() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(SchemaError::Parse(format!(
+                "expected {}, got {}",
+                expected,
+                token.unwrap_or_else(|| "end of schema".to_string())
+            ))),
+        }
+    }
+
+    fn expect_ident(&mut self) -> SchemaResult<String> {
+        match self.next_token() {
+            Some(token) if token.chars().all(|c| c.is_alphanumeric() || c == '_') => Ok(token),
+            token => Err(SchemaError::Parse(format!(
+                "expected a name, got {}",
+                token.unwrap_or_else(|| "end of schema".to_string())
+            ))),
+        }
+    }
+
+    // `{ name: Type, ... }` of structs and tables
+    fn fields(&mut self) -> SchemaResult<Vec<(String, String)>> {
+        self.expect("{")?;
+        let mut fields = vec![];
+        while self.peek() != Some("}") {
+            let name = self.expect_ident()?;
+            self.expect(":")?;
+            fields.push((name, self.expect_ident()?));
+            if self.peek() == Some(",") {
+                self.next_token();
+            }
+        }
+        self.expect("}")?;
+        Ok(fields)
+    }
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+            stripped.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            stripped.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        // Blockchain-style basics
+        array Byte32 [byte; 32];
+        array Uint32 [byte; 4];
+        vector Bytes <byte>;
+        struct Point { x: Uint32, y: Uint32 }
+        vector Points <Point>;
+        option BytesOpt (Bytes);
+        /* Dynamic types */
+        vector BytesVec <Bytes>;
+        union Shape { Point, Bytes }
+        table Card {
+            id: Byte32,
+            name: Bytes,
+            path: Points,
+            note: BytesOpt,
+            tags: BytesVec,
+            shape: Shape,
+        }
+    "#;
+
+    fn fixvec(count: usize, items: &[u8]) -> Vec<u8> {
+        let mut data = (count as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(items);
+        data
+    }
+
+    fn dynvec(items: &[Vec<u8>]) -> Vec<u8> {
+        let header = HEADER_SIZE * (items.len() + 1);
+        let total = header + items.iter().map(Vec::len).sum::<usize>();
+        let mut data = (total as u32).to_le_bytes().to_vec();
+        let mut offset = header;
+        for item in items {
+            data.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += item.len();
+        }
+        items.iter().for_each(|item| data.extend_from_slice(item));
+        data
+    }
+
+    #[test]
+    fn test_decode_table() {
+        let schema = MolSchema::parse(SCHEMA).unwrap();
+        assert_eq!(schema.root_type(), Some("Card"));
+
+        let point = [1u8, 0, 0, 0, 2, 0, 0, 0];
+        let mut shape = 1u32.to_le_bytes().to_vec();
+        shape.extend(fixvec(5, b"round"));
+        let card = dynvec(&[
+            vec![7u8; 32],
+            fixvec(2, b"ab"),
+            fixvec(2, &[point, point].concat()),
+            vec![],
+            dynvec(&[fixvec(1, b"x"), fixvec(0, &[])]),
+            shape,
+        ]);
+
+        let point_json = json!({"x": "0x01000000", "y": "0x02000000"});
+        assert_eq!(
+            schema.decode("Card", &card).unwrap(),
+            json!({
+                "id": format!("0x{}", "07".repeat(32)),
+                "name": "0x6162",
+                "path": [point_json, point_json],
+                "note": null,
+                "tags": ["0x78", "0x"],
+                "shape": {"type": "Bytes", "value": "0x726f756e64"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_rejects_malformed_data() {
+        let schema = MolSchema::parse(SCHEMA).unwrap();
+        assert!(matches!(
+            schema.decode("Byte32", &[0u8; 31]),
+            Err(SchemaError::Decode { .. })
+        ));
+        assert!(matches!(
+            schema.decode("Card", &dynvec(&[vec![7u8; 32]])),
+            Err(SchemaError::Decode { .. })
+        ));
+        assert!(matches!(
+            schema.decode("Missing", &[]),
+            Err(SchemaError::UnknownType(_))
+        ));
+    }
+}